@@ -0,0 +1,279 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{Context, Result, bail};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    time,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    bind_tracker::BindState,
+    config::Config,
+    protocol::{self, Reader, ToAgent, ToCoordinator},
+    run::Run,
+};
+
+/// How often a running agent sends its metrics to the coordinator.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Serves coordinators, one run at a time, until Ctrl+C. Only coordinators
+/// presenting `token` are given a share of the load.
+pub async fn serve(listen: &str, token: &str) -> Result<()> {
+    if token.is_empty() {
+        bail!("the agent token must not be empty");
+    }
+    let listener = TcpListener::bind(listen)
+        .await
+        .with_context(|| format!("failed to listen for a coordinator on {listen}"))?;
+    tracing::info!(%listen, "Agent waiting for a coordinator");
+    loop {
+        let (stream, peer) = tokio::select! {
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+            accepted = listener.accept() => accepted.context("failed to accept coordinator")?,
+        };
+        tracing::info!(%peer, "Coordinator connected");
+        match session(stream, token).await {
+            Ok(()) => tracing::info!(%peer, "Coordinator session ended"),
+            Err(err) => tracing::warn!(%peer, error = ?err, "Coordinator session failed"),
+        }
+    }
+}
+
+async fn session(stream: TcpStream, token: &str) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = Reader::new(reader);
+
+    let (config, index, count) = match reader.next().await? {
+        Some(ToAgent::Prepare {
+            token: presented,
+            config,
+            index,
+            count,
+        }) => {
+            if !tokens_match(&presented, token) {
+                let error = "invalid token".to_string();
+                protocol::send(&mut writer, &ToCoordinator::Failed { error }).await?;
+                bail!("coordinator presented an invalid token");
+            }
+            (config, index, count)
+        }
+        Some(_) => bail!("expected a prepare message"),
+        None => return Ok(()),
+    };
+    let config = match Config::from_toml(&config) {
+        Ok(config) => config.share(index, count),
+        Err(err) => {
+            let error = format!("{err:#}");
+            protocol::send(&mut writer, &ToCoordinator::Failed { error }).await?;
+            return Err(err);
+        }
+    };
+    let binds = config
+        .accounts
+        .iter()
+        .map(|account| account.load.binds)
+        .sum();
+    tracing::info!(index, count, binds, "Prepared share of the load");
+    protocol::send(&mut writer, &ToCoordinator::Ready { binds }).await?;
+
+    match reader.next().await? {
+        Some(ToAgent::Start) => {}
+        Some(ToAgent::Stop) | None => return Ok(()),
+        Some(_) => bail!("expected a start message"),
+    }
+
+    let run = Run::new(Arc::new(config));
+    let (metrics, tracker) = (run.metrics.clone(), run.tracker.clone());
+    let shutdown = CancellationToken::new();
+    let mut execution = tokio::spawn(run.execute(shutdown.clone(), false));
+    let mut ticker = time::interval(PROGRESS_INTERVAL);
+    // Once the coordinator is gone the run is stopped and nothing more sent.
+    let mut connected = true;
+
+    let result = loop {
+        tokio::select! {
+            result = &mut execution => break result,
+            message = reader.next::<ToAgent>(), if connected => match message {
+                Ok(Some(ToAgent::Stop)) => shutdown.cancel(),
+                Ok(Some(_)) => tracing::warn!("Unexpected message from coordinator"),
+                Ok(None) | Err(_) => {
+                    tracing::warn!("Coordinator disconnected; stopping the run");
+                    connected = false;
+                    shutdown.cancel();
+                }
+            },
+            _ = ticker.tick(), if connected => {
+                let bound = tracker
+                    .snapshot()
+                    .await
+                    .iter()
+                    .filter(|status| matches!(status.state, BindState::Bound))
+                    .count();
+                let progress = ToCoordinator::Progress {
+                    metrics: metrics.snapshot(),
+                    bound,
+                };
+                if protocol::send(&mut writer, &progress).await.is_err() {
+                    connected = false;
+                    shutdown.cancel();
+                }
+            }
+        }
+    };
+
+    let message = match result {
+        Ok(Ok(elapsed)) => ToCoordinator::Finished {
+            metrics: metrics.snapshot(),
+            elapsed_secs: elapsed.as_secs_f64(),
+        },
+        Ok(Err(err)) => ToCoordinator::Failed {
+            error: format!("{err:#}"),
+        },
+        Err(err) => ToCoordinator::Failed {
+            error: err.to_string(),
+        },
+    };
+    if connected {
+        protocol::send(&mut writer, &message).await?;
+    }
+    Ok(())
+}
+
+/// Compares in time independent of where the tokens differ.
+fn tokens_match(presented: &str, expected: &str) -> bool {
+    presented.len() == expected.len()
+        && presented
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{net::tcp::OwnedWriteHalf, task::JoinHandle};
+
+    use super::*;
+
+    const CONFIG: &str = r#"
+        [smpp]
+        host = "127.0.0.1"
+        port = 2775
+        system_id = "esme"
+        password = "secret"
+
+        [message]
+        source_addr = "1000"
+        destination_addr = "2000"
+        body = "hello"
+
+        [load]
+        binds = 3
+        total_tps = 90
+    "#;
+
+    /// A coordinator connected to an agent session expecting "secret".
+    async fn coordinator() -> (Reader, OwnedWriteHalf, JoinHandle<Result<()>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (agent, _) = listener.accept().await.unwrap();
+        let session = tokio::spawn(async move { session(agent, "secret").await });
+        let (reader, writer) = stream.into_split();
+        (Reader::new(reader), writer, session)
+    }
+
+    fn prepare(token: &str, config: &str) -> ToAgent {
+        ToAgent::Prepare {
+            token: token.to_string(),
+            config: config.to_string(),
+            index: 1,
+            count: 2,
+        }
+    }
+
+    async fn failure(reader: &mut Reader) -> String {
+        match reader.next().await.unwrap() {
+            Some(ToCoordinator::Failed { error }) => error,
+            _ => panic!("expected failed"),
+        }
+    }
+
+    #[test]
+    fn tokens_must_match_exactly() {
+        let cases = [
+            ("secret", "secret", true),
+            ("secret", "secreT", false),
+            ("secret", "secret2", false),
+            ("", "secret", false),
+            ("", "", true),
+        ];
+        for (presented, expected, matched) in cases {
+            assert_eq!(
+                tokens_match(presented, expected),
+                matched,
+                "{presented:?} vs {expected:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn share_is_accepted_and_stop_ends_the_session() {
+        let (mut reader, mut writer, session) = coordinator().await;
+        protocol::send(&mut writer, &prepare("secret", CONFIG))
+            .await
+            .unwrap();
+        // The second of two agents gets one of the three binds.
+        assert!(matches!(
+            reader.next().await.unwrap(),
+            Some(ToCoordinator::Ready { binds: 1 })
+        ));
+
+        protocol::send(&mut writer, &ToAgent::Stop).await.unwrap();
+        session.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn invalid_token_is_refused() {
+        let (mut reader, mut writer, session) = coordinator().await;
+        protocol::send(&mut writer, &prepare("guess", CONFIG))
+            .await
+            .unwrap();
+
+        assert_eq!(failure(&mut reader).await, "invalid token");
+        let err = session.await.unwrap().unwrap_err();
+        assert!(format!("{err:#}").contains("invalid token"), "{err:#}");
+    }
+
+    #[tokio::test]
+    async fn invalid_config_is_reported() {
+        let (mut reader, mut writer, session) = coordinator().await;
+        protocol::send(&mut writer, &prepare("secret", "[smpp]\nport = \"x\""))
+            .await
+            .unwrap();
+
+        let error = failure(&mut reader).await;
+        assert!(
+            error.contains("Failed to parse TOML configuration"),
+            "{error}"
+        );
+        assert!(session.await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn coordinator_leaving_before_start_ends_the_session() {
+        let (mut reader, mut writer, session) = coordinator().await;
+        protocol::send(&mut writer, &prepare("secret", CONFIG))
+            .await
+            .unwrap();
+        assert!(matches!(
+            reader.next().await.unwrap(),
+            Some(ToCoordinator::Ready { .. })
+        ));
+
+        drop(writer);
+        session.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,346 @@
+use std::{sync::Arc, time::Instant};
+
+use anyhow::{Context, Result};
+use axum::{
+    Json, Router,
+    extract::State,
+    http::StatusCode,
+    routing::{get, post},
+};
+use serde::{Deserialize, Serialize};
+use tokio::{net::TcpListener, task::JoinHandle};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    bind_tracker::BindTracker,
+    config::ApiConfig,
+    control::{RunControl, StageSnapshot},
+    metrics::{Metrics, MetricsSnapshot},
+};
+
+type ApiResult<T> = std::result::Result<Json<T>, (StatusCode, String)>;
+
+struct ApiState {
+    metrics: Arc<Metrics>,
+    tracker: Arc<BindTracker>,
+    control: Arc<RunControl>,
+    started: Instant,
+    shutdown: CancellationToken,
+}
+
+#[derive(Serialize)]
+struct Status {
+    elapsed_secs: f64,
+    /// False once a stop was requested and the binds are winding down.
+    running: bool,
+    /// While set, `metrics` holds the warm-up, which the final report leaves out.
+    warming_up: bool,
+    control: ControlStatus,
+    stage: Option<String>,
+    stages: Vec<StageSnapshot>,
+    metrics: MetricsSnapshot,
+    binds: Vec<BindView>,
+}
+
+#[derive(Serialize)]
+struct ControlStatus {
+    paused: bool,
+    rate_percent: u32,
+    /// Set while the capacity search owns the rate.
+    rate_locked: bool,
+    active_binds: usize,
+    bind_slots: usize,
+}
+
+#[derive(Serialize)]
+struct BindView {
+    idx: usize,
+    state: String,
+    last_message_id: Option<String>,
+    /// Latest state changes, oldest first.
+    history: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct RateRequest {
+    rate_percent: u32,
+}
+
+/// Starts a stage and optionally applies its settings in one call.
+#[derive(Deserialize)]
+struct StageRequest {
+    name: String,
+    #[serde(default)]
+    rate_percent: Option<u32>,
+    #[serde(default)]
+    paused: Option<bool>,
+    #[serde(default)]
+    binds: Option<usize>,
+}
+
+/// Serves the control API until the run stops.
+pub async fn spawn_server(
+    config: &ApiConfig,
+    metrics: Arc<Metrics>,
+    tracker: Arc<BindTracker>,
+    control: Arc<RunControl>,
+    shutdown: CancellationToken,
+) -> Result<JoinHandle<()>> {
+    let listener = TcpListener::bind(&config.listen)
+        .await
+        .with_context(|| format!("failed to listen for the control API on {}", config.listen))?;
+    tracing::info!(listen = %config.listen, "Control API listening");
+
+    let state = Arc::new(ApiState {
+        metrics,
+        tracker,
+        control,
+        started: Instant::now(),
+        shutdown: shutdown.clone(),
+    });
+    let app = router(state);
+
+    Ok(tokio::spawn(async move {
+        if let Err(err) = axum::serve(listener, app)
+            .with_graceful_shutdown(shutdown.cancelled_owned())
+            .await
+        {
+            tracing::warn!(?err, "Control API failed");
+        }
+    }))
+}
+
+fn router(state: Arc<ApiState>) -> Router {
+    Router::new()
+        .route("/status", get(status))
+        .route("/rate", post(rate))
+        .route("/pause", post(pause))
+        .route("/resume", post(resume))
+        .route("/stop", post(stop))
+        .route("/stage", post(stage))
+        .with_state(state)
+}
+
+async fn status(State(state): State<Arc<ApiState>>) -> Json<Status> {
+    let binds = state
+        .tracker
+        .snapshot()
+        .await
+        .into_iter()
+        .enumerate()
+        .map(|(idx, status)| BindView {
+            idx,
+            state: status.state.to_string(),
+            last_message_id: status.last_message_id,
+            history: status.history.iter().map(ToString::to_string).collect(),
+        })
+        .collect();
+    let warming_up = state.metrics.warming_up();
+    Json(Status {
+        elapsed_secs: state.started.elapsed().as_secs_f64(),
+        running: !state.shutdown.is_cancelled(),
+        warming_up,
+        control: control_status(&state.control),
+        stage: state.control.current_stage(),
+        stages: state.control.stages(),
+        metrics: if warming_up {
+            state.metrics.warmup_snapshot()
+        } else {
+            state.metrics.snapshot()
+        },
+        binds,
+    })
+}
+
+async fn rate(
+    State(state): State<Arc<ApiState>>,
+    Json(request): Json<RateRequest>,
+) -> ApiResult<ControlStatus> {
+    state
+        .control
+        .set_rate_percent(request.rate_percent)
+        .map_err(|err| (StatusCode::CONFLICT, err.to_string()))?;
+    Ok(Json(control_status(&state.control)))
+}
+
+async fn pause(State(state): State<Arc<ApiState>>) -> Json<ControlStatus> {
+    state.control.set_paused(true);
+    Json(control_status(&state.control))
+}
+
+async fn resume(State(state): State<Arc<ApiState>>) -> Json<ControlStatus> {
+    state.control.set_paused(false);
+    Json(control_status(&state.control))
+}
+
+async fn stop(State(state): State<Arc<ApiState>>) -> Json<ControlStatus> {
+    state.control.stop();
+    Json(control_status(&state.control))
+}
+
+async fn stage(
+    State(state): State<Arc<ApiState>>,
+    Json(request): Json<StageRequest>,
+) -> ApiResult<ControlStatus> {
+    let control = &state.control;
+    // The rate goes first: it is the only setting that can be refused.
+    if let Some(percent) = request.rate_percent {
+        control
+            .set_rate_percent(percent)
+            .map_err(|err| (StatusCode::CONFLICT, err.to_string()))?;
+    }
+    if let Some(binds) = request.binds {
+        control.set_binds(binds);
+    }
+    if let Some(paused) = request.paused {
+        control.set_paused(paused);
+    }
+    control.start_stage(request.name);
+    Ok(Json(control_status(control)))
+}
+
+fn control_status(control: &RunControl) -> ControlStatus {
+    let state = control.state();
+    let (active_binds, bind_slots) = control.bind_counts();
+    ControlStatus {
+        paused: state.paused,
+        rate_percent: state.rate_percent,
+        rate_locked: control.rate_locked(),
+        active_binds,
+        bind_slots,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{Request, header},
+    };
+    use http_body_util::BodyExt;
+    use serde_json::{Value, json};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::rate_limiter::TokenBucket;
+
+    fn state(rate_locked: bool) -> Arc<ApiState> {
+        let metrics = Arc::new(Metrics::new(&[], 0, false));
+        let tracker = Arc::new(BindTracker::new(2));
+        let shutdown = CancellationToken::new();
+        let control = RunControl::new(
+            2,
+            vec![(Arc::new(TokenBucket::new(100.0)), 100.0)],
+            rate_locked,
+            Box::new(|_, token, _| Box::pin(async move { token.cancelled().await })),
+            tracker.clone(),
+            metrics.clone(),
+            shutdown.clone(),
+        );
+        Arc::new(ApiState {
+            metrics,
+            tracker,
+            control: Arc::new(control),
+            started: Instant::now(),
+            shutdown,
+        })
+    }
+
+    async fn post(state: &Arc<ApiState>, path: &str, body: Value) -> (StatusCode, Value) {
+        let request = Request::post(path)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = router(state.clone()).oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body = serde_json::from_slice(&body)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&body).into_owned()));
+        (status, body)
+    }
+
+    #[tokio::test]
+    async fn rate_is_clamped() {
+        let state = state(false);
+        let (status, body) = post(&state, "/rate", json!({ "rate_percent": 250 })).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["rate_percent"], 250);
+
+        let (status, body) = post(&state, "/rate", json!({ "rate_percent": 5000 })).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["rate_percent"], 1000);
+        assert_eq!(state.control.state().rate_percent, 1000);
+    }
+
+    #[tokio::test]
+    async fn locked_rate_is_a_conflict() {
+        let state = state(true);
+        let (status, body) = post(&state, "/rate", json!({ "rate_percent": 250 })).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body, "the capacity search controls the rate");
+
+        let (status, _) = post(
+            &state,
+            "/stage",
+            json!({ "name": "peak", "rate_percent": 200 }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(state.control.current_stage(), None);
+        assert_eq!(state.control.state().rate_percent, 100);
+    }
+
+    #[tokio::test]
+    async fn bad_rate_request_is_rejected() {
+        let state = state(false);
+        let (status, _) = post(&state, "/rate", json!({ "rate_percent": -1 })).await;
+        assert!(status.is_client_error(), "{status}");
+        assert_eq!(state.control.state().rate_percent, 100);
+    }
+
+    #[tokio::test]
+    async fn pause_and_resume() {
+        let state = state(false);
+        let (status, body) = post(&state, "/pause", Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["paused"], true);
+        assert!(state.control.state().paused);
+
+        let (_, body) = post(&state, "/resume", Value::Null).await;
+        assert_eq!(body["paused"], false);
+        assert!(!state.control.state().paused);
+    }
+
+    #[tokio::test]
+    async fn stage_applies_its_settings_and_starts() {
+        let state = state(false);
+        let request = json!({ "name": "ramp", "rate_percent": 50, "binds": 5, "paused": true });
+        let (status, body) = post(&state, "/stage", request).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body,
+            json!({
+                "paused": true,
+                "rate_percent": 50,
+                "rate_locked": false,
+                "active_binds": 2,
+                "bind_slots": 2,
+            })
+        );
+        assert_eq!(state.control.current_stage().as_deref(), Some("ramp"));
+
+        let (status, _) = post(&state, "/stage", json!({ "name": "hold" })).await;
+        assert_eq!(status, StatusCode::OK);
+        let stages: Vec<_> = state
+            .control
+            .stages()
+            .into_iter()
+            .map(|stage| stage.name)
+            .collect();
+        assert_eq!(stages, ["ramp", "hold"]);
+        assert_eq!(state.control.state().rate_percent, 50);
+
+        state.control.stop();
+        state.control.join().await;
+    }
+}
@@ -1,12 +1,26 @@
-use std::fmt;
+use std::{
+    collections::VecDeque,
+    fmt,
+    time::{Duration, Instant},
+};
 
 use tokio::sync::RwLock;
 
+/// Transitions kept per bind.
+const HISTORY_LEN: usize = 10;
+
 #[derive(Clone, Debug)]
 pub enum BindState {
     Pending,
+    /// A spare slot, or a bind removed at runtime.
+    Idle,
     Connecting,
     Bound,
+    /// The SMSC unbound the session or dropped the connection.
+    Unbound(String),
+    /// We ended the session: the run stopped, the bind was removed or its
+    /// messages were sent.
+    Stopped,
     Error(String),
 }
 
@@ -14,17 +28,47 @@ impl fmt::Display for BindState {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             BindState::Pending => write!(f, "pending"),
+            BindState::Idle => write!(f, "idle"),
             BindState::Connecting => write!(f, "connecting"),
             BindState::Bound => write!(f, "bound"),
+            BindState::Unbound(reason) => write!(f, "unbound: {reason}"),
+            BindState::Stopped => write!(f, "stopped"),
             BindState::Error(err) => write!(f, "error: {err}"),
         }
     }
 }
 
+/// Duration as shown in event details, e.g. `12.3 ms`.
+pub fn format_ms(duration: Duration) -> String {
+    format!("{:.1} ms", duration.as_secs_f64() * 1000.0)
+}
+
+/// One state change of a bind.
+#[derive(Clone, Debug)]
+pub struct BindEvent {
+    /// Time since the run started.
+    pub at: Duration,
+    pub state: BindState,
+    /// Timings or cause that go with the change, e.g. the bind latency.
+    pub detail: Option<String>,
+}
+
+impl fmt::Display for BindEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "+{:.3}s {}", self.at.as_secs_f64(), self.state)?;
+        if let Some(detail) = &self.detail {
+            write!(f, " ({detail})")?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct BindStatus {
     pub state: BindState,
     pub last_message_id: Option<String>,
+    /// The last `HISTORY_LEN` state changes, oldest first.
+    pub history: VecDeque<BindEvent>,
 }
 
 impl BindStatus {
@@ -32,11 +76,13 @@ impl BindStatus {
         Self {
             state,
             last_message_id: None,
+            history: VecDeque::with_capacity(HISTORY_LEN),
         }
     }
 }
 
 pub struct BindTracker {
+    started: Instant,
     statuses: RwLock<Vec<BindStatus>>,
 }
 
@@ -47,12 +93,31 @@ impl BindTracker {
             .collect();
 
         Self {
+            started: Instant::now(),
             statuses: RwLock::new(statuses),
         }
     }
 
+    /// Time since the tracker was created, the clock of `BindEvent::at`.
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
     pub async fn set_state(&self, idx: usize, state: BindState) {
+        self.transition(idx, state, None).await;
+    }
+
+    /// Changes the state and records it in the bind's history with `detail`.
+    pub async fn transition(&self, idx: usize, state: BindState, detail: Option<String>) {
         if let Some(entry) = self.statuses.write().await.get_mut(idx) {
+            if entry.history.len() == HISTORY_LEN {
+                entry.history.pop_front();
+            }
+            entry.history.push_back(BindEvent {
+                at: self.elapsed(),
+                state: state.clone(),
+                detail,
+            });
             entry.state = state;
         }
     }
@@ -0,0 +1,473 @@
+use std::{
+    collections::HashMap,
+    io::Write,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use anyhow::Result;
+use futures::StreamExt;
+use owo_colors::OwoColorize;
+use rusmpp::{CommandId, pdus::DeliverSmResp, pdus::SubmitSm};
+use rusmppc::{Client, Event, error::Error as ClientError};
+use tokio::{
+    sync::{OwnedSemaphorePermit, Semaphore},
+    task::JoinSet,
+    time::{self, Instant, MissedTickBehavior},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    config::{AccountConfig, BindType, ChurnConfig, Config},
+    connection::{Connector, EventStream},
+    events::status_name,
+    histogram::{Histogram, HistogramSnapshot},
+    progress::format_percentiles,
+    report, worker,
+};
+
+const RENDER_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Step of a cycle a failure is attributed to.
+#[derive(Debug, Clone, Copy)]
+enum Step {
+    Connect,
+    Bind,
+    Unbind,
+}
+
+impl Step {
+    fn name(self) -> &'static str {
+        match self {
+            Step::Connect => "connect",
+            Step::Bind => "bind",
+            Step::Unbind => "unbind",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ChurnMetrics {
+    started: AtomicU64,
+    /// Cycles that went through bind and unbind.
+    completed: AtomicU64,
+    failed: AtomicU64,
+    /// Starts dropped because every task was busy.
+    skipped: AtomicU64,
+    submit_ok: AtomicU64,
+    submit_err: AtomicU64,
+    connect: Histogram,
+    bind: Histogram,
+    unbind: Histogram,
+    /// Failed cycles by step and reason, e.g. `bind ESME_RALYBND`.
+    failures: Mutex<HashMap<String, u64>>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ChurnSnapshot {
+    pub started: u64,
+    pub completed: u64,
+    pub failed: u64,
+    pub skipped: u64,
+    pub submit_ok: u64,
+    pub submit_err: u64,
+    pub connect: HistogramSnapshot,
+    pub bind: HistogramSnapshot,
+    pub unbind: HistogramSnapshot,
+    /// Most frequent first.
+    pub failures: Vec<(String, u64)>,
+}
+
+impl ChurnSnapshot {
+    /// Cycles currently connecting, bound or unbinding.
+    pub fn active(&self) -> u64 {
+        self.started
+            .saturating_sub(self.completed)
+            .saturating_sub(self.failed)
+    }
+}
+
+impl ChurnMetrics {
+    fn record_failure(&self, step: Step, reason: &str) {
+        self.failed.fetch_add(1, Ordering::Relaxed);
+        *self
+            .failures
+            .lock()
+            .expect("churn failure lock poisoned")
+            .entry(format!("{} {reason}", step.name()))
+            .or_default() += 1;
+    }
+
+    pub fn snapshot(&self) -> ChurnSnapshot {
+        let mut failures: Vec<(String, u64)> = self
+            .failures
+            .lock()
+            .expect("churn failure lock poisoned")
+            .iter()
+            .map(|(reason, count)| (reason.clone(), *count))
+            .collect();
+        failures.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        ChurnSnapshot {
+            started: self.started.load(Ordering::Relaxed),
+            completed: self.completed.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            skipped: self.skipped.load(Ordering::Relaxed),
+            submit_ok: self.submit_ok.load(Ordering::Relaxed),
+            submit_err: self.submit_err.load(Ordering::Relaxed),
+            connect: self.connect.snapshot(),
+            bind: self.bind.snapshot(),
+            unbind: self.unbind.snapshot(),
+            failures,
+        }
+    }
+}
+
+/// One account's session settings and, when it submits, its message.
+struct Target {
+    account: AccountConfig,
+    connector: Connector,
+    submit: Option<SubmitSm>,
+}
+
+/// Starts cycles at `churn.rate` across the accounts in turn until Ctrl+C
+/// or `churn.cycles` are done, then prints the churn report.
+pub async fn run(config: &Config, churn: &ChurnConfig) -> Result<()> {
+    let targets = config
+        .accounts
+        .iter()
+        .map(|account| {
+            let submit = if account.smpp.bind_type != BindType::Rx && churn.messages_per_cycle > 0 {
+                Some(worker::build_submit_sm(&account.message)?)
+            } else {
+                None
+            };
+            Ok(Arc::new(Target {
+                account: account.clone(),
+                connector: Connector::new(&account.smpp)?,
+                submit,
+            }))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if targets.is_empty() {
+        anyhow::bail!("churn: no accounts configured");
+    }
+
+    let metrics = Arc::new(ChurnMetrics::default());
+    let permits = Arc::new(Semaphore::new(churn.concurrency));
+    let shutdown = CancellationToken::new();
+    let mut tasks = JoinSet::new();
+    let mut ticker = time::interval(Duration::from_secs_f64(1.0 / churn.rate));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut render_ticker = time::interval(RENDER_INTERVAL);
+    let mut throughput = (0u64, Instant::now());
+    let started = Instant::now();
+    let mut launched = 0u64;
+
+    loop {
+        let launching = churn.cycles == 0 || launched < churn.cycles;
+        if !launching && tasks.is_empty() {
+            break;
+        }
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {
+                println!("\nCtrl+C received. Stopping churn test...");
+                break;
+            }
+            _ = ticker.tick(), if launching => {
+                let Ok(permit) = permits.clone().try_acquire_owned() else {
+                    metrics.skipped.fetch_add(1, Ordering::Relaxed);
+                    continue;
+                };
+                let target = targets[launched as usize % targets.len()].clone();
+                tasks.spawn(cycle(
+                    target,
+                    churn.messages_per_cycle,
+                    churn.hold(),
+                    metrics.clone(),
+                    shutdown.clone(),
+                    permit,
+                ));
+                launched += 1;
+            }
+            Some(_) = tasks.join_next() => {}
+            _ = render_ticker.tick() => render(churn, &metrics.snapshot(), &mut throughput),
+        }
+    }
+
+    // Sessions still up skip the rest of their hold and unbind.
+    shutdown.cancel();
+    while tasks.join_next().await.is_some() {}
+
+    let elapsed = started.elapsed();
+    render(churn, &metrics.snapshot(), &mut throughput);
+    report::print_churn(&metrics.snapshot(), elapsed);
+    println!("Churn test stopped.");
+    Ok(())
+}
+
+/// Connect, bind, submit, hold, unbind and disconnect once.
+async fn cycle(
+    target: Arc<Target>,
+    messages: u32,
+    hold: Duration,
+    metrics: Arc<ChurnMetrics>,
+    shutdown: CancellationToken,
+    _permit: OwnedSemaphorePermit,
+) {
+    metrics.started.fetch_add(1, Ordering::Relaxed);
+    let smpp = &target.account.smpp;
+
+    let start = Instant::now();
+    let (client, _, events) = match target.connector.open().await {
+        Ok(connection) => connection,
+        Err(err) => {
+            tracing::debug!(account = %target.account.name, error = ?err, "Churn connect failed");
+            let reason = if start.elapsed() >= smpp.connect_timeout() {
+                "timeout".to_string()
+            } else {
+                connect_failure(&err)
+            };
+            metrics.record_failure(Step::Connect, &reason);
+            return;
+        }
+    };
+    metrics.connect.record(start.elapsed());
+    let answering = tokio::spawn(answer(client.clone(), events));
+
+    let start = Instant::now();
+    match time::timeout(smpp.bind_timeout(), worker::bind(&client, smpp)).await {
+        Ok(Ok(())) => {
+            metrics.bind.record(start.elapsed());
+            if let Some(submit) = &target.submit {
+                for _ in 0..messages {
+                    match client.submit_sm(submit.clone()).await {
+                        Ok(_) => metrics.submit_ok.fetch_add(1, Ordering::Relaxed),
+                        Err(_) => metrics.submit_err.fetch_add(1, Ordering::Relaxed),
+                    };
+                }
+            }
+            tokio::select! {
+                _ = shutdown.cancelled() => {}
+                _ = time::sleep(hold) => {}
+            }
+
+            let start = Instant::now();
+            match client.unbind().await {
+                Ok(()) => {
+                    metrics.unbind.record(start.elapsed());
+                    metrics.completed.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(err) => metrics.record_failure(Step::Unbind, &client_failure(&err)),
+            }
+        }
+        Ok(Err(err)) => {
+            let reason = err
+                .downcast_ref::<ClientError>()
+                .map_or_else(|| "error".to_string(), client_failure);
+            metrics.record_failure(Step::Bind, &reason);
+        }
+        Err(_) => metrics.record_failure(Step::Bind, "timeout"),
+    }
+
+    client.close().await.ok();
+    answering.abort();
+}
+
+/// Keeps the session healthy while it is up: receipts, MO messages and
+/// keep-alives are acknowledged without being accounted.
+async fn answer(client: Client, mut events: EventStream) {
+    while let Some(event) = events.next().await {
+        let Event::Incoming(command) = event else {
+            continue;
+        };
+        let sequence_number = command.sequence_number();
+        let result = match command.id() {
+            CommandId::DeliverSm => {
+                client
+                    .deliver_sm_resp(sequence_number, DeliverSmResp::default())
+                    .await
+            }
+            CommandId::EnquireLink => client.enquire_link_resp(sequence_number).await,
+            _ => Ok(()),
+        };
+        if let Err(err) = result {
+            tracing::debug!(?err, "Churn session failed to answer the SMSC");
+        }
+    }
+}
+
+fn connect_failure(err: &anyhow::Error) -> String {
+    let io = err
+        .chain()
+        .find_map(|cause| match cause.downcast_ref::<ClientError>() {
+            Some(ClientError::Connect(io)) => Some(io),
+            _ => cause.downcast_ref::<std::io::Error>(),
+        });
+    io.map_or_else(|| "error".to_string(), |io| format!("{:?}", io.kind()))
+}
+
+fn client_failure(err: &ClientError) -> String {
+    match err {
+        ClientError::UnexpectedResponse { response } => status_name(response.status()),
+        ClientError::ResponseTimeout { .. } => "timeout".to_string(),
+        ClientError::ConnectionClosed => "connection closed".to_string(),
+        _ => "error".to_string(),
+    }
+}
+
+/// `throughput` holds the cycles and time of the previous render.
+fn render(churn: &ChurnConfig, snapshot: &ChurnSnapshot, throughput: &mut (u64, Instant)) {
+    let finished = snapshot.completed + snapshot.failed;
+    let (last_finished, last_instant) = *throughput;
+    let elapsed = last_instant.elapsed().as_secs_f64();
+    let rate = if elapsed > 0.0 {
+        finished.saturating_sub(last_finished) as f64 / elapsed
+    } else {
+        0.0
+    };
+    *throughput = (finished, Instant::now());
+
+    let mut stdout = std::io::stdout();
+    let _ = write!(stdout, "\x1B[2J\x1B[H"); // Clear screen + move cursor home.
+    writeln!(stdout, "{}", "SMPP Connection Churn Test".bold()).ok();
+    writeln!(stdout, "{}", "-".repeat(80)).ok();
+    writeln!(
+        stdout,
+        "Target: {:.1} cycles/s | concurrency {} | {} messages per cycle | hold {} ms",
+        churn.rate, churn.concurrency, churn.messages_per_cycle, churn.hold_ms
+    )
+    .ok();
+    writeln!(
+        stdout,
+        "Cycles: {} | active {} | OK {} | failed {} | skipped {} | {:.1} cycles/s",
+        snapshot.started.to_string().bold(),
+        snapshot.active(),
+        snapshot.completed.green(),
+        snapshot.failed.red(),
+        snapshot.skipped.yellow(),
+        rate
+    )
+    .ok();
+    if snapshot.submit_ok + snapshot.submit_err > 0 {
+        writeln!(
+            stdout,
+            "Messages: OK {} | Err {}",
+            snapshot.submit_ok.green(),
+            snapshot.submit_err.red()
+        )
+        .ok();
+    }
+    writeln!(
+        stdout,
+        "Latency p50/p95/p99 (ms): connect {} | bind {} | unbind {}",
+        format_percentiles(&snapshot.connect),
+        format_percentiles(&snapshot.bind),
+        format_percentiles(&snapshot.unbind)
+    )
+    .ok();
+    if !snapshot.failures.is_empty() {
+        let failures: Vec<String> = snapshot
+            .failures
+            .iter()
+            .map(|(reason, count)| format!("{reason} {count}"))
+            .collect();
+        writeln!(stdout, "Failures: {}", failures.join(" | ").red()).ok();
+    }
+    stdout.flush().ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, ErrorKind};
+
+    use anyhow::{Context, anyhow};
+    use rusmpp::{Command, CommandStatus, Pdu};
+
+    use super::*;
+
+    fn response(status: CommandStatus) -> ClientError {
+        ClientError::UnexpectedResponse {
+            response: Box::new(
+                Command::builder()
+                    .status(status)
+                    .sequence_number(1)
+                    .pdu(Pdu::BindTransceiverResp(Default::default())),
+            ),
+        }
+    }
+
+    #[test]
+    fn connect_failures_are_named_by_io_kind() {
+        let refused = || io::Error::from(ErrorKind::ConnectionRefused);
+        let cases: Vec<(anyhow::Error, &str)> = vec![
+            (anyhow::Error::new(refused()), "ConnectionRefused"),
+            (
+                Err::<(), _>(refused())
+                    .context("failed to connect to SMPP server localhost:2775")
+                    .unwrap_err(),
+                "ConnectionRefused",
+            ),
+            (
+                anyhow::Error::new(ClientError::Connect(io::Error::from(ErrorKind::TimedOut))),
+                "TimedOut",
+            ),
+            (anyhow::Error::new(ClientError::ConnectionClosed), "error"),
+            (anyhow!("connect timed out after 5s"), "error"),
+        ];
+        for (err, expected) in cases {
+            assert_eq!(connect_failure(&err), expected, "{err:#}");
+        }
+    }
+
+    #[test]
+    fn client_failures_are_named_by_status() {
+        let cases = [
+            (response(CommandStatus::EsmeRalybnd), "ESME_RALYBND"),
+            (response(CommandStatus::EsmeRinvpaswd), "ESME_RINVPASWD"),
+            (response(CommandStatus::Other(0x400)), "0x00000400"),
+            (
+                ClientError::ResponseTimeout {
+                    sequence_number: 1,
+                    timeout: Duration::from_secs(5),
+                },
+                "timeout",
+            ),
+            (ClientError::ConnectionClosed, "connection closed"),
+            (
+                ClientError::Connect(io::Error::from(ErrorKind::ConnectionReset)),
+                "error",
+            ),
+        ];
+        for (err, expected) in cases {
+            assert_eq!(client_failure(&err), expected, "{err:?}");
+        }
+    }
+
+    #[test]
+    fn failures_are_counted_by_step_and_reason() {
+        let metrics = ChurnMetrics::default();
+        metrics.started.fetch_add(6, Ordering::Relaxed);
+        metrics.record_failure(Step::Bind, "ESME_RALYBND");
+        metrics.record_failure(Step::Connect, "ConnectionRefused");
+        metrics.record_failure(Step::Bind, "ESME_RALYBND");
+        metrics.record_failure(Step::Unbind, "timeout");
+        metrics.record_failure(Step::Bind, "timeout");
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.failed, 5);
+        assert_eq!(snapshot.active(), 1);
+        // Most frequent first, ties by name.
+        assert_eq!(
+            snapshot.failures,
+            [
+                ("bind ESME_RALYBND".to_string(), 2),
+                ("bind timeout".to_string(), 1),
+                ("connect ConnectionRefused".to_string(), 1),
+                ("unbind timeout".to_string(), 1),
+            ]
+        );
+    }
+}
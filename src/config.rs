@@ -1,42 +1,328 @@
-use std::{fs, path::Path};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-use anyhow::{Context, Result};
-use rusmpp::values::{Npi, Ton};
+use anyhow::{Context, Result, bail};
+use regex::Regex;
+use rusmpp::values::{InterfaceVersion, Npi, Ton};
 use serde::Deserialize;
 
-#[derive(Debug, Clone, Deserialize)]
+use crate::control::{MAX_RATE_PERCENT, MIN_RATE_PERCENT};
+
+/// Fully resolved configuration: every bind belongs to exactly one account.
+#[derive(Debug, Clone)]
 pub struct Config {
-    pub smpp: SmppConfig,
-    pub message: MessageConfig,
-    pub load: LoadConfig,
+    pub accounts: Vec<AccountConfig>,
+    pub dlr: DlrConfig,
+    pub mo: MoConfig,
+    pub deliver_resp: DeliverRespConfig,
+    pub outbind: Option<OutbindConfig>,
+    pub search: Option<SearchConfig>,
+    pub api: Option<ApiConfig>,
+    pub scenario: Option<ScenarioConfig>,
+    pub churn: Option<ChurnConfig>,
+    /// Seconds at the start of the run whose metrics are kept apart from
+    /// the final statistics; 0 measures everything.
+    pub warmup_secs: u64,
 }
 
 impl Config {
     pub fn from_file(path: &Path) -> Result<Self> {
         let data = fs::read_to_string(path)
             .with_context(|| format!("Unable to read config from {}", path.display()))?;
-        let config: Config = toml::from_str(&data).context("Failed to parse TOML configuration")?;
+        Self::from_toml(&data)
+    }
 
-        if config.load.binds == 0 {
+    pub fn from_toml(data: &str) -> Result<Self> {
+        let raw: RawConfig = toml::from_str(data).context("Failed to parse TOML configuration")?;
+        let config = raw.resolve()?;
+
+        if config.total_binds() == 0 {
             tracing::warn!("Configured bind count is 0. No traffic will be generated.");
         }
 
+        for account in &config.accounts {
+            if !matches!(account.smpp.interface_version, 0x33 | 0x34 | 0x50) {
+                bail!(
+                    "account '{}': interface_version must be 0x33, 0x34 or 0x50, got {:#04x}",
+                    account.name,
+                    account.smpp.interface_version
+                );
+            }
+            match account.load.operations.total_weight() {
+                Some(0) => bail!(
+                    "account '{}': load.operations weights must not all be 0",
+                    account.name
+                ),
+                None => bail!(
+                    "account '{}': load.operations weights must add up to at most {}",
+                    account.name,
+                    u32::MAX
+                ),
+                Some(_) => {}
+            }
+        }
+
+        if let Some(outbind) = &config.outbind
+            && !matches!(outbind.interface_version, 0x33 | 0x34 | 0x50)
+        {
+            bail!(
+                "outbind: interface_version must be 0x33, 0x34 or 0x50, got {:#04x}",
+                outbind.interface_version
+            );
+        }
+
+        if let Some(scenario) = &config.scenario {
+            scenario.validate(&config)?;
+        }
+
+        if let Some(churn) = &config.churn {
+            churn.validate()?;
+            if config.search.is_some() || config.scenario.is_some() {
+                bail!("churn: can't be combined with [search] or [scenario]");
+            }
+        }
+
+        if let Some(search) = &config.search {
+            search.validate()?;
+        }
+
+        config.dlr.id_regex()?;
+        config.mo.expected_pattern()?;
+        config.deliver_resp.validate()?;
+
         Ok(config)
     }
+
+    /// Keeps the `index`-th of `count` even shares of every account's binds
+    /// for one agent of a distributed run. An account's `total_tps` and
+    /// `messages_count` are split only among the agents that get one of its
+    /// binds, so none of it is left with an agent that can't send. Only the
+    /// first agent keeps the outbind listener; the control API, the capacity
+    /// search, the scenario and the churn test are not passed on to agents.
+    pub fn share(mut self, index: usize, count: usize) -> Config {
+        let part = |total: u64, parts: usize| {
+            let (index, parts) = (index as u64, parts as u64);
+            if index >= parts {
+                0
+            } else {
+                total / parts + u64::from(index < total % parts)
+            }
+        };
+        for account in &mut self.accounts {
+            let load = &mut account.load;
+            // Binds are handed out from the first agent on, so these are
+            // the agents with at least one.
+            let senders = load.binds.clamp(1, count);
+            let slots = part(load.bind_slots() as u64, count) as usize;
+            load.binds = part(load.binds as u64, count) as usize;
+            load.max_binds = slots;
+            let mut idle = false;
+            if load.total_tps > 0 {
+                load.total_tps = part(u64::from(load.total_tps), senders) as u32;
+                idle |= load.total_tps == 0;
+            }
+            if load.messages_count > 0 {
+                load.messages_count = part(load.messages_count, senders);
+                idle |= load.messages_count == 0;
+            }
+            // 0 means unlimited for both, so a share that rounds down to
+            // nothing gets no binds instead.
+            if idle {
+                load.binds = 0;
+                load.max_binds = 0;
+            }
+        }
+        if index > 0 {
+            self.outbind = None;
+        }
+        self.api = None;
+        self.search = None;
+        self.scenario = None;
+        self.churn = None;
+        self
+    }
+
+    pub fn warmup(&self) -> Option<Duration> {
+        (self.warmup_secs > 0).then(|| Duration::from_secs(self.warmup_secs))
+    }
+
+    /// Aggregate configured rate of the submitting accounts' launch binds.
+    pub fn configured_tps(&self) -> f64 {
+        self.accounts
+            .iter()
+            .filter(|account| account.smpp.bind_type != BindType::Rx)
+            .map(|account| match account.load.total_tps() {
+                Some(tps) => f64::from(tps),
+                None => f64::from(account.load.max_tps_per_bind()) * account.load.binds as f64,
+            })
+            .sum()
+    }
+
+    /// Bind slots of all accounts, including the spare ones of `max_binds`.
+    pub fn total_binds(&self) -> usize {
+        self.accounts.iter().map(|account| account.load.bind_slots()).sum()
+    }
+
+    /// Bind slot shared by all outbind sessions, placed after the account binds.
+    pub fn outbind_bind(&self) -> Option<usize> {
+        self.outbind.as_ref().map(|_| self.total_binds())
+    }
+
+    /// Every bind slot that metrics and the tracker have to hold.
+    pub fn bind_slots(&self) -> usize {
+        self.total_binds() + usize::from(self.outbind.is_some())
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum BindType {
-    Tx,
-    Trx,
+/// One simulated customer: its own credentials, target, message profile and load.
+#[derive(Debug, Clone)]
+pub struct AccountConfig {
+    pub name: String,
+    pub smpp: SmppConfig,
+    pub message: MessageConfig,
+    pub load: LoadConfig,
+    /// The scenario's message profiles, which replace `message` while a
+    /// phase selects them; see `ScenarioConfig::profile_index`.
+    pub profiles: Vec<MessageConfig>,
 }
 
-impl Default for BindType {
-    fn default() -> Self {
-        BindType::Trx
+/// Configuration as written in TOML. The top-level `[smpp]`, `[message]` and
+/// `[load]` sections describe a single account when no `[[accounts]]` are
+/// given; otherwise `[message]` and `[load]` act as defaults for accounts
+/// that omit their own.
+#[derive(Debug, Deserialize)]
+struct RawConfig {
+    #[serde(default)]
+    warmup_secs: u64,
+    #[serde(default)]
+    smpp: Option<SmppConfig>,
+    #[serde(default)]
+    message: Option<MessageConfig>,
+    #[serde(default)]
+    load: Option<LoadConfig>,
+    #[serde(default)]
+    accounts: Vec<RawAccount>,
+    #[serde(default)]
+    dlr: DlrConfig,
+    #[serde(default)]
+    mo: MoConfig,
+    #[serde(default)]
+    deliver_resp: DeliverRespConfig,
+    #[serde(default)]
+    outbind: Option<OutbindConfig>,
+    #[serde(default)]
+    search: Option<SearchConfig>,
+    #[serde(default)]
+    api: Option<ApiConfig>,
+    #[serde(default)]
+    scenario: Option<ScenarioConfig>,
+    #[serde(default)]
+    churn: Option<ChurnConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawAccount {
+    #[serde(default)]
+    name: Option<String>,
+    smpp: SmppConfig,
+    #[serde(default)]
+    message: Option<MessageConfig>,
+    #[serde(default)]
+    load: Option<LoadConfig>,
+}
+
+impl RawConfig {
+    fn resolve(self) -> Result<Config> {
+        let accounts = if self.accounts.is_empty() {
+            let smpp = self
+                .smpp
+                .context("missing [smpp] section (or [[accounts]] entries)")?;
+            let message = self.message.context("missing [message] section")?;
+            vec![AccountConfig {
+                name: smpp.system_id.clone(),
+                smpp,
+                message,
+                load: self.load.unwrap_or_default(),
+                profiles: Vec::new(),
+            }]
+        } else {
+            if self.smpp.is_some() {
+                tracing::warn!("[smpp] is ignored because [[accounts]] are configured");
+            }
+            self.accounts
+                .into_iter()
+                .enumerate()
+                .map(|(idx, account)| {
+                    let name = account
+                        .name
+                        .unwrap_or_else(|| format!("{}#{idx}", account.smpp.system_id));
+                    let message = account
+                        .message
+                        .or_else(|| self.message.clone())
+                        .with_context(|| {
+                            format!("account '{name}' has no [accounts.message] and no default [message]")
+                        })?;
+                    Ok(AccountConfig {
+                        name,
+                        smpp: account.smpp,
+                        message,
+                        load: account
+                            .load
+                            .or_else(|| self.load.clone())
+                            .unwrap_or_default(),
+                        profiles: Vec::new(),
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?
+        };
+
+        // Metrics and reports are grouped by name.
+        for (idx, account) in accounts.iter().enumerate() {
+            if accounts[..idx]
+                .iter()
+                .any(|other| other.name == account.name)
+            {
+                bail!("account name '{}' is used more than once", account.name);
+            }
+        }
+
+        let mut accounts = accounts;
+        for account in &mut accounts {
+            account.smpp.addr_ton.get_or_insert(account.message.source_ton);
+            account.smpp.addr_npi.get_or_insert(account.message.source_npi);
+            if let Some(scenario) = &self.scenario {
+                account.profiles = scenario.profiles.values().cloned().collect();
+            }
+        }
+
+        Ok(Config {
+            accounts,
+            dlr: self.dlr,
+            mo: self.mo,
+            deliver_resp: self.deliver_resp,
+            outbind: self.outbind,
+            search: self.search,
+            api: self.api,
+            scenario: self.scenario,
+            churn: self.churn,
+            warmup_secs: self.warmup_secs,
+        })
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BindType {
+    Tx,
+    #[default]
+    Trx,
+    /// Receive-only: no submits, used to measure MO `deliver_sm` traffic.
+    Rx,
+}
+
 impl<'de> Deserialize<'de> for BindType {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -46,8 +332,9 @@ impl<'de> Deserialize<'de> for BindType {
         match s.to_ascii_uppercase().as_str() {
             "TX" => Ok(BindType::Tx),
             "TRX" => Ok(BindType::Trx),
+            "RX" => Ok(BindType::Rx),
             other => Err(serde::de::Error::custom(format!(
-                "invalid bind_type '{}' expected TX or TRX",
+                "invalid bind_type '{}' expected TX, TRX or RX",
                 other
             ))),
         }
@@ -64,14 +351,148 @@ pub struct SmppConfig {
     pub password: String,
     #[serde(default)]
     pub bind_type: BindType,
+    /// Interval between keep-alive `enquire_link` requests, 0 disables them.
+    #[serde(default = "default_enquire_link_interval_ms")]
+    pub enquire_link_interval_ms: u64,
+    /// How long to wait for any response PDU, 0 waits forever.
+    #[serde(default = "default_response_timeout_ms")]
+    pub response_timeout_ms: u64,
+    #[serde(default = "default_connect_timeout_ms")]
+    pub connect_timeout_ms: u64,
+    #[serde(default = "default_bind_timeout_ms")]
+    pub bind_timeout_ms: u64,
+    /// Bind `addr_ton`; defaults to the account's `message.source_ton`.
+    #[serde(default)]
+    pub addr_ton: Option<u8>,
+    /// Bind `addr_npi`; defaults to the account's `message.source_npi`.
+    #[serde(default)]
+    pub addr_npi: Option<u8>,
+    #[serde(default)]
+    pub address_range: String,
+    /// SMPP version announced in the bind: 0x33, 0x34 or 0x50.
+    #[serde(default = "default_interface_version")]
+    pub interface_version: u8,
+    /// Wraps the SMPP session in TLS when present.
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+    /// What a bind does when the SMSC unbinds it or drops the connection.
+    #[serde(default)]
+    pub on_disconnect: DisconnectAction,
+    #[serde(default = "default_reconnect_delay_ms")]
+    pub reconnect_delay_ms: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisconnectAction {
+    /// Connect and bind again after `reconnect_delay_ms`.
+    #[default]
+    Reconnect,
+    /// Leave the bind down for the rest of the run.
+    Stop,
 }
 
 impl SmppConfig {
-    pub fn connection_uri(&self) -> String {
-        format!("smpp://{}:{}", self.host, self.port)
+    pub fn addr_ton(&self) -> Ton {
+        Ton::from(self.addr_ton.unwrap_or_default())
+    }
+
+    pub fn addr_npi(&self) -> Npi {
+        Npi::from(self.addr_npi.unwrap_or_default())
+    }
+
+    pub fn interface_version(&self) -> InterfaceVersion {
+        interface_version(self.interface_version)
+    }
+
+    pub fn tls_enabled(&self) -> bool {
+        self.tls.as_ref().is_some_and(|tls| tls.enabled)
+    }
+
+    pub fn enquire_link_interval(&self) -> Option<Duration> {
+        non_zero_millis(self.enquire_link_interval_ms)
+    }
+
+    pub fn response_timeout(&self) -> Option<Duration> {
+        non_zero_millis(self.response_timeout_ms)
+    }
+
+    pub fn connect_timeout(&self) -> Duration {
+        non_zero_millis(self.connect_timeout_ms)
+            .unwrap_or(Duration::from_millis(default_connect_timeout_ms()))
+    }
+
+    pub fn bind_timeout(&self) -> Duration {
+        non_zero_millis(self.bind_timeout_ms)
+            .unwrap_or(Duration::from_millis(default_bind_timeout_ms()))
+    }
+
+    pub fn reconnect_delay(&self) -> Duration {
+        Duration::from_millis(self.reconnect_delay_ms)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TlsConfig {
+    #[serde(default = "default_tls_enabled")]
+    pub enabled: bool,
+    /// PEM bundle of trusted CAs; the Mozilla root set is used when omitted.
+    #[serde(default)]
+    pub ca_file: Option<PathBuf>,
+    /// PEM client certificate chain for mutual TLS.
+    #[serde(default)]
+    pub cert_file: Option<PathBuf>,
+    /// PEM private key matching `cert_file`.
+    #[serde(default)]
+    pub key_file: Option<PathBuf>,
+    /// Name sent in SNI and checked against the server certificate, defaults to `host`.
+    #[serde(default)]
+    pub server_name: Option<String>,
+    /// Accept any server certificate. Only meant for lab setups.
+    #[serde(default)]
+    pub insecure_skip_verify: bool,
+}
+
+const fn default_tls_enabled() -> bool {
+    true
+}
+
+fn interface_version(version: u8) -> InterfaceVersion {
+    match version {
+        0x50 => InterfaceVersion::Smpp5_0,
+        0x34 => InterfaceVersion::Smpp3_4,
+        other => InterfaceVersion::Smpp3_3OrEarlier(other),
     }
 }
 
+fn non_zero_millis(ms: u64) -> Option<Duration> {
+    (ms > 0).then(|| Duration::from_millis(ms))
+}
+
+const fn default_interface_version() -> u8 {
+    0x34
+}
+
+const fn default_enquire_link_interval_ms() -> u64 {
+    5_000
+}
+
+const fn default_response_timeout_ms() -> u64 {
+    5_000
+}
+
+const fn default_connect_timeout_ms() -> u64 {
+    10_000
+}
+
+const fn default_bind_timeout_ms() -> u64 {
+    10_000
+}
+
+const fn default_reconnect_delay_ms() -> u64 {
+    1_000
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct MessageConfig {
     pub source_addr: String,
@@ -111,6 +532,523 @@ impl MessageConfig {
     }
 }
 
+/// Delivery receipt correlation settings shared by all accounts.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DlrConfig {
+    /// How long to wait for a receipt before counting it as missing.
+    #[serde(default = "default_dlr_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Upper bound on messages awaiting a receipt; the oldest are evicted beyond it.
+    #[serde(default = "default_max_pending")]
+    pub max_pending: usize,
+    /// Normalizations applied, in order, to submit_sm_resp and receipt IDs
+    /// before they are matched. Empty means exact matching.
+    #[serde(default)]
+    pub id_match: Vec<IdNormalization>,
+    /// Pattern used by the `regex` normalization; the first capture group (or
+    /// the whole match) becomes the matching key.
+    #[serde(default)]
+    pub id_regex: Option<String>,
+}
+
+impl Default for DlrConfig {
+    fn default() -> Self {
+        Self {
+            timeout_secs: default_dlr_timeout_secs(),
+            max_pending: default_max_pending(),
+            id_match: Vec::new(),
+            id_regex: None,
+        }
+    }
+}
+
+impl DlrConfig {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(if self.timeout_secs == 0 {
+            default_dlr_timeout_secs()
+        } else {
+            self.timeout_secs
+        })
+    }
+
+    pub fn max_pending(&self) -> usize {
+        if self.max_pending == 0 {
+            default_max_pending()
+        } else {
+            self.max_pending
+        }
+    }
+
+    /// Compiles `id_regex`, which must be set when `id_match` uses `regex`.
+    pub fn id_regex(&self) -> Result<Option<Regex>> {
+        if !self.id_match.contains(&IdNormalization::Regex) {
+            return Ok(None);
+        }
+        let pattern = self
+            .id_regex
+            .as_deref()
+            .context("dlr.id_regex is required when dlr.id_match contains \"regex\"")?;
+        Regex::new(pattern)
+            .map(Some)
+            .with_context(|| format!("invalid dlr.id_regex '{pattern}'"))
+    }
+}
+
+/// Checks applied to mobile-originated `deliver_sm` received on TRX/RX binds.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct MoConfig {
+    /// Regex every MO body is expected to match; mismatches are counted.
+    #[serde(default)]
+    pub expected_pattern: Option<String>,
+}
+
+impl MoConfig {
+    pub fn expected_pattern(&self) -> Result<Option<Regex>> {
+        self.expected_pattern
+            .as_deref()
+            .map(|pattern| {
+                Regex::new(pattern)
+                    .with_context(|| format!("invalid mo.expected_pattern '{pattern}'"))
+            })
+            .transpose()
+    }
+}
+
+/// Capacity search: the aggregate rate is raised step by step (or bisected)
+/// until a step breaks the error, latency or throughput limits.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SearchConfig {
+    #[serde(default)]
+    pub strategy: SearchStrategy,
+    #[serde(default = "default_search_start_tps")]
+    pub start_tps: u32,
+    /// Increment of the `step` strategy and resolution of `binary`.
+    #[serde(default = "default_search_step_tps")]
+    pub step_tps: u32,
+    pub max_tps: u32,
+    /// How long each rate is held and measured.
+    #[serde(default = "default_search_step_secs")]
+    pub step_secs: u64,
+    /// Time after each rate change that is not measured.
+    #[serde(default = "default_search_settle_secs")]
+    pub settle_secs: u64,
+    #[serde(default = "default_search_max_error_percent")]
+    pub max_error_percent: f64,
+    #[serde(default = "default_search_max_p99_ms")]
+    pub max_p99_ms: f64,
+    /// Share of the target rate a step must actually reach to pass.
+    #[serde(default = "default_search_min_achieved_percent")]
+    pub min_achieved_percent: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchStrategy {
+    /// `start_tps`, then `step_tps` more each step until one fails.
+    #[default]
+    Step,
+    /// Bisects between `start_tps` and `max_tps` down to `step_tps`.
+    Binary,
+}
+
+impl SearchConfig {
+    fn validate(&self) -> Result<()> {
+        if self.start_tps == 0 || self.step_tps == 0 {
+            bail!("search: start_tps and step_tps must be greater than 0");
+        }
+        if self.max_tps < self.start_tps {
+            bail!(
+                "search: max_tps ({}) must not be below start_tps ({})",
+                self.max_tps,
+                self.start_tps
+            );
+        }
+        if self.step_secs == 0 {
+            bail!("search: step_secs must be greater than 0");
+        }
+        Ok(())
+    }
+
+    pub fn step_duration(&self) -> Duration {
+        Duration::from_secs(self.step_secs)
+    }
+
+    pub fn settle_duration(&self) -> Duration {
+        Duration::from_secs(self.settle_secs)
+    }
+}
+
+const fn default_search_start_tps() -> u32 {
+    100
+}
+
+const fn default_search_step_tps() -> u32 {
+    100
+}
+
+const fn default_search_step_secs() -> u64 {
+    30
+}
+
+const fn default_search_settle_secs() -> u64 {
+    5
+}
+
+const fn default_search_max_error_percent() -> f64 {
+    1.0
+}
+
+const fn default_search_max_p99_ms() -> f64 {
+    1000.0
+}
+
+const fn default_search_min_achieved_percent() -> f64 {
+    95.0
+}
+
+/// Connection churn ("bind storm") test: instead of long-lived binds, tasks
+/// connect, bind, optionally submit, unbind and disconnect over and over,
+/// cycling through the accounts.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChurnConfig {
+    /// Cycles started per second across all tasks.
+    #[serde(default = "default_churn_rate")]
+    pub rate: f64,
+    /// Cycles in progress at once; a start is skipped while all are busy.
+    #[serde(default = "default_churn_concurrency")]
+    pub concurrency: usize,
+    /// `submit_sm` sent on each session before it unbinds; none on RX accounts.
+    #[serde(default)]
+    pub messages_per_cycle: u32,
+    /// How long each session stays bound after its messages.
+    #[serde(default)]
+    pub hold_ms: u64,
+    /// Cycles to run before stopping; 0 runs until stopped.
+    #[serde(default)]
+    pub cycles: u64,
+}
+
+impl ChurnConfig {
+    fn validate(&self) -> Result<()> {
+        if !(self.rate.is_finite() && self.rate > 0.0) {
+            bail!("churn: rate must be greater than 0, got {}", self.rate);
+        }
+        if self.concurrency == 0 {
+            bail!("churn: concurrency must be greater than 0");
+        }
+        Ok(())
+    }
+
+    pub fn hold(&self) -> Duration {
+        Duration::from_millis(self.hold_ms)
+    }
+}
+
+const fn default_churn_rate() -> f64 {
+    10.0
+}
+
+const fn default_churn_concurrency() -> usize {
+    100
+}
+
+/// Scripted run: the phases are applied one after another through the same
+/// controls as the keyboard and the control API.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScenarioConfig {
+    /// Stops the run after the last phase instead of holding its settings.
+    #[serde(default = "default_scenario_stop_at_end")]
+    pub stop_at_end: bool,
+    /// Named message profiles a phase can switch every account to.
+    #[serde(default)]
+    pub profiles: BTreeMap<String, MessageConfig>,
+    pub phases: Vec<PhaseConfig>,
+}
+
+/// One step of a scenario. Unset settings keep their current value.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PhaseConfig {
+    pub name: String,
+    /// How long the phase lasts; with `until` the longest it may last.
+    #[serde(default)]
+    pub duration_secs: u64,
+    /// Ends the phase early once the condition holds.
+    #[serde(default)]
+    pub until: Option<PhaseUntil>,
+    /// Target rate as a percentage of the configured one.
+    #[serde(default)]
+    pub rate_percent: Option<u32>,
+    /// Target aggregate rate, converted to a percentage of the configured one.
+    #[serde(default)]
+    pub tps: Option<u32>,
+    /// Running binds across all accounts.
+    #[serde(default)]
+    pub binds: Option<usize>,
+    #[serde(default)]
+    pub paused: Option<bool>,
+    /// A key of `profiles`, or "default" for each account's own message.
+    #[serde(default)]
+    pub profile: Option<String>,
+    #[serde(default, rename = "assert")]
+    pub assertions: PhaseAssertions,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PhaseUntil {
+    /// No delivery receipt is outstanding any more.
+    DlrDrained,
+}
+
+/// Limits checked against the phase's own window of metrics when it ends.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PhaseAssertions {
+    #[serde(default)]
+    pub max_error_percent: Option<f64>,
+    #[serde(default)]
+    pub max_p99_ms: Option<f64>,
+    /// Lowest acceptable achieved rate over the phase.
+    #[serde(default)]
+    pub min_tps: Option<f64>,
+    /// Highest acceptable number of outstanding receipts at the phase's end.
+    #[serde(default)]
+    pub max_dlr_pending: Option<u64>,
+}
+
+impl ScenarioConfig {
+    fn validate(&self, config: &Config) -> Result<()> {
+        if config.search.is_some() {
+            bail!("scenario: can't be combined with [search], which owns the rate");
+        }
+        if self.phases.is_empty() {
+            bail!("scenario: needs at least one phase");
+        }
+        if self.profiles.contains_key(DEFAULT_PROFILE) {
+            bail!("scenario: profile name '{DEFAULT_PROFILE}' is reserved");
+        }
+        for phase in &self.phases {
+            if phase.rate_percent.is_some() && phase.tps.is_some() {
+                bail!("scenario phase '{}': set rate_percent or tps, not both", phase.name);
+            }
+            if phase.tps.is_some() && config.configured_tps() == 0.0 {
+                bail!(
+                    "scenario phase '{}': tps needs binds that submit",
+                    phase.name
+                );
+            }
+            // The run control would clamp it, leaving the phase at a rate
+            // it didn't ask for.
+            if let Some(percent) = phase.rate_percent(config.configured_tps())
+                && !(MIN_RATE_PERCENT..=MAX_RATE_PERCENT).contains(&percent)
+            {
+                let configured = config.configured_tps();
+                bail!(
+                    "scenario phase '{}': target rate must be between {MIN_RATE_PERCENT}% and \
+                     {MAX_RATE_PERCENT}% of the configured {configured} TPS, got {percent}%",
+                    phase.name
+                );
+            }
+            if phase.until.is_some() && phase.duration_secs == 0 {
+                bail!(
+                    "scenario phase '{}': until needs duration_secs as its timeout",
+                    phase.name
+                );
+            }
+            if let Some(profile) = &phase.profile {
+                self.profile_index(profile).with_context(|| {
+                    format!("scenario phase '{}': unknown profile '{profile}'", phase.name)
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Value of `ControlState::profile` for a profile name: 0 for each
+    /// account's own message, then the profiles in name order.
+    pub fn profile_index(&self, name: &str) -> Option<usize> {
+        if name == DEFAULT_PROFILE {
+            return Some(0);
+        }
+        self.profiles.keys().position(|key| key == name).map(|idx| idx + 1)
+    }
+}
+
+impl PhaseConfig {
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs(self.duration_secs)
+    }
+
+    /// Target rate as a percentage of `configured_tps`, from `rate_percent`
+    /// or `tps`.
+    pub fn rate_percent(&self, configured_tps: f64) -> Option<u32> {
+        match (self.rate_percent, self.tps) {
+            (Some(percent), _) => Some(percent),
+            (None, Some(tps)) => Some((f64::from(tps) / configured_tps * 100.0).round() as u32),
+            (None, None) => None,
+        }
+    }
+}
+
+const DEFAULT_PROFILE: &str = "default";
+
+const fn default_scenario_stop_at_end() -> bool {
+    true
+}
+
+/// Listener for SMSCs that open the connection themselves with `outbind`
+/// and expect a `bind_receiver` back on it.
+#[derive(Debug, Clone, Deserialize)]
+pub struct OutbindConfig {
+    /// Address to accept SMSC connections on, e.g. "0.0.0.0:2776".
+    pub listen: String,
+    /// Credentials the SMSC must present in `outbind`; also used for the
+    /// `bind_receiver` sent back.
+    pub system_id: String,
+    pub password: String,
+    #[serde(default)]
+    pub system_type: Option<String>,
+    #[serde(default = "default_interface_version")]
+    pub interface_version: u8,
+    /// How long to wait for `outbind` after accepting, and for `bind_resp`.
+    #[serde(default = "default_bind_timeout_ms")]
+    pub bind_timeout_ms: u64,
+    #[serde(default = "default_response_timeout_ms")]
+    pub response_timeout_ms: u64,
+}
+
+impl OutbindConfig {
+    pub fn interface_version(&self) -> InterfaceVersion {
+        interface_version(self.interface_version)
+    }
+
+    pub fn bind_timeout(&self) -> Duration {
+        non_zero_millis(self.bind_timeout_ms)
+            .unwrap_or(Duration::from_millis(default_bind_timeout_ms()))
+    }
+
+    pub fn response_timeout(&self) -> Option<Duration> {
+        non_zero_millis(self.response_timeout_ms)
+    }
+}
+
+/// Local HTTP API that reports status and takes rate, pause, stop and stage
+/// commands from scripts.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ApiConfig {
+    /// Address to serve on; keep it on loopback unless the network is trusted.
+    #[serde(default = "default_api_listen")]
+    pub listen: String,
+}
+
+fn default_api_listen() -> String {
+    "127.0.0.1:8080".to_string()
+}
+
+/// How `deliver_sm` is answered, to exercise the SMSC's retry logic. By
+/// default every `deliver_sm` is acknowledged immediately with ESME_ROK.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DeliverRespConfig {
+    /// Share of `deliver_sm` (0-100) answered with `error_status`.
+    #[serde(default)]
+    pub error_percent: f64,
+    /// `command_status` used for rejected `deliver_sm`; defaults to ESME_RX_T_APPN.
+    #[serde(default = "default_deliver_error_status")]
+    pub error_status: u32,
+    /// Share of `deliver_sm` (0-100) that get no response at all.
+    #[serde(default)]
+    pub drop_percent: f64,
+    #[serde(default)]
+    pub delay_distribution: DelayDistribution,
+    /// Fixed delay, lower bound of `uniform`, or mean of `exponential`.
+    #[serde(default)]
+    pub delay_ms: u64,
+    /// Upper bound of `uniform`, cap of `exponential` (0 = uncapped).
+    #[serde(default)]
+    pub delay_max_ms: u64,
+    /// How long a rejected or dropped `deliver_sm` is remembered to spot its
+    /// redelivery.
+    #[serde(default = "default_redelivery_window_secs")]
+    pub redelivery_window_secs: u64,
+}
+
+impl Default for DeliverRespConfig {
+    fn default() -> Self {
+        Self {
+            error_percent: 0.0,
+            error_status: default_deliver_error_status(),
+            drop_percent: 0.0,
+            delay_distribution: DelayDistribution::default(),
+            delay_ms: 0,
+            delay_max_ms: 0,
+            redelivery_window_secs: default_redelivery_window_secs(),
+        }
+    }
+}
+
+impl DeliverRespConfig {
+    fn validate(&self) -> Result<()> {
+        for (name, value) in [
+            ("error_percent", self.error_percent),
+            ("drop_percent", self.drop_percent),
+        ] {
+            if !(0.0..=100.0).contains(&value) {
+                bail!("deliver_resp.{name} must be between 0 and 100, got {value}");
+            }
+        }
+        if self.error_percent + self.drop_percent > 100.0 {
+            bail!("deliver_resp.error_percent + drop_percent must not exceed 100");
+        }
+        if self.delay_distribution == DelayDistribution::Uniform && self.delay_max_ms < self.delay_ms {
+            bail!("deliver_resp.delay_max_ms must be >= delay_ms for a uniform delay");
+        }
+        Ok(())
+    }
+
+    pub fn redelivery_window(&self) -> Duration {
+        Duration::from_secs(self.redelivery_window_secs.max(1))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DelayDistribution {
+    #[default]
+    None,
+    Fixed,
+    Uniform,
+    Exponential,
+}
+
+/// How a message ID is rewritten before DLR matching.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IdNormalization {
+    Exact,
+    /// submit_sm_resp IDs are hex, receipts carry the same number in decimal.
+    HexToDecimal,
+    /// submit_sm_resp IDs are decimal, receipts carry the same number in hex.
+    DecimalToHex,
+    StripLeadingZeros,
+    CaseInsensitive,
+    Regex,
+}
+
+const fn default_deliver_error_status() -> u32 {
+    0x64 // ESME_RX_T_APPN
+}
+
+const fn default_redelivery_window_secs() -> u64 {
+    600
+}
+
+const fn default_dlr_timeout_secs() -> u64 {
+    300
+}
+
+const fn default_max_pending() -> usize {
+    1_000_000
+}
+
 const fn default_request_dlr() -> bool {
     true
 }
@@ -123,12 +1061,39 @@ const fn default_data_coding() -> u8 {
 pub struct LoadConfig {
     #[serde(default = "default_binds")]
     pub binds: usize,
+    /// Bind slots kept for binds added at runtime; 0 means `binds`.
+    #[serde(default)]
+    pub max_binds: usize,
     #[serde(default = "default_max_tps")]
     pub max_tps_per_bind: u32,
+    /// Aggregate rate shared by all of the account's binds; overrides
+    /// `max_tps_per_bind` when non-zero.
+    #[serde(default)]
+    pub total_tps: u32,
+    /// How sends are spaced; anything but `batched` runs the open-loop generator.
+    #[serde(default)]
+    pub arrival: Arrival,
     #[serde(default = "default_inflight")]
     pub inflight_per_bind: usize,
     #[serde(default = "default_messages_count")]
     pub messages_count: u64,
+    #[serde(default)]
+    pub operations: OperationMix,
+}
+
+impl Default for LoadConfig {
+    fn default() -> Self {
+        Self {
+            binds: default_binds(),
+            max_binds: 0,
+            max_tps_per_bind: default_max_tps(),
+            total_tps: 0,
+            arrival: Arrival::default(),
+            inflight_per_bind: default_inflight(),
+            messages_count: default_messages_count(),
+            operations: OperationMix::default(),
+        }
+    }
 }
 
 impl LoadConfig {
@@ -140,6 +1105,15 @@ impl LoadConfig {
         }
     }
 
+    /// Binds started at launch plus the spare ones that can be added later.
+    pub fn bind_slots(&self) -> usize {
+        self.binds.max(self.max_binds)
+    }
+
+    pub fn total_tps(&self) -> Option<u32> {
+        (self.total_tps > 0).then_some(self.total_tps)
+    }
+
     pub fn inflight_per_bind(&self) -> usize {
         if self.inflight_per_bind == 0 {
             default_inflight()
@@ -149,6 +1123,61 @@ impl LoadConfig {
     }
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Arrival {
+    /// Closed loop: sends are released in 10 ms batches and skipped while the
+    /// in-flight window is full.
+    #[default]
+    Batched,
+    /// Open loop with exponentially distributed gaps (Poisson arrivals).
+    Poisson,
+    /// Open loop with evenly spaced sends.
+    Uniform,
+}
+
+/// Relative weights of the SMPP operations issued by each bind.
+///
+/// `query_sm`, `replace_sm` and `cancel_sm` target message IDs that were
+/// already accepted by the SMSC; while none are known the bind falls back to
+/// `submit_sm`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct OperationMix {
+    #[serde(default = "default_submit_weight")]
+    pub submit_sm: u32,
+    #[serde(default)]
+    pub query_sm: u32,
+    #[serde(default)]
+    pub replace_sm: u32,
+    #[serde(default)]
+    pub cancel_sm: u32,
+}
+
+impl Default for OperationMix {
+    fn default() -> Self {
+        Self {
+            submit_sm: default_submit_weight(),
+            query_sm: 0,
+            replace_sm: 0,
+            cancel_sm: 0,
+        }
+    }
+}
+
+impl OperationMix {
+    /// `None` when the weights don't fit in a `u32` together.
+    pub fn total_weight(&self) -> Option<u32> {
+        self.submit_sm
+            .checked_add(self.query_sm)?
+            .checked_add(self.replace_sm)?
+            .checked_add(self.cancel_sm)
+    }
+}
+
+const fn default_submit_weight() -> u32 {
+    100
+}
+
 const fn default_binds() -> usize {
     1
 }
@@ -164,3 +1193,398 @@ const fn default_inflight() -> usize {
 const fn default_messages_count() -> u64 {
     0
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One account submitting 100 TPS over two binds, plus `extra`.
+    fn config(extra: &str) -> Result<Config> {
+        Config::from_toml(&format!(
+            r#"
+            [smpp]
+            host = "127.0.0.1"
+            port = 2775
+            system_id = "esme"
+            password = "secret"
+
+            [message]
+            source_addr = "1000"
+            destination_addr = "2000"
+            body = "hello"
+
+            [load]
+            binds = 2
+            total_tps = 100
+
+            {extra}
+            "#
+        ))
+    }
+
+    /// Top-level `[message]` and `[load]` defaults followed by `accounts`.
+    fn accounts(accounts: &str) -> Result<Config> {
+        Config::from_toml(&format!(
+            r#"
+            [message]
+            source_addr = "1000"
+            source_ton = 5
+            destination_addr = "2000"
+            body = "default"
+
+            [load]
+            binds = 3
+            max_tps_per_bind = 20
+
+            {accounts}
+            "#
+        ))
+    }
+
+    #[test]
+    fn single_smpp_section_is_one_account() {
+        let config = config("").unwrap();
+        assert_eq!(config.accounts.len(), 1);
+        let account = &config.accounts[0];
+        assert_eq!(account.name, "esme");
+        assert_eq!(account.smpp.host, "127.0.0.1");
+        assert_eq!(account.message.body, "hello");
+        assert_eq!((account.load.binds, account.load.total_tps), (2, 100));
+        // Bind addressing follows the message's source address.
+        assert_eq!(account.smpp.addr_ton, Some(account.message.source_ton));
+    }
+
+    #[test]
+    fn single_smpp_section_needs_a_message() {
+        let err = Config::from_toml(
+            r#"
+            [smpp]
+            host = "127.0.0.1"
+            port = 2775
+            system_id = "esme"
+            password = "secret"
+            "#,
+        )
+        .unwrap_err();
+        assert!(format!("{err:#}").contains("missing [message]"), "{err:#}");
+
+        let err = Config::from_toml("[load]\nbinds = 1").unwrap_err();
+        assert!(format!("{err:#}").contains("missing [smpp]"), "{err:#}");
+    }
+
+    #[test]
+    fn accounts_fall_back_to_the_top_level_defaults() {
+        let config = accounts(
+            r#"
+            [[accounts]]
+            name = "acme"
+            [accounts.smpp]
+            host = "10.0.0.1"
+            port = 2775
+            system_id = "acme"
+            password = "one"
+            [accounts.load]
+            binds = 1
+            total_tps = 50
+
+            [[accounts]]
+            [accounts.smpp]
+            host = "10.0.0.2"
+            port = 2776
+            system_id = "globex"
+            password = "two"
+            addr_ton = 1
+            [accounts.message]
+            source_addr = "GLOBEX"
+            destination_addr = "3000"
+            body = "own"
+            "#,
+        )
+        .unwrap();
+
+        let [acme, globex] = &config.accounts[..] else {
+            panic!("expected two accounts");
+        };
+        assert_eq!(acme.name, "acme");
+        assert_eq!(acme.message.body, "default");
+        assert_eq!(acme.smpp.addr_ton, Some(5));
+        assert_eq!((acme.load.binds, acme.load.total_tps), (1, 50));
+        // Unnamed accounts are named after their system_id and position.
+        assert_eq!(globex.name, "globex#1");
+        assert_eq!(globex.smpp.port, 2776);
+        assert_eq!(globex.message.body, "own");
+        assert_eq!(globex.smpp.addr_ton, Some(1));
+        assert_eq!((globex.load.binds, globex.load.max_tps_per_bind), (3, 20));
+    }
+
+    #[test]
+    fn account_without_any_message_is_rejected() {
+        let err = Config::from_toml(
+            r#"
+            [[accounts]]
+            name = "acme"
+            [accounts.smpp]
+            host = "127.0.0.1"
+            port = 2775
+            system_id = "acme"
+            password = "secret"
+            "#,
+        )
+        .unwrap_err();
+        assert!(
+            format!("{err:#}").contains("account 'acme' has no [accounts.message]"),
+            "{err:#}"
+        );
+    }
+
+    #[test]
+    fn account_names_must_be_unique() {
+        let account = |name: &str| {
+            format!(
+                r#"
+                [[accounts]]
+                {name}
+                [accounts.smpp]
+                host = "127.0.0.1"
+                port = 2775
+                system_id = "acme"
+                password = "secret"
+                "#
+            )
+        };
+        let cases = [
+            (
+                account("name = \"acme\"") + &account("name = \"acme\""),
+                false,
+            ),
+            (
+                account("name = \"acme\"") + &account("name = \"acme2\""),
+                true,
+            ),
+            // Default names carry the position.
+            (account("") + &account(""), true),
+            (account("name = \"acme#1\"") + &account(""), false),
+        ];
+        for (toml, valid) in cases {
+            match accounts(&toml) {
+                Ok(_) => assert!(valid, "accepted:{toml}"),
+                Err(err) => {
+                    assert!(!valid, "{err:#}");
+                    assert!(
+                        format!("{err:#}").contains("used more than once"),
+                        "{err:#}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn total_tps_and_max_binds_size_each_account() {
+        let config = accounts(
+            r#"
+            [[accounts]]
+            name = "fixed"
+            [accounts.smpp]
+            host = "127.0.0.1"
+            port = 2775
+            system_id = "fixed"
+            password = "secret"
+            [accounts.load]
+            binds = 2
+            max_binds = 5
+            total_tps = 70
+
+            [[accounts]]
+            name = "per-bind"
+            [accounts.smpp]
+            host = "127.0.0.1"
+            port = 2775
+            system_id = "per-bind"
+            password = "secret"
+
+            [[accounts]]
+            name = "receiver"
+            [accounts.smpp]
+            host = "127.0.0.1"
+            port = 2775
+            system_id = "receiver"
+            password = "secret"
+            bind_type = "RX"
+            [accounts.load]
+            binds = 4
+            max_binds = 2
+            "#,
+        )
+        .unwrap();
+
+        let slots: Vec<_> = config
+            .accounts
+            .iter()
+            .map(|account| account.load.bind_slots())
+            .collect();
+        assert_eq!(slots, [5, 3, 4]);
+        assert_eq!(config.total_binds(), 12);
+        // total_tps caps the whole account however many binds it has; the
+        // receiver sends nothing.
+        assert_eq!(config.configured_tps(), 70.0 + 3.0 * 20.0);
+    }
+
+    /// `(binds, max_binds, total_tps, messages_count)` of each agent's share.
+    fn shares(load: &str, count: usize) -> Vec<(usize, usize, u32, u64)> {
+        let config = Config::from_toml(&format!(
+            r#"
+            [smpp]
+            host = "127.0.0.1"
+            port = 2775
+            system_id = "esme"
+            password = "secret"
+
+            [message]
+            source_addr = "1000"
+            destination_addr = "2000"
+            body = "hello"
+
+            [load]
+            {load}
+            "#
+        ))
+        .unwrap();
+        (0..count)
+            .map(|index| {
+                let load = &config.clone().share(index, count).accounts[0].load;
+                (
+                    load.binds,
+                    load.max_binds,
+                    load.total_tps,
+                    load.messages_count,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn share_splits_load_evenly() {
+        let load = "binds = 4\nmax_binds = 6\ntotal_tps = 100\nmessages_count = 10";
+        assert_eq!(
+            shares(load, 3),
+            [(2, 2, 34, 4), (1, 2, 33, 3), (1, 2, 33, 3)]
+        );
+    }
+
+    #[test]
+    fn share_gives_rate_only_to_agents_with_binds() {
+        // Two binds across three agents: the third gets none, so the rate
+        // and messages are split between the first two.
+        assert_eq!(
+            shares("binds = 2\ntotal_tps = 100\nmessages_count = 9", 3),
+            [(1, 1, 50, 5), (1, 1, 50, 4), (0, 0, 0, 0)]
+        );
+        assert_eq!(
+            shares("binds = 1\ntotal_tps = 100", 2),
+            [(1, 1, 100, 0), (0, 0, 0, 0)]
+        );
+    }
+
+    #[test]
+    fn share_too_small_to_send_gets_no_binds() {
+        // 0 would mean unlimited, so the agent whose share rounds down to
+        // nothing is left idle instead.
+        assert_eq!(
+            shares("binds = 2\ntotal_tps = 1", 2),
+            [(1, 1, 1, 0), (0, 0, 0, 0)]
+        );
+        assert_eq!(
+            shares("binds = 3\nmessages_count = 2", 3),
+            [(1, 1, 0, 1), (1, 1, 0, 1), (0, 0, 0, 0)]
+        );
+    }
+
+    #[test]
+    fn share_keeps_outbind_on_the_first_agent_only() {
+        let config = Config::from_toml(
+            r#"
+            [smpp]
+            host = "127.0.0.1"
+            port = 2775
+            system_id = "esme"
+            password = "secret"
+
+            [message]
+            source_addr = "1000"
+            destination_addr = "2000"
+            body = "hello"
+
+            [outbind]
+            listen = "127.0.0.1:2776"
+            system_id = "smsc"
+            password = "secret"
+
+            [api]
+            "#,
+        )
+        .unwrap();
+        let first = config.clone().share(0, 2);
+        let second = config.share(1, 2);
+        assert!(first.outbind.is_some() && first.api.is_none());
+        assert!(second.outbind.is_none() && second.api.is_none());
+    }
+
+    #[test]
+    fn operation_weights_must_add_up() {
+        let cases = [
+            ("submit_sm = 0\nquery_sm = 1", None),
+            ("submit_sm = 0", Some("must not all be 0")),
+            ("submit_sm = 4294967295\nquery_sm = 0\ncancel_sm = 0", None),
+            (
+                "submit_sm = 4294967295\ncancel_sm = 1",
+                Some("must add up to at most 4294967295"),
+            ),
+            (
+                "submit_sm = 2147483648\nquery_sm = 2147483648",
+                Some("must add up to at most 4294967295"),
+            ),
+        ];
+        for (weights, error) in cases {
+            let result = config(&format!("[load.operations]\n{weights}"));
+            match (result, error) {
+                (Ok(_), None) => {}
+                (Err(err), Some(error)) => {
+                    assert!(format!("{err:#}").contains(error), "{err:#}")
+                }
+                (Ok(_), Some(error)) => panic!("{weights:?} was accepted, expected {error}"),
+                (Err(err), None) => panic!("{weights:?}: {err:#}"),
+            }
+        }
+    }
+
+    #[test]
+    fn scenario_phase_rate_must_be_reachable() {
+        let cases = [
+            ("rate_percent = 10", true),
+            ("rate_percent = 1000", true),
+            ("rate_percent = 5", false),
+            ("rate_percent = 1001", false),
+            ("tps = 10", true),
+            ("tps = 1000", true),
+            ("tps = 9", false),
+            ("tps = 1001", false),
+            ("tps = 0", false),
+        ];
+        for (rate, valid) in cases {
+            let result = config(&format!(
+                "[[scenario.phases]]\nname = \"peak\"\nduration_secs = 10\n{rate}"
+            ));
+            match result {
+                Ok(_) => assert!(valid, "{rate} was accepted"),
+                Err(err) => {
+                    assert!(!valid, "{rate}: {err:#}");
+                    assert!(
+                        format!("{err:#}").contains("between 10% and 1000%"),
+                        "{err:#}"
+                    );
+                }
+            }
+        }
+    }
+}
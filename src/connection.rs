@@ -0,0 +1,97 @@
+use anyhow::{Context, Result, anyhow};
+use futures::{StreamExt, stream::BoxStream};
+use rusmppc::{Client, ConnectionBuilder, Event};
+use std::time::Duration;
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+    time,
+};
+
+use crate::{
+    config::SmppConfig,
+    side_channel::{self, SideChannel},
+    tls::TlsClient,
+};
+
+pub type EventStream = BoxStream<'static, Event>;
+
+/// Opens an account's connections to the SMSC over plain TCP or TLS. Built
+/// once per account so reconnects don't reload the TLS setup.
+pub struct Connector {
+    smpp: SmppConfig,
+    tls: Option<TlsClient>,
+}
+
+impl Connector {
+    pub fn new(smpp: &SmppConfig) -> Result<Self> {
+        let tls = smpp
+            .tls
+            .as_ref()
+            .filter(|tls| tls.enabled)
+            .map(|tls| TlsClient::new(tls, &smpp.host))
+            .transpose()?;
+        Ok(Self {
+            smpp: smpp.clone(),
+            tls,
+        })
+    }
+
+    /// Connects to the SMSC, bounded by `connect_timeout_ms`.
+    pub async fn open(&self) -> Result<(Client, SideChannel, EventStream)> {
+        let connect_timeout = self.smpp.connect_timeout();
+        time::timeout(connect_timeout, self.open_inner())
+            .await
+            .map_err(|_| anyhow!("connect timed out after {connect_timeout:?}"))?
+    }
+
+    async fn open_inner(&self) -> Result<(Client, SideChannel, EventStream)> {
+        let smpp = &self.smpp;
+        match &self.tls {
+            Some(tls) => {
+                let stream = tls.connect(&smpp.host, smpp.port).await?;
+                Ok(connected(stream, smpp.response_timeout()))
+            }
+            None => {
+                let stream = TcpStream::connect((smpp.host.as_str(), smpp.port))
+                    .await
+                    .with_context(|| {
+                        format!(
+                            "failed to connect to SMPP server {}:{}",
+                            smpp.host, smpp.port
+                        )
+                    })?;
+                stream.set_nodelay(true).ok();
+                Ok(connected(stream, smpp.response_timeout()))
+            }
+        }
+    }
+}
+
+/// Wraps a connection the SMSC opened to us (outbind).
+pub fn accept(
+    stream: TcpStream,
+    response_timeout: Option<Duration>,
+) -> (Client, SideChannel, EventStream) {
+    connected(stream, response_timeout)
+}
+
+fn connected<S>(stream: S, response_timeout: Option<Duration>) -> (Client, SideChannel, EventStream)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (stream, side_channel) = side_channel::wrap(stream, response_timeout);
+    // Keep-alives are driven by `drive_enquire_link` so they can be measured.
+    // rusmppc only binds as SMPP 5.0 unless told otherwise; the configured
+    // `interface_version` is validated when the config is loaded.
+    let builder = ConnectionBuilder::new()
+        .no_enquire_link_interval()
+        .disable_interface_version_check();
+    let builder = match response_timeout {
+        Some(timeout) => builder.response_timeout(timeout),
+        None => builder.no_response_timeout(),
+    };
+    let (client, events) = builder.connected(stream);
+    (client, side_channel, events.boxed())
+}
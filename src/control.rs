@@ -0,0 +1,540 @@
+use std::{
+    io::IsTerminal,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Result, bail};
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    terminal,
+};
+use futures::future::BoxFuture;
+use serde::Serialize;
+use tokio::{
+    sync::{mpsc, watch},
+    task::JoinHandle,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    bind_tracker::{BindState, BindTracker},
+    metrics::{Metrics, MetricsCheckpoint, WindowSnapshot},
+    rate_limiter::TokenBucket,
+};
+
+/// Each `+`/`-` moves the target rate by this share of the configured one.
+const RATE_STEP_PERCENT: u32 = 10;
+/// Bounds of the target rate, as a percentage of the configured one.
+pub const MIN_RATE_PERCENT: u32 = RATE_STEP_PERCENT;
+pub const MAX_RATE_PERCENT: u32 = 1000;
+/// How often the key reader looks at the shutdown token while no key is
+/// pressed.
+const KEY_POLL: Duration = Duration::from_millis(200);
+
+/// What the submit loops are told to do; published on a watch channel so
+/// every bind sees the latest value without queueing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ControlState {
+    /// Submit loops stop sending but stay bound.
+    pub paused: bool,
+    /// Target rate as a percentage of the configured one.
+    pub rate_percent: u32,
+    /// Message submitted: 0 for the account's own, otherwise the scenario
+    /// profile `profile - 1`.
+    pub profile: usize,
+}
+
+impl Default for ControlState {
+    fn default() -> Self {
+        Self {
+            paused: false,
+            rate_percent: 100,
+            profile: 0,
+        }
+    }
+}
+
+impl ControlState {
+    pub fn scale(&self) -> f64 {
+        f64::from(self.rate_percent) / 100.0
+    }
+
+    pub fn scale_tps(&self, tps: u32) -> u32 {
+        (f64::from(tps) * self.scale()).round() as u32
+    }
+}
+
+/// Results of a named part of the run, measured from when it was started.
+#[derive(Debug, Clone, Serialize)]
+pub struct StageSnapshot {
+    pub name: String,
+    pub duration_secs: f64,
+    pub achieved_tps: f64,
+    pub window: WindowSnapshot,
+}
+
+struct Stage {
+    name: String,
+    started: Instant,
+    checkpoint: MetricsCheckpoint,
+}
+
+/// Runs the bind in slot `idx` until the token is cancelled or it ends on
+/// its own.
+pub type BindLauncher = Box<
+    dyn Fn(usize, CancellationToken, watch::Receiver<ControlState>) -> BoxFuture<'static, ()>
+        + Send
+        + Sync,
+>;
+
+/// Changes a running test: rate, pause, message profile and the set of
+/// running binds.
+pub struct RunControl {
+    state: watch::Sender<ControlState>,
+    /// Account buckets and their configured `total_tps`, rescaled with the
+    /// rate.
+    buckets: Vec<(Arc<TokenBucket>, f64)>,
+    /// Set while the capacity search owns the rate.
+    rate_locked: bool,
+    launcher: BindLauncher,
+    /// Per account bind slot, the token of the bind running in it. A
+    /// removed bind keeps its cancelled token until it has unbound.
+    binds: Arc<Mutex<Vec<Option<CancellationToken>>>>,
+    tasks: Mutex<Vec<JoinHandle<()>>>,
+    tracker: Arc<BindTracker>,
+    metrics: Arc<Metrics>,
+    stage: Mutex<Option<Stage>>,
+    finished_stages: Mutex<Vec<StageSnapshot>>,
+    shutdown: CancellationToken,
+}
+
+impl RunControl {
+    pub fn new(
+        slots: usize,
+        buckets: Vec<(Arc<TokenBucket>, f64)>,
+        rate_locked: bool,
+        launcher: BindLauncher,
+        tracker: Arc<BindTracker>,
+        metrics: Arc<Metrics>,
+        shutdown: CancellationToken,
+    ) -> Self {
+        Self {
+            state: watch::Sender::new(ControlState::default()),
+            buckets,
+            rate_locked,
+            launcher,
+            binds: Arc::new(Mutex::new(vec![None; slots])),
+            tasks: Mutex::new(Vec::new()),
+            tracker,
+            metrics,
+            stage: Mutex::new(None),
+            finished_stages: Mutex::new(Vec::new()),
+            shutdown,
+        }
+    }
+
+    pub fn state(&self) -> ControlState {
+        *self.state.borrow()
+    }
+
+    pub fn rate_locked(&self) -> bool {
+        self.rate_locked
+    }
+
+    /// Returns the rate actually applied, after clamping.
+    pub fn set_rate_percent(&self, percent: u32) -> Result<u32> {
+        if self.rate_locked {
+            bail!("the capacity search controls the rate");
+        }
+        let percent = percent.clamp(MIN_RATE_PERCENT, MAX_RATE_PERCENT);
+        for (bucket, tps) in &self.buckets {
+            bucket.set_rate(tps * f64::from(percent) / 100.0);
+        }
+        self.state.send_modify(|state| state.rate_percent = percent);
+        tracing::info!(percent, "Target rate changed");
+        Ok(percent)
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.state.send_modify(|state| state.paused = paused);
+        tracing::info!(
+            paused,
+            "Submit loops {}",
+            if paused { "paused" } else { "resumed" }
+        );
+    }
+
+    pub fn set_profile(&self, profile: usize) {
+        self.state.send_modify(|state| state.profile = profile);
+        tracing::info!(profile, "Message profile changed");
+    }
+
+    /// Starts a bind in slot `idx` unless one is already running there.
+    pub fn start_bind(&self, idx: usize) -> bool {
+        if self.shutdown.is_cancelled() {
+            return false;
+        }
+        let token = {
+            let mut binds = self.binds.lock().expect("bind slots lock poisoned");
+            let Some(slot @ None) = binds.get_mut(idx) else {
+                return false;
+            };
+            slot.insert(self.shutdown.child_token()).clone()
+        };
+
+        let bind = (self.launcher)(idx, token.clone(), self.state.subscribe());
+        let binds = self.binds.clone();
+        let tracker = self.tracker.clone();
+        let shutdown = self.shutdown.clone();
+        let task = tokio::spawn(async move {
+            bind.await;
+            // Removed at runtime rather than stopped with the run.
+            if token.is_cancelled() && !shutdown.is_cancelled() {
+                tracker.set_state(idx, BindState::Idle).await;
+            }
+            binds.lock().expect("bind slots lock poisoned")[idx] = None;
+        });
+        self.tasks
+            .lock()
+            .expect("bind tasks lock poisoned")
+            .push(task);
+        true
+    }
+
+    /// Starts a bind in the first free slot: a spare one from `max_binds`,
+    /// a removed one or one whose bind has failed.
+    pub fn add_bind(&self) -> Option<usize> {
+        let free = self
+            .binds
+            .lock()
+            .expect("bind slots lock poisoned")
+            .iter()
+            .position(Option::is_none);
+        match free {
+            Some(idx) if self.start_bind(idx) => {
+                tracing::info!(bind = idx, "Bind added");
+                Some(idx)
+            }
+            _ => {
+                tracing::warn!("No free bind slot; raise load.max_binds to add more");
+                None
+            }
+        }
+    }
+
+    /// Unbinds the most recently numbered running bind.
+    pub fn remove_bind(&self) -> Option<usize> {
+        let binds = self.binds.lock().expect("bind slots lock poisoned");
+        let (idx, token) = binds.iter().enumerate().rev().find_map(|(idx, slot)| {
+            slot.as_ref()
+                .filter(|token| !token.is_cancelled())
+                .map(|token| (idx, token))
+        })?;
+        token.cancel();
+        tracing::info!(bind = idx, "Bind removed");
+        Some(idx)
+    }
+
+    /// Adds or removes binds until `target` are running, as far as the
+    /// slots allow. Returns how many are running.
+    pub fn set_binds(&self, target: usize) -> usize {
+        loop {
+            let (active, _) = self.bind_counts();
+            let changed = match active.cmp(&target) {
+                std::cmp::Ordering::Equal => break active,
+                std::cmp::Ordering::Less => self.add_bind(),
+                std::cmp::Ordering::Greater => self.remove_bind(),
+            };
+            if changed.is_none() {
+                break active;
+            }
+        }
+    }
+
+    /// Ends the current stage, if any, and starts measuring a new one.
+    pub fn start_stage(&self, name: String) {
+        let stage = Stage {
+            name,
+            started: Instant::now(),
+            checkpoint: self.metrics.checkpoint(),
+        };
+        tracing::info!(stage = %stage.name, "Stage started");
+        let previous = self
+            .stage
+            .lock()
+            .expect("stage lock poisoned")
+            .replace(stage);
+        if let Some(previous) = previous {
+            let finished = self.measure(&previous);
+            self.finished_stages
+                .lock()
+                .expect("stage lock poisoned")
+                .push(finished);
+        }
+    }
+
+    /// Ends the current stage and returns its results.
+    pub fn end_stage(&self) -> Option<StageSnapshot> {
+        let stage = self.stage.lock().expect("stage lock poisoned").take()?;
+        let finished = self.measure(&stage);
+        self.finished_stages
+            .lock()
+            .expect("stage lock poisoned")
+            .push(finished.clone());
+        Some(finished)
+    }
+
+    pub fn current_stage(&self) -> Option<String> {
+        self.stage
+            .lock()
+            .expect("stage lock poisoned")
+            .as_ref()
+            .map(|stage| stage.name.clone())
+    }
+
+    /// Every stage so far, the current one measured up to now.
+    pub fn stages(&self) -> Vec<StageSnapshot> {
+        let mut stages = self
+            .finished_stages
+            .lock()
+            .expect("stage lock poisoned")
+            .clone();
+        if let Some(stage) = &*self.stage.lock().expect("stage lock poisoned") {
+            stages.push(self.measure(stage));
+        }
+        stages
+    }
+
+    fn measure(&self, stage: &Stage) -> StageSnapshot {
+        let window = self.metrics.window_since(&stage.checkpoint);
+        let duration_secs = stage.started.elapsed().as_secs_f64();
+        StageSnapshot {
+            name: stage.name.clone(),
+            duration_secs,
+            achieved_tps: if duration_secs > 0.0 {
+                window.attempts as f64 / duration_secs
+            } else {
+                0.0
+            },
+            window,
+        }
+    }
+
+    /// Running binds and all account bind slots.
+    pub fn bind_counts(&self) -> (usize, usize) {
+        let binds = self.binds.lock().expect("bind slots lock poisoned");
+        let active = binds
+            .iter()
+            .flatten()
+            .filter(|token| !token.is_cancelled())
+            .count();
+        (active, binds.len())
+    }
+
+    pub fn stop(&self) {
+        if !self.shutdown.is_cancelled() {
+            tracing::info!("Stop requested. Stopping load test...");
+            self.shutdown.cancel();
+        }
+    }
+
+    /// Waits for every bind started so far, including ones added meanwhile.
+    pub async fn join(&self) {
+        loop {
+            let tasks = std::mem::take(&mut *self.tasks.lock().expect("bind tasks lock poisoned"));
+            if tasks.is_empty() {
+                break;
+            }
+            for task in tasks {
+                let _ = task.await;
+            }
+        }
+    }
+}
+
+/// Reads single key presses from the terminal and applies them to `control`.
+/// Does nothing when stdin is not a terminal (e.g. `docker run` without `-t`).
+/// The terminal is back in cooked mode once the returned task has finished.
+pub fn spawn_keyboard(
+    control: Arc<RunControl>,
+    shutdown: CancellationToken,
+) -> Option<JoinHandle<()>> {
+    if !std::io::stdin().is_terminal() {
+        return None;
+    }
+    if let Err(err) = terminal::enable_raw_mode() {
+        tracing::warn!(?err, "Keyboard controls unavailable");
+        return None;
+    }
+
+    let (keys_tx, mut keys) = mpsc::unbounded_channel();
+    let reader = tokio::task::spawn_blocking({
+        let shutdown = shutdown.clone();
+        move || read_keys(keys_tx, shutdown)
+    });
+    Some(tokio::spawn(async move {
+        loop {
+            let key = tokio::select! {
+                _ = shutdown.cancelled() => break,
+                key = keys.recv() => match key {
+                    Some(key) => key,
+                    None => break,
+                },
+            };
+            apply_key(&control, key);
+        }
+        let _ = reader.await;
+    }))
+}
+
+fn read_keys(keys: mpsc::UnboundedSender<KeyEvent>, shutdown: CancellationToken) {
+    while !shutdown.is_cancelled() {
+        match event::poll(KEY_POLL) {
+            Ok(false) => {}
+            Ok(true) => {
+                if let Ok(Event::Key(key)) = event::read()
+                    && key.kind == KeyEventKind::Press
+                    && keys.send(key).is_err()
+                {
+                    break;
+                }
+            }
+            Err(err) => {
+                tracing::warn!(?err, "Failed to read keyboard");
+                break;
+            }
+        }
+    }
+    terminal::disable_raw_mode().ok();
+}
+
+fn apply_key(control: &RunControl, key: KeyEvent) {
+    let state = control.state();
+    match key.code {
+        KeyCode::Char('+') | KeyCode::Char('=') => {
+            if let Err(err) = control.set_rate_percent(state.rate_percent + RATE_STEP_PERCENT) {
+                tracing::warn!("Rate unchanged: {err}");
+            }
+        }
+        KeyCode::Char('-') => {
+            let percent = state.rate_percent.saturating_sub(RATE_STEP_PERCENT);
+            if let Err(err) = control.set_rate_percent(percent) {
+                tracing::warn!("Rate unchanged: {err}");
+            }
+        }
+        KeyCode::Char('p') => control.set_paused(!state.paused),
+        KeyCode::Char('b') => {
+            control.add_bind();
+        }
+        KeyCode::Char('B') => {
+            control.remove_bind();
+        }
+        KeyCode::Char('q') => control.stop(),
+        // Raw mode turns Ctrl+C into a key press instead of SIGINT.
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => control.stop(),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn control(slots: usize, buckets: &[f64], rate_locked: bool) -> RunControl {
+        RunControl::new(
+            slots,
+            buckets
+                .iter()
+                .map(|&tps| (Arc::new(TokenBucket::new(tps)), tps))
+                .collect(),
+            rate_locked,
+            // Each bind runs until it is removed or the run stops.
+            Box::new(|_, token, _| Box::pin(async move { token.cancelled().await })),
+            Arc::new(BindTracker::new(slots)),
+            Arc::new(Metrics::new(&[], 0, false)),
+            CancellationToken::new(),
+        )
+    }
+
+    fn press(control: &RunControl, code: KeyCode) {
+        apply_key(control, KeyEvent::new(code, KeyModifiers::NONE));
+    }
+
+    #[test]
+    fn rate_is_clamped_and_applied_to_every_bucket() {
+        let control = control(0, &[100.0, 40.0], false);
+        for (requested, applied) in [(250, 250), (0, MIN_RATE_PERCENT), (5000, MAX_RATE_PERCENT)] {
+            assert_eq!(control.set_rate_percent(requested).unwrap(), applied);
+            assert_eq!(control.state().rate_percent, applied);
+            let rates: Vec<_> = control
+                .buckets
+                .iter()
+                .map(|(bucket, _)| bucket.rate())
+                .collect();
+            let scale = f64::from(applied) / 100.0;
+            assert_eq!(rates, [100.0 * scale, 40.0 * scale], "{requested}%");
+        }
+    }
+
+    #[test]
+    fn locked_rate_is_refused() {
+        let control = control(0, &[100.0], true);
+        assert!(control.set_rate_percent(200).is_err());
+        press(&control, KeyCode::Char('+'));
+        assert_eq!(control.state().rate_percent, 100);
+        assert_eq!(control.buckets[0].0.rate(), 100.0);
+    }
+
+    #[test]
+    fn keys_step_the_rate_within_bounds() {
+        let control = control(0, &[100.0], false);
+        press(&control, KeyCode::Char('+'));
+        press(&control, KeyCode::Char('='));
+        assert_eq!(control.state().rate_percent, 100 + 2 * RATE_STEP_PERCENT);
+        for _ in 0..20 {
+            press(&control, KeyCode::Char('-'));
+        }
+        assert_eq!(control.state().rate_percent, MIN_RATE_PERCENT);
+    }
+
+    #[test]
+    fn pause_resume_and_profile_reach_subscribers() {
+        let control = control(0, &[100.0], false);
+        let mut updates = control.state.subscribe();
+
+        press(&control, KeyCode::Char('p'));
+        assert!(updates.has_changed().unwrap());
+        assert!(updates.borrow_and_update().paused);
+
+        press(&control, KeyCode::Char('p'));
+        assert!(!updates.borrow_and_update().paused);
+
+        control.set_profile(2);
+        let state = *updates.borrow_and_update();
+        assert_eq!(
+            (state.profile, state.paused, state.rate_percent),
+            (2, false, 100)
+        );
+    }
+
+    #[tokio::test]
+    async fn binds_are_added_and_removed_within_the_slots() {
+        let control = control(3, &[100.0], false);
+        assert_eq!(control.set_binds(2), 2);
+        assert_eq!(control.bind_counts(), (2, 3));
+        assert_eq!(control.set_binds(5), 3);
+        assert_eq!(control.add_bind(), None);
+
+        // The highest numbered bind goes first.
+        assert_eq!(control.remove_bind(), Some(2));
+        assert_eq!(control.set_binds(1), 1);
+        assert_eq!(control.bind_counts(), (1, 3));
+
+        press(&control, KeyCode::Char('q'));
+        assert!(control.shutdown.is_cancelled());
+        control.join().await;
+        assert_eq!(control.bind_counts(), (0, 3));
+        assert!(!control.start_bind(0));
+    }
+}
@@ -0,0 +1,471 @@
+use std::{
+    fs,
+    io::Write,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result, bail};
+use owo_colors::OwoColorize;
+use tokio::{
+    net::{TcpStream, tcp::OwnedWriteHalf},
+    sync::mpsc,
+    time,
+};
+
+use crate::{
+    config::Config,
+    metrics::MetricsSnapshot,
+    protocol::{self, Reader, ToAgent, ToCoordinator},
+    report,
+};
+
+const RENDER_INTERVAL: Duration = Duration::from_millis(500);
+/// How long an agent may take to connect and accept its share.
+const READY_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, PartialEq)]
+enum AgentState {
+    Running,
+    Finished,
+    Failed(String),
+}
+
+struct Agent {
+    addr: String,
+    writer: OwnedWriteHalf,
+    binds: usize,
+    bound: usize,
+    metrics: Option<MetricsSnapshot>,
+    state: AgentState,
+}
+
+impl Agent {
+    fn update(&mut self, message: Result<Option<ToCoordinator>>) {
+        match message {
+            Ok(Some(ToCoordinator::Progress { metrics, bound })) => {
+                self.metrics = Some(metrics);
+                self.bound = bound;
+            }
+            Ok(Some(ToCoordinator::Finished {
+                metrics,
+                elapsed_secs,
+            })) => {
+                tracing::info!(agent = %self.addr, elapsed_secs, "Agent finished");
+                self.metrics = Some(metrics);
+                self.bound = 0;
+                self.state = AgentState::Finished;
+            }
+            Ok(Some(ToCoordinator::Failed { error })) => {
+                self.state = AgentState::Failed(error);
+            }
+            Ok(Some(ToCoordinator::Ready { .. })) => {}
+            // Only a running agent can lose its connection; a finished or
+            // failed one keeps its outcome.
+            Ok(None) if self.state == AgentState::Running => {
+                self.state = AgentState::Failed("connection lost".to_string())
+            }
+            Ok(None) => {}
+            Err(err) => self.state = AgentState::Failed(format!("{err:#}")),
+        }
+    }
+}
+
+/// Splits the load of `config_path` evenly across `addrs`, starts every
+/// agent once all of them have accepted their share, and shows their
+/// combined metrics until all have finished.
+pub async fn run(config_path: &Path, addrs: Vec<String>, token: String) -> Result<()> {
+    let text = fs::read_to_string(config_path)
+        .with_context(|| format!("Unable to read config from {}", config_path.display()))?;
+    let config = Config::from_toml(&text)?;
+    if config.search.is_some() {
+        bail!("the capacity search can't be split across agents");
+    }
+    if config.scenario.is_some() {
+        bail!("a scenario can't be split across agents");
+    }
+    if config.churn.is_some() {
+        bail!("a churn test can't be split across agents");
+    }
+
+    let count = addrs.len();
+    let mut agents = Vec::with_capacity(count);
+    let mut readers = Vec::with_capacity(count);
+    for (index, addr) in addrs.into_iter().enumerate() {
+        let prepare = ToAgent::Prepare {
+            token: token.clone(),
+            config: text.clone(),
+            index,
+            count,
+        };
+        let prepared = time::timeout(READY_TIMEOUT, prepare_agent(&addr, &prepare))
+            .await
+            .unwrap_or_else(|_| {
+                bail!(
+                    "agent {addr} did not get ready within {}s",
+                    READY_TIMEOUT.as_secs()
+                )
+            });
+        let (reader, writer, binds) = match prepared {
+            Ok(prepared) => prepared,
+            Err(err) => {
+                stop_all(&mut agents).await;
+                return Err(err);
+            }
+        };
+        tracing::info!(agent = %addr, binds, "Agent ready");
+        agents.push(Agent {
+            addr,
+            writer,
+            binds,
+            bound: 0,
+            metrics: None,
+            state: AgentState::Running,
+        });
+        readers.push(reader);
+    }
+
+    // Every agent has its share; start them together.
+    for idx in 0..agents.len() {
+        let agent = &mut agents[idx];
+        if let Err(err) = protocol::send(&mut agent.writer, &ToAgent::Start).await {
+            let err = err.context(format!("failed to start agent {}", agent.addr));
+            stop_all(&mut agents).await;
+            return Err(err);
+        }
+    }
+    let started = Instant::now();
+
+    let (messages_tx, mut messages) = mpsc::unbounded_channel();
+    for (idx, mut reader) in readers.into_iter().enumerate() {
+        let messages_tx = messages_tx.clone();
+        tokio::spawn(async move {
+            loop {
+                let message = reader.next::<ToCoordinator>().await;
+                let last = !matches!(
+                    message,
+                    Ok(Some(
+                        ToCoordinator::Progress { .. } | ToCoordinator::Ready { .. }
+                    ))
+                );
+                if messages_tx.send((idx, message)).is_err() || last {
+                    break;
+                }
+            }
+        });
+    }
+    drop(messages_tx);
+
+    let mut ticker = time::interval(RENDER_INTERVAL);
+    let mut throughput = (0u64, Instant::now());
+    let mut stopping = false;
+    while agents
+        .iter()
+        .any(|agent| agent.state == AgentState::Running)
+    {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c(), if !stopping => {
+                println!("\nCtrl+C received. Stopping agents...");
+                stopping = true;
+                stop_all(&mut agents).await;
+            }
+            message = messages.recv() => match message {
+                Some((idx, message)) => agents[idx].update(message),
+                // Every reader has stopped, so nothing will change any more.
+                None => {
+                    for agent in &mut agents {
+                        agent.update(Ok(None));
+                    }
+                }
+            },
+            _ = ticker.tick() => render(&agents, &mut throughput),
+        }
+    }
+
+    render(&agents, &mut throughput);
+    // Agents keep their warm-up out of the metrics they report.
+    let measured = started
+        .elapsed()
+        .saturating_sub(config.warmup().unwrap_or_default());
+    report::print_summary(&merged(&agents), measured);
+    println!("Load test stopped.");
+    Ok(())
+}
+
+/// Connects to the agent at `addr` and hands it its share. Returns its
+/// connection and the number of binds it will start.
+async fn prepare_agent(addr: &str, prepare: &ToAgent) -> Result<(Reader, OwnedWriteHalf, usize)> {
+    let stream = TcpStream::connect(addr)
+        .await
+        .with_context(|| format!("failed to connect to agent {addr}"))?;
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = Reader::new(reader);
+    protocol::send(&mut writer, prepare).await?;
+    match reader.next().await? {
+        Some(ToCoordinator::Ready { binds }) => Ok((reader, writer, binds)),
+        Some(ToCoordinator::Failed { error }) => {
+            bail!("agent {addr} rejected its share: {error}")
+        }
+        _ => bail!("agent {addr} did not get ready"),
+    }
+}
+
+/// Tells every agent to stop, whether it is waiting to start or running.
+async fn stop_all(agents: &mut [Agent]) {
+    for agent in agents {
+        if protocol::send(&mut agent.writer, &ToAgent::Stop)
+            .await
+            .is_err()
+        {
+            tracing::warn!(agent = %agent.addr, "Failed to stop agent");
+        }
+    }
+}
+
+fn merged(agents: &[Agent]) -> MetricsSnapshot {
+    let snapshots: Vec<MetricsSnapshot> = agents
+        .iter()
+        .filter_map(|agent| agent.metrics.clone())
+        .collect();
+    MetricsSnapshot::merge(&snapshots)
+}
+
+/// `throughput` holds the attempts and time of the previous render.
+fn render(agents: &[Agent], throughput: &mut (u64, Instant)) {
+    let snapshot = merged(agents);
+    let (last_attempts, last_instant) = *throughput;
+    let elapsed = last_instant.elapsed().as_secs_f64();
+    let tps = if elapsed > 0.0 {
+        snapshot.attempts.saturating_sub(last_attempts) as f64 / elapsed
+    } else {
+        0.0
+    };
+    *throughput = (snapshot.attempts, Instant::now());
+
+    let mut stdout = std::io::stdout();
+    let _ = write!(stdout, "\x1B[2J\x1B[H"); // Clear screen + move cursor home.
+    writeln!(stdout, "{}", "SMPP Load Test Coordinator".bold()).ok();
+    writeln!(stdout, "{}", "-".repeat(80)).ok();
+    writeln!(
+        stdout,
+        "Agents: {} | running {} | binds bound {}/{}",
+        agents.len(),
+        agents
+            .iter()
+            .filter(|agent| agent.state == AgentState::Running)
+            .count(),
+        agents.iter().map(|agent| agent.bound).sum::<usize>(),
+        agents.iter().map(|agent| agent.binds).sum::<usize>()
+    )
+    .ok();
+    writeln!(
+        stdout,
+        "Messages: {} | OK: {} | Err: {} | Average latency: {:.2} ms | Total TPS: {:.1}",
+        snapshot.attempts.to_string().bold(),
+        snapshot.ok.green(),
+        snapshot.err.red(),
+        snapshot.avg_latency_ms,
+        tps
+    )
+    .ok();
+    let delays = &snapshot.delays;
+    writeln!(
+        stdout,
+        "Delay p50/p95/p99 (ms): submit→resp {:.1}/{:.1}/{:.1} | DLR pending {}",
+        delays.submit_to_resp.p50_ms,
+        delays.submit_to_resp.p95_ms,
+        delays.submit_to_resp.p99_ms,
+        snapshot.dlr_pending
+    )
+    .ok();
+    writeln!(stdout).ok();
+
+    for (idx, agent) in agents.iter().enumerate() {
+        let state = match &agent.state {
+            AgentState::Running => "running".green().to_string(),
+            AgentState::Finished => "finished".dimmed().to_string(),
+            AgentState::Failed(err) => format!("{}: {err}", "failed".red()),
+        };
+        let (attempts, ok, err, avg_latency_ms) =
+            agent.metrics.as_ref().map_or((0, 0, 0, 0.0), |metrics| {
+                (
+                    metrics.attempts,
+                    metrics.ok,
+                    metrics.err,
+                    metrics.avg_latency_ms,
+                )
+            });
+        writeln!(
+            stdout,
+            "Agent {idx} {} -> {} | binds {}/{} | Messages {} | OK {} | Err {} | Avg {:.2} ms",
+            agent.addr,
+            state,
+            agent.bound,
+            agent.binds,
+            attempts,
+            ok.green(),
+            err.red(),
+            avg_latency_ms
+        )
+        .ok();
+    }
+    stdout.flush().ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::metrics::Metrics;
+
+    const CONFIG: &str = r#"
+        [smpp]
+        host = "127.0.0.1"
+        port = 2775
+        system_id = "esme"
+        password = "secret"
+
+        [message]
+        source_addr = "1000"
+        destination_addr = "2000"
+        body = "hello"
+
+        [load]
+        binds = 2
+        total_tps = 100
+    "#;
+
+    fn config_file(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "smpp-perf-coordinator-{name}-{}.toml",
+            std::process::id()
+        ));
+        fs::write(&path, CONFIG).unwrap();
+        path
+    }
+
+    fn metrics(attempts: u64) -> MetricsSnapshot {
+        let mut metrics = Metrics::new(&[], 0, false).snapshot();
+        metrics.attempts = attempts;
+        metrics.ok = attempts;
+        metrics
+    }
+
+    /// An agent that accepts its share and, once started, sends `messages`
+    /// and hangs up.
+    async fn agent(messages: Vec<ToCoordinator>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut reader = Reader::new(reader);
+            let Some(ToAgent::Prepare { index, .. }) = reader.next().await.unwrap() else {
+                panic!("expected prepare");
+            };
+            let ready = ToCoordinator::Ready { binds: index + 1 };
+            protocol::send(&mut writer, &ready).await.unwrap();
+            assert!(matches!(reader.next().await.unwrap(), Some(ToAgent::Start)));
+            for message in &messages {
+                protocol::send(&mut writer, message).await.unwrap();
+            }
+        });
+        addr
+    }
+
+    async fn coordinate(name: &str, agents: Vec<String>) -> Result<()> {
+        let path = config_file(name);
+        let result = time::timeout(
+            Duration::from_secs(10),
+            run(&path, agents, "secret".to_string()),
+        )
+        .await
+        .expect("coordinator kept waiting");
+        fs::remove_file(path).ok();
+        result
+    }
+
+    #[tokio::test]
+    async fn agents_that_finish_end_the_run() {
+        let finished = |attempts| ToCoordinator::Finished {
+            metrics: metrics(attempts),
+            elapsed_secs: 1.0,
+        };
+        let agents = vec![
+            agent(vec![finished(10)]).await,
+            agent(vec![
+                ToCoordinator::Progress {
+                    metrics: metrics(5),
+                    bound: 1,
+                },
+                finished(20),
+            ])
+            .await,
+        ];
+        coordinate("finish", agents).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn agent_hanging_up_while_running_ends_the_run() {
+        let progress = ToCoordinator::Progress {
+            metrics: metrics(5),
+            bound: 1,
+        };
+        let agents = vec![
+            agent(vec![progress]).await,
+            // A stray ready mustn't stop the coordinator from reading on.
+            agent(vec![ToCoordinator::Ready { binds: 1 }]).await,
+        ];
+        coordinate("hang-up", agents).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn unreachable_agent_fails_the_run() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        drop(listener);
+
+        let err = coordinate("unreachable", vec![addr]).await.unwrap_err();
+        assert!(
+            format!("{err:#}").contains("failed to connect to agent"),
+            "{err:#}"
+        );
+    }
+
+    #[tokio::test]
+    async fn lost_connection_fails_only_a_running_agent() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (_, writer) = stream.into_split();
+        let mut agent = Agent {
+            addr: "agent".to_string(),
+            writer,
+            binds: 1,
+            bound: 0,
+            metrics: None,
+            state: AgentState::Running,
+        };
+
+        agent.update(Ok(Some(ToCoordinator::Ready { binds: 1 })));
+        assert_eq!(agent.state, AgentState::Running);
+        agent.update(Ok(Some(ToCoordinator::Progress {
+            metrics: metrics(5),
+            bound: 1,
+        })));
+        assert_eq!((agent.state.clone(), agent.bound), (AgentState::Running, 1));
+        agent.update(Ok(None));
+        assert_eq!(
+            agent.state,
+            AgentState::Failed("connection lost".to_string())
+        );
+
+        agent.state = AgentState::Finished;
+        agent.update(Ok(None));
+        assert_eq!(agent.state, AgentState::Finished);
+    }
+}
@@ -0,0 +1,386 @@
+use std::{borrow::Cow, sync::Arc, time::Duration};
+
+use anyhow::Result;
+use regex::Regex;
+use rusmpp::values::MessageState;
+use tokio::{
+    task::JoinHandle,
+    time::{self, Instant},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    config::{DlrConfig, IdNormalization},
+    dlr,
+    metrics::{Metrics, Phase},
+    timed_index::TimedIndex,
+};
+
+/// How often expired entries are swept out of the store.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A submitted message still waiting for its delivery receipt.
+#[derive(Debug, Clone, Copy)]
+pub struct PendingMessage {
+    /// Bind that submitted the message; receipts are accounted against it.
+    pub bind: usize,
+    /// Message profile it was built from, whose body its receipt must match.
+    pub profile: usize,
+    /// Phase its `submit_sm_resp` was recorded in; its receipt, or its
+    /// absence, counts toward the same one.
+    pub phase: Phase,
+    /// When `submit_sm` was sent.
+    pub submitted_at: Instant,
+    /// When its `submit_sm_resp` arrived.
+    pub accepted_at: Instant,
+    /// Latest intermediate state reported for the message, if any.
+    pub last_state: Option<MessageState>,
+    /// Cancelled with `cancel_sm`, so it may never get a receipt and is not
+    /// counted as missing.
+    pub cancelled: bool,
+}
+
+/// A message that reached a final state, kept to recognise later receipts.
+#[derive(Debug, Clone, Copy)]
+pub struct ResolvedMessage {
+    pub bind: usize,
+    pub phase: Phase,
+    pub state: MessageState,
+}
+
+/// Result of looking up a receipt's message ID.
+pub enum Lookup {
+    /// An intermediate state (ENROUTE, SCHEDULED); the message stays pending.
+    Intermediate(PendingMessage),
+    /// The first final state for a pending message.
+    Final(PendingMessage),
+    /// The final state was already counted and this receipt repeats it.
+    Duplicate(ResolvedMessage),
+    /// A receipt after the final one that reports a different state, such as
+    /// DELIVRD after EXPIRED or ENROUTE after DELIVRD.
+    IllegalTransition(ResolvedMessage),
+    /// The ID was never submitted by this run (or has long expired).
+    Orphan,
+}
+
+/// Maps SMSC message IDs to pending messages so receipts can be correlated
+/// across every bind. Bounded by `dlr.max_pending`; entries older than
+/// `dlr.timeout_secs` are dropped and counted as missing DLRs. IDs are
+/// normalized per `dlr.id_match` on both sides before they are keyed.
+pub struct CorrelationStore {
+    normalizer: IdNormalizer,
+    pending: TimedIndex<PendingMessage>,
+    resolved: TimedIndex<ResolvedMessage>,
+    metrics: Arc<Metrics>,
+}
+
+impl CorrelationStore {
+    pub fn new(config: &DlrConfig, metrics: Arc<Metrics>) -> Result<Self> {
+        Ok(Self {
+            normalizer: IdNormalizer::new(config)?,
+            pending: TimedIndex::new(config.max_pending(), config.timeout()),
+            resolved: TimedIndex::new(config.max_pending(), config.timeout()),
+            metrics,
+        })
+    }
+
+    /// `response_latency` is the `submit_sm` round trip, used to date the submit.
+    pub fn insert(
+        &self,
+        message_id: String,
+        bind: usize,
+        profile: usize,
+        response_latency: Duration,
+    ) {
+        let accepted_at = Instant::now();
+        let submitted_at = accepted_at
+            .checked_sub(response_latency)
+            .unwrap_or(accepted_at);
+        let pending = PendingMessage {
+            bind,
+            profile,
+            phase: self.metrics.phase(),
+            submitted_at,
+            accepted_at,
+            last_state: None,
+            cancelled: false,
+        };
+        let key = self
+            .normalizer
+            .normalize(&message_id, Side::Submitted)
+            .into_owned();
+        if let Some(evicted) = self.pending.insert(key, pending, accepted_at)
+            && !evicted.cancelled
+        {
+            self.metrics.record_dlr_evicted(evicted.phase, evicted.bind);
+        }
+        self.metrics.observe_dlr_pending(self.pending.len());
+    }
+
+    /// Applies a receipt's state to its message. Intermediate states keep the
+    /// message pending; the first final state resolves it, and every receipt
+    /// after that is a duplicate or an illegal transition.
+    pub fn resolve(&self, message_id: &str, state: MessageState) -> Lookup {
+        let key = self.normalizer.normalize(message_id, Side::Receipt);
+        if !dlr::is_final(state) {
+            if let Some(pending) = self
+                .pending
+                .update(&key, |pending| pending.last_state = Some(state))
+            {
+                return Lookup::Intermediate(pending);
+            }
+        } else if let Some(pending) = self.pending.remove(&key) {
+            let resolved = ResolvedMessage {
+                bind: pending.bind,
+                phase: pending.phase,
+                state,
+            };
+            self.resolved
+                .insert(key.into_owned(), resolved, Instant::now());
+            self.metrics.observe_dlr_pending(self.pending.len());
+            return Lookup::Final(pending);
+        }
+
+        match self.resolved.get(&key) {
+            Some(resolved) if resolved.state == state => Lookup::Duplicate(resolved),
+            Some(resolved) => Lookup::IllegalTransition(resolved),
+            None => Lookup::Orphan,
+        }
+    }
+
+    /// The normalization applied to IDs before they are keyed.
+    pub fn normalizer(&self) -> &IdNormalizer {
+        &self.normalizer
+    }
+
+    /// Takes an ID as returned in `submit_sm_resp`. Cancelled messages are
+    /// no longer pending.
+    pub fn is_pending(&self, message_id: &str) -> bool {
+        self.pending
+            .get(&self.normalizer.normalize(message_id, Side::Submitted))
+            .is_some_and(|pending| !pending.cancelled)
+    }
+
+    /// Marks a message cancelled by a successful `cancel_sm`. It stays in
+    /// the store so a late receipt still matches, but its expiry is not a
+    /// missing DLR. Takes an ID as returned in `submit_sm_resp`.
+    pub fn cancel(&self, message_id: &str) {
+        let key = self.normalizer.normalize(message_id, Side::Submitted);
+        self.pending
+            .update(&key, |pending| pending.cancelled = true);
+    }
+
+    fn expire(&self, now: Instant) {
+        for expired in self.pending.expire(now) {
+            if !expired.cancelled {
+                self.metrics.record_dlr_expired(expired.phase, expired.bind);
+            }
+        }
+        self.resolved.expire(now);
+        self.metrics.observe_dlr_pending(self.pending.len());
+    }
+
+    pub fn spawn_sweeper(self: Arc<Self>, shutdown: CancellationToken) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = time::interval(SWEEP_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    now = ticker.tick() => self.expire(now),
+                }
+            }
+        })
+    }
+}
+
+/// Where a message ID came from; hex/decimal conversions treat the two sides
+/// differently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    /// `message_id` of a `submit_sm_resp`.
+    Submitted,
+    /// `receipted_message_id` TLV or `id:` field of a receipt.
+    Receipt,
+}
+
+/// Rewrites message IDs into a common matching key by applying the
+/// configured normalizations in order.
+pub struct IdNormalizer {
+    steps: Vec<IdNormalization>,
+    regex: Option<Regex>,
+}
+
+impl IdNormalizer {
+    pub fn new(config: &DlrConfig) -> Result<Self> {
+        Ok(Self {
+            steps: config
+                .id_match
+                .iter()
+                .copied()
+                .filter(|step| *step != IdNormalization::Exact)
+                .collect(),
+            regex: config.id_regex()?,
+        })
+    }
+
+    pub fn normalize<'a>(&self, id: &'a str, side: Side) -> Cow<'a, str> {
+        let mut key = Cow::Borrowed(id);
+        for step in &self.steps {
+            let next = match step {
+                IdNormalization::Exact => None,
+                IdNormalization::HexToDecimal => match side {
+                    Side::Submitted => reformat(&key, 16),
+                    Side::Receipt => reformat(&key, 10),
+                },
+                IdNormalization::DecimalToHex => match side {
+                    Side::Submitted => reformat(&key, 10),
+                    Side::Receipt => reformat(&key, 16),
+                },
+                IdNormalization::StripLeadingZeros => {
+                    let trimmed = key.trim_start_matches('0');
+                    match (trimmed.len() != key.len(), trimmed.is_empty()) {
+                        (false, _) => None,
+                        (true, true) => Some("0".to_string()),
+                        (true, false) => Some(trimmed.to_string()),
+                    }
+                }
+                IdNormalization::CaseInsensitive => key
+                    .chars()
+                    .any(|c| c.is_uppercase())
+                    .then(|| key.to_lowercase()),
+                IdNormalization::Regex => self.regex.as_ref().and_then(|regex| {
+                    let captures = regex.captures(&key)?;
+                    let matched = captures.get(1).or_else(|| captures.get(0))?;
+                    Some(matched.as_str().to_string())
+                }),
+            };
+            if let Some(next) = next {
+                key = Cow::Owned(next);
+            }
+        }
+        key
+    }
+}
+
+/// Parses `id` in `radix` and renders it in decimal, so both sides of a
+/// hex/decimal pair end up with the same key. IDs that don't parse are kept.
+fn reformat(id: &str, radix: u32) -> Option<String> {
+    u128::from_str_radix(id, radix)
+        .ok()
+        .map(|value| value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::MetricsSnapshot;
+
+    fn normalizer(id_match: &[IdNormalization], id_regex: Option<&str>) -> IdNormalizer {
+        let config = DlrConfig {
+            id_match: id_match.to_vec(),
+            id_regex: id_regex.map(str::to_string),
+            ..DlrConfig::default()
+        };
+        IdNormalizer::new(&config).unwrap()
+    }
+
+    #[test]
+    fn normalize() {
+        use IdNormalization::*;
+        let cases: &[(&[IdNormalization], &str, Side, &str)] = &[
+            (&[], "0ABC", Side::Submitted, "0ABC"),
+            (&[Exact], "0ABC", Side::Receipt, "0ABC"),
+            (&[HexToDecimal], "1F", Side::Submitted, "31"),
+            (&[HexToDecimal], "1f", Side::Submitted, "31"),
+            (&[HexToDecimal], "31", Side::Receipt, "31"),
+            (&[HexToDecimal], "0031", Side::Receipt, "31"),
+            (&[HexToDecimal], "xyz", Side::Submitted, "xyz"),
+            (&[DecimalToHex], "31", Side::Submitted, "31"),
+            (&[DecimalToHex], "1F", Side::Receipt, "31"),
+            (&[DecimalToHex], "001f", Side::Receipt, "31"),
+            (&[StripLeadingZeros], "000123", Side::Receipt, "123"),
+            (&[StripLeadingZeros], "0000", Side::Receipt, "0"),
+            (&[StripLeadingZeros], "123", Side::Receipt, "123"),
+            (&[CaseInsensitive], "AbC", Side::Submitted, "abc"),
+            (
+                &[StripLeadingZeros, CaseInsensitive],
+                "00AbC",
+                Side::Receipt,
+                "abc",
+            ),
+        ];
+        for (id_match, id, side, expected) in cases {
+            assert_eq!(
+                normalizer(id_match, None).normalize(id, *side),
+                *expected,
+                "{id_match:?} {id} {side:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn normalize_regex() {
+        let normalizer = normalizer(
+            &[IdNormalization::Regex, IdNormalization::CaseInsensitive],
+            Some("^msg-([0-9A-F]+)"),
+        );
+        assert_eq!(normalizer.normalize("msg-00AB-1", Side::Submitted), "00ab");
+        assert_eq!(normalizer.normalize("other", Side::Receipt), "other");
+    }
+
+    #[test]
+    fn cancelled_messages_are_not_missing() {
+        let config = DlrConfig {
+            timeout_secs: 10,
+            ..DlrConfig::default()
+        };
+        let metrics = Arc::new(Metrics::new(&[], 1, false));
+        let store = CorrelationStore::new(&config, metrics.clone()).unwrap();
+        store.insert("cancelled".to_string(), 0, 0, Duration::ZERO);
+        store.insert("lost".to_string(), 0, 0, Duration::ZERO);
+
+        store.cancel("cancelled");
+        assert!(!store.is_pending("cancelled"));
+        assert!(store.is_pending("lost"));
+
+        store.expire(Instant::now() + config.timeout());
+        let missing: u64 = metrics
+            .snapshot()
+            .per_bind
+            .iter()
+            .map(|bind| bind.dlr_missing)
+            .sum();
+        assert_eq!(missing, 1);
+    }
+
+    #[test]
+    fn expiry_counts_toward_the_submit_phase() {
+        let metrics = Arc::new(Metrics::new(&[], 1, true));
+        let store = CorrelationStore::new(&DlrConfig::default(), metrics.clone()).unwrap();
+        store.insert("warm".to_string(), 0, 0, Duration::ZERO);
+        metrics.end_warmup();
+        store.insert("measured".to_string(), 0, 0, Duration::ZERO);
+        store.insert("measured-too".to_string(), 0, 0, Duration::ZERO);
+
+        store.expire(Instant::now() + DlrConfig::default().timeout());
+        let missing = |snapshot: MetricsSnapshot| -> u64 {
+            snapshot.per_bind.iter().map(|bind| bind.dlr_missing).sum()
+        };
+        assert_eq!(missing(metrics.warmup_snapshot()), 1);
+        assert_eq!(missing(metrics.snapshot()), 2);
+    }
+
+    #[test]
+    fn hex_and_decimal_sides_meet() {
+        let hex = normalizer(&[IdNormalization::HexToDecimal], None);
+        assert_eq!(
+            hex.normalize("00FF", Side::Submitted),
+            hex.normalize("255", Side::Receipt)
+        );
+        let decimal = normalizer(&[IdNormalization::DecimalToHex], None);
+        assert_eq!(
+            decimal.normalize("255", Side::Submitted),
+            decimal.normalize("00ff", Side::Receipt)
+        );
+    }
+}
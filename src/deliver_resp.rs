@@ -0,0 +1,225 @@
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::Arc,
+    time::Duration,
+};
+
+use rand::Rng;
+use rusmpp::{CommandStatus, pdus::DeliverSm, pdus::DeliverSmResp, tlvs::TlvValue};
+use rusmppc::Client;
+use tokio::{
+    task::JoinHandle,
+    time::{self, Instant},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    config::{DelayDistribution, DeliverRespConfig},
+    metrics::Metrics,
+    timed_index::TimedIndex,
+};
+
+/// Upper bound on rejected/dropped `deliver_sm` remembered for redelivery.
+const MAX_REMEMBERED: usize = 100_000;
+/// How often rejections older than the redelivery window are forgotten.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How a single `deliver_sm` is answered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reply {
+    Accept,
+    Reject(u32),
+    Drop,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Decision {
+    pub reply: Reply,
+    pub delay: Duration,
+}
+
+impl Default for Decision {
+    /// An immediate ESME_ROK.
+    fn default() -> Self {
+        Self {
+            reply: Reply::Accept,
+            delay: Duration::ZERO,
+        }
+    }
+}
+
+impl Decision {
+    /// Only accepted `deliver_sm` count as received DLRs or MO messages; the
+    /// rest are expected to come back as redeliveries.
+    pub fn accepted(&self) -> bool {
+        self.reply == Reply::Accept
+    }
+}
+
+/// A `deliver_sm` that was rejected or dropped and may be redelivered.
+#[derive(Debug, Clone, Copy)]
+struct Attempt {
+    attempts: u32,
+    last_at: Instant,
+}
+
+/// Answers `deliver_sm` according to `[deliver_resp]` and recognises the
+/// SMSC's redeliveries of the ones it rejected or dropped.
+pub struct DeliverResponder {
+    config: DeliverRespConfig,
+    metrics: Arc<Metrics>,
+    rejected: TimedIndex<Attempt>,
+}
+
+impl DeliverResponder {
+    pub fn new(config: &DeliverRespConfig, metrics: Arc<Metrics>) -> Self {
+        Self {
+            config: config.clone(),
+            metrics,
+            rejected: TimedIndex::new(MAX_REMEMBERED, config.redelivery_window()),
+        }
+    }
+
+    /// Only a `deliver_sm` matching one this responder rejected or dropped
+    /// within the redelivery window counts as a redelivery.
+    pub fn decide(&self, deliver: &DeliverSm) -> Decision {
+        let now = Instant::now();
+        let key = fingerprint(deliver);
+        let previous = self.rejected.remove(&key);
+        if let Some(previous) = previous {
+            self.metrics.record_redelivery(
+                previous.attempts,
+                now.saturating_duration_since(previous.last_at),
+            );
+        }
+
+        let decision = Decision {
+            reply: self.pick_reply(),
+            delay: self.pick_delay(),
+        };
+        match (decision.reply, previous) {
+            (Reply::Accept, Some(_)) => self.metrics.record_redelivery_recovered(),
+            (Reply::Accept, None) => {}
+            (_, previous) => {
+                let attempt = Attempt {
+                    attempts: previous.map_or(1, |previous| previous.attempts + 1),
+                    last_at: now,
+                };
+                self.rejected.insert(key, attempt, now);
+            }
+        }
+        self.metrics.record_deliver_reply(decision.reply);
+        decision
+    }
+
+    pub fn spawn_sweeper(self: Arc<Self>, shutdown: CancellationToken) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = time::interval(SWEEP_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    now = ticker.tick() => {
+                        self.rejected.expire(now);
+                    }
+                }
+            }
+        })
+    }
+
+    /// Sends the response, in the background when it is delayed so the event
+    /// loop keeps reading.
+    pub async fn reply(&self, client: &Client, sequence_number: u32, decision: Decision) {
+        let status = match decision.reply {
+            Reply::Drop => return,
+            Reply::Accept => None,
+            Reply::Reject(status) => Some(CommandStatus::from(status)),
+        };
+        if decision.delay.is_zero() {
+            send(client.clone(), sequence_number, status).await;
+        } else {
+            let client = client.clone();
+            tokio::spawn(async move {
+                time::sleep(decision.delay).await;
+                send(client, sequence_number, status).await;
+            });
+        }
+    }
+
+    fn pick_reply(&self) -> Reply {
+        let config = &self.config;
+        if config.error_percent <= 0.0 && config.drop_percent <= 0.0 {
+            return Reply::Accept;
+        }
+        let roll = rand::rng().random_range(0.0..100.0);
+        if roll < config.error_percent {
+            Reply::Reject(config.error_status)
+        } else if roll < config.error_percent + config.drop_percent {
+            Reply::Drop
+        } else {
+            Reply::Accept
+        }
+    }
+
+    fn pick_delay(&self) -> Duration {
+        let config = &self.config;
+        let millis = match config.delay_distribution {
+            DelayDistribution::None => 0.0,
+            DelayDistribution::Fixed => config.delay_ms as f64,
+            DelayDistribution::Uniform => {
+                rand::rng().random_range(config.delay_ms..=config.delay_max_ms) as f64
+            }
+            DelayDistribution::Exponential => {
+                let sample: f64 = rand::rng().random_range(0.0..1.0);
+                let millis = -(config.delay_ms as f64) * (1.0 - sample).ln();
+                if config.delay_max_ms > 0 {
+                    millis.min(config.delay_max_ms as f64)
+                } else {
+                    millis
+                }
+            }
+        };
+        Duration::from_secs_f64(millis / 1000.0)
+    }
+}
+
+async fn send(client: Client, sequence_number: u32, status: Option<CommandStatus>) {
+    let result = match status {
+        Some(status) => {
+            client
+                .status(status)
+                .deliver_sm_resp(sequence_number, DeliverSmResp::default())
+                .await
+        }
+        None => {
+            client
+                .deliver_sm_resp(sequence_number, DeliverSmResp::default())
+                .await
+        }
+    };
+    if let Err(err) = result {
+        tracing::warn!(sequence_number, ?err, "Failed to send deliver_sm_resp");
+    }
+}
+
+/// Identifies a `deliver_sm` across redeliveries, which carry a new sequence
+/// number but otherwise repeat the PDU. Every TLV is covered along with the
+/// addresses and content, so distinct messages with the same text (MO
+/// segments, different `user_message_reference`s, receipts for different
+/// messages) don't match each other.
+fn fingerprint(deliver: &DeliverSm) -> String {
+    let mut hasher = DefaultHasher::new();
+    deliver.source_addr.bytes().hash(&mut hasher);
+    deliver.destination_addr.bytes().hash(&mut hasher);
+    u8::from(deliver.esm_class).hash(&mut hasher);
+    u8::from(deliver.data_coding).hash(&mut hasher);
+    deliver.short_message().bytes().hash(&mut hasher);
+    for tlv in deliver.tlvs().iter() {
+        match tlv.value() {
+            Some(TlvValue::ReceiptedMessageId(id)) => id.as_str().hash(&mut hasher),
+            // TLVs have no common byte accessor; their debug form is stable
+            // within a run, which is all the index needs.
+            value => format!("{:?} {value:?}", tlv.tag()).hash(&mut hasher),
+        }
+    }
+    format!("{:016x}", hasher.finish())
+}
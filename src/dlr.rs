@@ -0,0 +1,593 @@
+use std::{sync::Arc, time::Duration};
+
+use rusmpp::{pdus::DeliverSm, tlvs::TlvValue, values::MessageState};
+
+use crate::{
+    config::AccountConfig,
+    correlation::{CorrelationStore, IdNormalizer, Lookup, Side},
+    metrics::Metrics,
+};
+
+/// `esm_class` message-type bits for an SMSC delivery receipt (0x04) or an
+/// intermediate delivery notification (0x20).
+const ESM_CLASS_RECEIPT_MASK: u8 = 0x24;
+
+/// `data_coding` whose body can't be compared with the receipt's `text:`.
+pub const UCS2_DATA_CODING: u8 = 0x08;
+
+/// A delivery receipt extracted from a `deliver_sm`, merging the TLVs with
+/// the textual `id:... stat:...` body. TLV values win when both are present.
+#[derive(Debug)]
+pub struct Receipt {
+    pub message_id: String,
+    pub state: Option<MessageState>,
+    /// TLVs and text carried different states. Differing IDs are only known
+    /// once normalized; see [`Receipt::is_conflicting`].
+    pub conflicting_state: bool,
+    /// The parsed text body, when it looked like a receipt.
+    pub text: Option<TextReceipt>,
+}
+
+impl Receipt {
+    /// Returns `None` for anything that isn't a delivery receipt, i.e. a
+    /// mobile-originated message. A receipt is flagged by its `esm_class`,
+    /// carries a `receipted_message_id` TLV, or has both `id:` and `stat:` in
+    /// its text. A receipt no message ID can be read from is malformed.
+    pub fn from_deliver_sm(deliver: &DeliverSm) -> Option<Result<Self, &'static str>> {
+        let mut tlv_id = None;
+        let mut tlv_state = None;
+        for tlv in deliver.tlvs().iter() {
+            match tlv.value() {
+                Some(TlvValue::ReceiptedMessageId(id)) => tlv_id = Some(id.as_str().to_string()),
+                Some(TlvValue::MessageState(state)) => tlv_state = Some(*state),
+                _ => {}
+            }
+        }
+
+        let text = deliver
+            .short_message()
+            .to_str()
+            .ok()
+            .and_then(TextReceipt::parse);
+        let flagged = u8::from(deliver.esm_class) & ESM_CLASS_RECEIPT_MASK != 0;
+        let looks_like_receipt = text
+            .as_ref()
+            .is_some_and(|text| text.id.is_some() && text.stat.is_some());
+        if !flagged && tlv_id.is_none() && !looks_like_receipt {
+            return None;
+        }
+
+        let text_id = text.as_ref().and_then(|text| text.id.clone());
+        let text_state = text.as_ref().and_then(TextReceipt::state);
+
+        let Some(message_id) = tlv_id.or(text_id) else {
+            return Some(Err("no message ID"));
+        };
+        let conflicting_state = differs(&tlv_state, &text_state);
+        Some(Ok(Self {
+            message_id,
+            state: tlv_state.or(text_state),
+            conflicting_state,
+            text,
+        }))
+    }
+
+    /// Whether the TLVs and text disagree: different states, or message IDs
+    /// that don't normalize to the same key (`0000ABC` and `abc` are the same
+    /// message once leading zeros and case are ignored).
+    pub fn is_conflicting(&self, normalizer: &IdNormalizer) -> bool {
+        let text_id = self.text.as_ref().and_then(|text| text.id.as_deref());
+        let ids_differ = text_id.is_some_and(|text_id| {
+            normalizer.normalize(text_id, Side::Receipt)
+                != normalizer.normalize(&self.message_id, Side::Receipt)
+        });
+        self.conflicting_state || ids_differ
+    }
+
+    /// Submit-to-done time as reported by the SMSC in the receipt dates.
+    pub fn smsc_delay(&self) -> Option<Duration> {
+        let text = self.text.as_ref()?;
+        let seconds = text.done_date?.checked_sub(text.submit_date?)?;
+        u64::try_from(seconds).ok().map(Duration::from_secs)
+    }
+}
+
+fn differs<T: PartialEq>(a: &Option<T>, b: &Option<T>) -> bool {
+    matches!((a, b), (Some(a), Some(b)) if a != b)
+}
+
+/// Fields of the de-facto standard textual receipt:
+/// `id:IIII sub:SSS dlvrd:DDD submit date:YYMMDDhhmm done date:YYMMDDhhmm stat:DDDDDDD err:E text:...`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TextReceipt {
+    pub id: Option<String>,
+    pub sub: Option<u32>,
+    pub dlvrd: Option<u32>,
+    /// Seconds since the Unix epoch, in the SMSC's own time zone.
+    pub submit_date: Option<i64>,
+    pub done_date: Option<i64>,
+    pub stat: Option<String>,
+    pub err: Option<String>,
+    pub text: Option<String>,
+}
+
+impl TextReceipt {
+    const KEYS: [&'static str; 7] = [
+        "id:",
+        "sub:",
+        "dlvrd:",
+        "submit date:",
+        "done date:",
+        "stat:",
+        "err:",
+    ];
+
+    /// Keys are matched case-insensitively at word boundaries, so values may
+    /// contain spaces. `text:` always runs to the end of the message. Returns
+    /// `None` unless at least `id:` or `stat:` is present.
+    pub fn parse(message: &str) -> Option<Self> {
+        // ASCII lowercasing keeps byte offsets valid for `message`.
+        let lower = message.to_ascii_lowercase();
+        let text_at = find_key(&lower, "text:");
+        let head = &lower[..text_at.unwrap_or(lower.len())];
+
+        let mut found: Vec<(usize, &str)> = Self::KEYS
+            .iter()
+            .filter_map(|key| find_key(head, key).map(|pos| (pos, *key)))
+            .collect();
+        found.sort_unstable();
+
+        let mut receipt = TextReceipt {
+            text: text_at.map(|pos| message[pos + "text:".len()..].trim().to_string()),
+            ..TextReceipt::default()
+        };
+        for (idx, (pos, key)) in found.iter().enumerate() {
+            let end = found.get(idx + 1).map_or(head.len(), |(next, _)| *next);
+            let value = message[pos + key.len()..end].trim();
+            if value.is_empty() {
+                continue;
+            }
+            match *key {
+                "id:" => receipt.id = Some(value.to_string()),
+                "sub:" => receipt.sub = value.parse().ok(),
+                "dlvrd:" => receipt.dlvrd = value.parse().ok(),
+                "submit date:" => receipt.submit_date = parse_smsc_date(value),
+                "done date:" => receipt.done_date = parse_smsc_date(value),
+                "stat:" => receipt.stat = Some(value.to_string()),
+                "err:" => receipt.err = Some(value.to_string()),
+                _ => {}
+            }
+        }
+
+        (receipt.id.is_some() || receipt.stat.is_some()).then_some(receipt)
+    }
+
+    pub fn state(&self) -> Option<MessageState> {
+        self.stat.as_deref().map(map_stat_to_message_state)
+    }
+
+    /// Checks the receipt against the body that was submitted. The `text:`
+    /// field is usually truncated to the first 20 characters, so it only has
+    /// to be a prefix.
+    fn validate(&self, expected_text: Option<&str>) -> Result<(), &'static str> {
+        if matches!((self.sub, self.dlvrd), (Some(sub), Some(dlvrd)) if dlvrd > sub) {
+            return Err("dlvrd exceeds sub");
+        }
+        if matches!((self.submit_date, self.done_date), (Some(submit), Some(done)) if done < submit)
+        {
+            return Err("done date precedes submit date");
+        }
+        if let (Some(text), Some(expected)) = (self.text.as_deref(), expected_text)
+            && !text.is_empty()
+            && !expected.starts_with(text)
+        {
+            return Err("text does not match the submitted body");
+        }
+        Ok(())
+    }
+}
+
+/// Finds `key` at the start of `haystack` or right after whitespace.
+fn find_key(haystack: &str, key: &str) -> Option<usize> {
+    haystack
+        .match_indices(key)
+        .map(|(pos, _)| pos)
+        .find(|&pos| pos == 0 || haystack.as_bytes()[pos - 1].is_ascii_whitespace())
+}
+
+/// Parses `YYMMDDhhmm` or `YYMMDDhhmmss` into seconds since the Unix epoch.
+fn parse_smsc_date(value: &str) -> Option<i64> {
+    if !matches!(value.len(), 10 | 12) || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let field = |at: usize| value[at..at + 2].parse::<i64>().ok();
+    let (year, month, day) = (2000 + field(0)?, field(2)?, field(4)?);
+    let (hour, minute) = (field(6)?, field(8)?);
+    let second = if value.len() == 12 { field(10)? } else { 0 };
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 59
+    {
+        return None;
+    }
+    Some(days_from_civil(year, month, day) * 86_400 + hour * 3_600 + minute * 60 + second)
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Correlates receipts with submitted messages and records them, checking
+/// each against the body of the message it was submitted as.
+pub struct DlrTracker {
+    store: Arc<CorrelationStore>,
+    metrics: Arc<Metrics>,
+    /// Per account and message profile; `None` when the body can't be
+    /// compared as text (UCS2).
+    expected_text: Vec<Vec<Option<String>>>,
+}
+
+impl DlrTracker {
+    pub fn new(
+        store: Arc<CorrelationStore>,
+        metrics: Arc<Metrics>,
+        accounts: &[AccountConfig],
+    ) -> Self {
+        let expected_text = accounts
+            .iter()
+            .map(|account| {
+                std::iter::once(&account.message)
+                    .chain(&account.profiles)
+                    .map(|message| {
+                        (message.data_coding != UCS2_DATA_CODING).then(|| message.body.clone())
+                    })
+                    .collect()
+            })
+            .collect();
+        Self {
+            store,
+            metrics,
+            expected_text,
+        }
+    }
+
+    /// Receipts that can't be matched to any message are only counted on the
+    /// bind they arrived on.
+    pub fn record_malformed(&self, receiving_bind: usize, reason: &str) {
+        tracing::debug!(bind = receiving_bind, reason, "Malformed DLR");
+        self.metrics.record_dlr_malformed(receiving_bind);
+    }
+
+    /// Matched receipts are credited to the bind that submitted the message.
+    /// Each message counts once, when it reaches a final state; intermediate
+    /// receipts are counted separately and anything after the final one is a
+    /// duplicate or an illegal transition.
+    pub fn record(&self, receiving_bind: usize, receipt: &Receipt) {
+        let metrics = &self.metrics;
+        if receipt.is_conflicting(self.store.normalizer()) {
+            tracing::debug!(
+                bind = receiving_bind,
+                ?receipt,
+                "DLR TLVs disagree with text"
+            );
+            metrics.record_dlr_conflict(receiving_bind);
+        }
+
+        let state = receipt.state.unwrap_or(MessageState::Unknown);
+        match self.store.resolve(&receipt.message_id, state) {
+            Lookup::Intermediate(pending) => {
+                metrics.record_dlr_intermediate(pending.phase, pending.bind, state)
+            }
+            Lookup::Final(pending) => {
+                tracing::debug!(bind = pending.bind, from = ?pending.last_state, to = ?state, "Final DLR");
+                let phase = pending.phase;
+                metrics.record_dlr(phase, pending.bind, pending.accepted_at.elapsed());
+                metrics.record_dlr_state(phase, pending.bind, state);
+                metrics.record_delivery_time(
+                    phase,
+                    state,
+                    pending.submitted_at.elapsed(),
+                    pending.accepted_at.elapsed(),
+                );
+                if let Some(delay) = receipt.smsc_delay() {
+                    metrics.record_dlr_smsc_delay(phase, pending.bind, delay);
+                }
+                if let Some(text) = &receipt.text {
+                    if let Some(err) = &text.err {
+                        metrics.record_dlr_error_code(phase, err);
+                    }
+                    let expected = self
+                        .expected_text
+                        .get(metrics.account_of(pending.bind))
+                        .and_then(|profiles| profiles.get(pending.profile))
+                        .and_then(Option::as_deref);
+                    if let Err(reason) = text.validate(expected) {
+                        tracing::debug!(bind = pending.bind, ?receipt, reason, "Invalid DLR");
+                        metrics.record_dlr_invalid(phase, pending.bind);
+                    }
+                }
+            }
+            Lookup::Duplicate(resolved) => {
+                metrics.record_dlr_duplicate(resolved.phase, resolved.bind)
+            }
+            Lookup::IllegalTransition(resolved) => {
+                tracing::debug!(
+                    bind = resolved.bind,
+                    id = %receipt.message_id,
+                    from = ?resolved.state,
+                    to = ?state,
+                    "Illegal DLR state transition"
+                );
+                metrics.record_dlr_illegal_transition(resolved.phase, resolved.bind);
+            }
+            Lookup::Orphan => {
+                tracing::debug!(bind = receiving_bind, id = %receipt.message_id, "Orphan DLR");
+                metrics.record_dlr_orphan(receiving_bind);
+            }
+        }
+    }
+}
+
+/// Final states end a message's receipt history; only ENROUTE and SCHEDULED
+/// may be followed by another state.
+pub fn is_final(state: MessageState) -> bool {
+    !matches!(state, MessageState::Enroute | MessageState::Scheduled)
+}
+
+fn map_stat_to_message_state(stat: &str) -> MessageState {
+    use rusmpp::values::MessageState as MS;
+    match stat.to_ascii_uppercase().as_str() {
+        "DELIVRD" | "DELIVERED" => MS::Delivered,
+        "ENROUTE" => MS::Enroute,
+        "EXPIRED" => MS::Expired,
+        "DELETED" => MS::Deleted,
+        "UNDELIV" | "UNDELIVERABLE" => MS::Undeliverable,
+        "ACCEPTD" | "ACCEPTED" => MS::Accepted,
+        "REJECTD" | "REJECTED" => MS::Rejected,
+        "UNKNOWN" => MS::Unknown,
+        _ => MS::Unknown,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rusmpp::{
+        pdus::builders::DeliverSmBuilder,
+        tlvs::MessageDeliveryRequestTlvValue,
+        types::{COctetString, OctetString},
+        values::EsmClass,
+    };
+
+    use super::*;
+    use crate::config::{DlrConfig, IdNormalization};
+
+    fn normalizer(id_match: &[IdNormalization]) -> IdNormalizer {
+        let config = DlrConfig {
+            id_match: id_match.to_vec(),
+            ..DlrConfig::default()
+        };
+        IdNormalizer::new(&config).unwrap()
+    }
+
+    fn receipt(tlv_id: &str, text_id: &str) -> Receipt {
+        Receipt {
+            message_id: tlv_id.to_string(),
+            state: None,
+            conflicting_state: false,
+            text: Some(TextReceipt {
+                id: Some(text_id.to_string()),
+                ..TextReceipt::default()
+            }),
+        }
+    }
+
+    #[test]
+    fn ids_are_compared_after_normalization() {
+        use IdNormalization::*;
+        let cases: &[(&[IdNormalization], &str, &str, bool)] = &[
+            (&[], "abc", "abc", false),
+            (&[], "ABC", "abc", true),
+            (&[CaseInsensitive], "ABC", "abc", false),
+            (&[], "000123", "123", true),
+            (&[StripLeadingZeros], "000123", "123", false),
+            (
+                &[StripLeadingZeros, CaseInsensitive],
+                "000ABC",
+                "abc",
+                false,
+            ),
+            (&[StripLeadingZeros, CaseInsensitive], "000ABC", "abd", true),
+            (&[HexToDecimal], "0031", "31", false),
+            (&[HexToDecimal], "31", "32", true),
+            (&[DecimalToHex], "1F", "1f", false),
+            (&[DecimalToHex], "1F", "20", true),
+        ];
+        for (id_match, tlv_id, text_id, conflicting) in cases {
+            assert_eq!(
+                receipt(tlv_id, text_id).is_conflicting(&normalizer(id_match)),
+                *conflicting,
+                "{id_match:?} {tlv_id} vs {text_id}"
+            );
+        }
+    }
+
+    fn deliver_sm(esm_class: u8, body: &str) -> DeliverSmBuilder {
+        DeliverSm::builder()
+            .esm_class(EsmClass::from(esm_class))
+            .short_message(OctetString::from_str(body).unwrap())
+    }
+
+    #[test]
+    fn deliver_sm_is_a_receipt_mo_or_malformed() {
+        let receipt_body = "id:abc sub:001 dlvrd:001 stat:DELIVRD err:000";
+        // Receipts are shown by their message ID.
+        let cases: &[(u8, &str, Option<&str>, &str)] = &[
+            (0x00, "hello", None, "MO"),
+            (0x00, receipt_body, None, "abc"),
+            (0x00, "hello", Some("tlv"), "tlv"),
+            (0x04, receipt_body, None, "abc"),
+            (0x04, "hello", Some("tlv"), "tlv"),
+            (0x04, "hello", None, "malformed"),
+            (0x20, "stat:ENROUTE", None, "malformed"),
+        ];
+        for (esm_class, body, tlv_id, expected) in cases {
+            let mut builder = deliver_sm(*esm_class, body);
+            if let Some(id) = tlv_id {
+                builder = builder.push_tlv(MessageDeliveryRequestTlvValue::ReceiptedMessageId(
+                    COctetString::from_str(id).unwrap(),
+                ));
+            }
+            let parsed = match Receipt::from_deliver_sm(&builder.build()) {
+                Some(Ok(receipt)) => receipt.message_id,
+                Some(Err(_)) => "malformed".to_string(),
+                None => "MO".to_string(),
+            };
+            assert_eq!(parsed, *expected, "{esm_class:#04x} {body:?} {tlv_id:?}");
+        }
+    }
+
+    #[test]
+    fn state_conflict_is_kept() {
+        let receipt = Receipt {
+            conflicting_state: true,
+            ..receipt("abc", "abc")
+        };
+        assert!(receipt.is_conflicting(&normalizer(&[])));
+    }
+
+    #[test]
+    fn parse_text_receipt() {
+        let full = TextReceipt::parse(
+            "id:12345 sub:001 dlvrd:001 submit date:2403151430 done date:240315143045 \
+             stat:DELIVRD err:000 text:Hello world",
+        );
+        assert_eq!(
+            full,
+            Some(TextReceipt {
+                id: Some("12345".to_string()),
+                sub: Some(1),
+                dlvrd: Some(1),
+                submit_date: Some(1_710_513_000),
+                done_date: Some(1_710_513_045),
+                stat: Some("DELIVRD".to_string()),
+                err: Some("000".to_string()),
+                text: Some("Hello world".to_string()),
+            })
+        );
+
+        let cases: &[(&str, Option<TextReceipt>)] = &[
+            // Keys in any case, with spaces around the values.
+            (
+                "ID: abc  Sub: 2 Dlvrd: 1  Stat: ENROUTE Text:  hi there ",
+                Some(TextReceipt {
+                    id: Some("abc".to_string()),
+                    sub: Some(2),
+                    dlvrd: Some(1),
+                    stat: Some("ENROUTE".to_string()),
+                    text: Some("hi there".to_string()),
+                    ..TextReceipt::default()
+                }),
+            ),
+            // Values run up to the next key, so they may contain spaces.
+            (
+                "id:abc def stat:DELIVRD",
+                Some(TextReceipt {
+                    id: Some("abc def".to_string()),
+                    stat: Some("DELIVRD".to_string()),
+                    ..TextReceipt::default()
+                }),
+            ),
+            // Missing and empty fields stay unset.
+            (
+                "id: stat:UNDELIV",
+                Some(TextReceipt {
+                    stat: Some("UNDELIV".to_string()),
+                    ..TextReceipt::default()
+                }),
+            ),
+            (
+                "id:abc sub:x submit date:24031514",
+                Some(TextReceipt {
+                    id: Some("abc".to_string()),
+                    ..TextReceipt::default()
+                }),
+            ),
+            // Keys must start a word, and nothing inside `text:` is a key.
+            ("xid:1 xstat:DELIVRD", None),
+            ("text: id:abc stat:DELIVRD", None),
+            ("hello world", None),
+            ("", None),
+        ];
+        for (message, expected) in cases {
+            assert_eq!(&TextReceipt::parse(message), expected, "{message:?}");
+        }
+    }
+
+    #[test]
+    fn parse_smsc_dates() {
+        let cases: &[(&str, Option<i64>)] = &[
+            // Two-digit years are read as 20YY.
+            ("7001010000", Some(3_155_760_000)),
+            ("0001010000", Some(946_684_800)),
+            ("2403151430", Some(1_710_513_000)),
+            ("240315143045", Some(1_710_513_045)),
+            ("240229235959", Some(1_709_251_199)),
+            // Neither 10 nor 12 digits.
+            ("24031514", None),
+            ("24031514300", None),
+            ("2403151430451", None),
+            ("24031514a0", None),
+            ("", None),
+            // Out-of-range fields.
+            ("2400151430", None),
+            ("2413151430", None),
+            ("2403001430", None),
+            ("2403321430", None),
+            ("2403152430", None),
+            ("2403151460", None),
+            ("240315143060", None),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(parse_smsc_date(value), *expected, "{value:?}");
+        }
+    }
+
+    #[test]
+    fn days_from_civil_dates() {
+        let cases = [
+            ((1970, 1, 1), 0),
+            ((1969, 12, 31), -1),
+            ((2000, 1, 1), 10_957),
+            ((2000, 2, 29), 11_016),
+            ((2000, 3, 1), 11_017),
+            ((2024, 2, 29), 19_782),
+            ((2099, 12, 31), 47_481),
+        ];
+        for ((year, month, day), expected) in cases {
+            assert_eq!(
+                days_from_civil(year, month, day),
+                expected,
+                "{year}-{month}-{day}"
+            );
+        }
+    }
+
+    #[test]
+    fn receipt_without_text_id_does_not_conflict() {
+        let receipt = Receipt {
+            text: None,
+            ..receipt("abc", "abc")
+        };
+        assert!(!receipt.is_conflicting(&normalizer(&[])));
+    }
+}
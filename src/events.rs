@@ -0,0 +1,207 @@
+use std::sync::Arc;
+
+use futures::StreamExt;
+use rusmpp::{Command, CommandId, CommandStatus, Pdu, pdus::DataSmResp};
+use rusmppc::{Client, Event};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    connection::EventStream,
+    deliver_resp::{Decision, DeliverResponder},
+    dlr::{DlrTracker, Receipt},
+    metrics::Metrics,
+    mo::MoTracker,
+    side_channel::SideChannel,
+};
+
+/// Why a session's event stream stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionEnd {
+    /// The session was cancelled locally (run stopped or submit loop done).
+    Finished,
+    /// The SMSC sent `unbind`; it has been answered.
+    ServerUnbind,
+    /// The connection dropped without an `unbind`.
+    Closed,
+}
+
+impl SessionEnd {
+    /// Shown as the bind's state after an unexpected end.
+    pub fn reason(self) -> &'static str {
+        match self {
+            SessionEnd::Finished => "finished",
+            SessionEnd::ServerUnbind => "unbind from SMSC",
+            SessionEnd::Closed => "connection lost",
+        }
+    }
+}
+
+/// Answers and accounts every PDU the SMSC sends on its own initiative.
+/// Shared by all binds so receipts land in one correlation store.
+pub struct Inbound {
+    dlr_tracker: Arc<DlrTracker>,
+    mo_tracker: Arc<MoTracker>,
+    responder: Arc<DeliverResponder>,
+    metrics: Arc<Metrics>,
+}
+
+impl Inbound {
+    pub fn new(
+        dlr_tracker: Arc<DlrTracker>,
+        mo_tracker: Arc<MoTracker>,
+        responder: Arc<DeliverResponder>,
+        metrics: Arc<Metrics>,
+    ) -> Self {
+        Self {
+            dlr_tracker,
+            mo_tracker,
+            responder,
+            metrics,
+        }
+    }
+
+    /// Reads `events` until `session` is cancelled or the SMSC ends the
+    /// session, which also cancels `session` so the bind's other tasks stop.
+    pub fn spawn(
+        self: Arc<Self>,
+        idx: usize,
+        client: Client,
+        side_channel: SideChannel,
+        mut events: EventStream,
+        session: CancellationToken,
+    ) -> JoinHandle<SessionEnd> {
+        tokio::spawn(async move {
+            let end = loop {
+                let event = tokio::select! {
+                    _ = session.cancelled() => break SessionEnd::Finished,
+                    event = events.next() => event,
+                };
+                match event {
+                    Some(Event::Incoming(command)) => {
+                        if let Some(end) = self.handle(idx, &client, &side_channel, &command).await
+                        {
+                            break end;
+                        }
+                    }
+                    Some(Event::Error(err)) => {
+                        tracing::warn!(bind = idx, ?err, "Background error");
+                    }
+                    None => {
+                        tracing::warn!(bind = idx, "Connection closed by SMSC");
+                        self.metrics.record_disconnect(idx);
+                        break SessionEnd::Closed;
+                    }
+                }
+            };
+            session.cancel();
+            end
+        })
+    }
+
+    async fn handle(
+        &self,
+        idx: usize,
+        client: &Client,
+        side_channel: &SideChannel,
+        command: &Command,
+    ) -> Option<SessionEnd> {
+        let sequence_number = command.sequence_number();
+        self.metrics.record_incoming(command_name(command.id()));
+        tracing::debug!(bind = idx, ?command, "Incoming command");
+
+        match command.id() {
+            CommandId::DeliverSm => {
+                let deliver = match command.pdu() {
+                    Some(Pdu::DeliverSm(deliver)) => Some(deliver),
+                    _ => None,
+                };
+                let decision = match deliver {
+                    Some(deliver) => self.responder.decide(deliver),
+                    None => Decision::default(),
+                };
+                self.responder
+                    .reply(client, sequence_number, decision)
+                    .await;
+
+                if let Some(deliver) = deliver
+                    && decision.accepted()
+                {
+                    match Receipt::from_deliver_sm(deliver) {
+                        Some(Ok(receipt)) => self.dlr_tracker.record(idx, &receipt),
+                        Some(Err(reason)) => self.dlr_tracker.record_malformed(idx, reason),
+                        None => self.mo_tracker.record(idx, deliver),
+                    }
+                }
+            }
+            CommandId::DataSm => {
+                // Acknowledged so the SMSC doesn't retry; the payload is not
+                // interpreted.
+                if let Err(err) = side_channel.respond(sequence_number, DataSmResp::default()) {
+                    tracing::warn!(bind = idx, ?err, "Failed to send data_sm_resp");
+                }
+            }
+            CommandId::EnquireLink => {
+                if let Err(err) = client.enquire_link_resp(sequence_number).await {
+                    tracing::warn!(bind = idx, ?err, "Failed to send enquire_link_resp");
+                }
+            }
+            CommandId::Unbind => {
+                tracing::warn!(bind = idx, "SMSC requested unbind");
+                if let Err(err) = client.unbind_resp(sequence_number).await {
+                    tracing::warn!(bind = idx, ?err, "Failed to send unbind_resp");
+                }
+                self.metrics.record_server_unbind(idx);
+                return Some(SessionEnd::ServerUnbind);
+            }
+            CommandId::GenericNack => {
+                tracing::warn!(
+                    bind = idx,
+                    sequence_number,
+                    status = ?command.status(),
+                    "SMSC rejected a PDU with generic_nack"
+                );
+            }
+            // alert_notification has no response; counting it is all we do.
+            CommandId::AlertNotification => {}
+            other => {
+                tracing::debug!(bind = idx, command_id = ?other, "Unhandled incoming command");
+            }
+        }
+        None
+    }
+}
+
+/// `DeliverSm` -> `deliver_sm`, matching the names used in the SMPP spec.
+fn command_name(id: CommandId) -> String {
+    if let CommandId::Other(raw) = id {
+        return format!("0x{raw:08x}");
+    }
+    let mut name = String::new();
+    for (i, c) in format!("{id:?}").chars().enumerate() {
+        if c.is_ascii_uppercase() {
+            if i > 0 {
+                name.push('_');
+            }
+            name.push(c.to_ascii_lowercase());
+        } else {
+            name.push(c);
+        }
+    }
+    name
+}
+
+/// `EsmeRbindfail` -> `ESME_RBINDFAIL`, matching the names used in the SMPP spec.
+pub fn status_name(status: CommandStatus) -> String {
+    if let CommandStatus::Other(raw) = status {
+        return format!("0x{raw:08x}");
+    }
+    let mut name = String::new();
+    for (i, c) in format!("{status:?}").chars().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            name.push('_');
+        }
+        name.push(c.to_ascii_uppercase());
+    }
+    name
+}
@@ -0,0 +1,243 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Each power of two is split into this many linear sub-buckets, so recorded
+/// values keep roughly 1/16 (~6%) relative precision.
+const SUB_BUCKET_BITS: u32 = 4;
+const SUB_BUCKETS: usize = 1 << SUB_BUCKET_BITS;
+/// Values up to 2^44 µs (about 200 days) get their own bucket; larger ones
+/// land in the last.
+const MAX_SHIFT: usize = 40;
+const BUCKETS: usize = (MAX_SHIFT + 2) * SUB_BUCKETS;
+
+/// Lock-free log-linear histogram of durations, recorded in microseconds.
+#[derive(Debug)]
+pub struct Histogram {
+    buckets: Box<[AtomicU64]>,
+    sum_micros: AtomicU64,
+    max_micros: AtomicU64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            buckets: (0..BUCKETS).map(|_| AtomicU64::new(0)).collect(),
+            sum_micros: AtomicU64::new(0),
+            max_micros: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Histogram {
+    pub fn record(&self, value: Duration) {
+        let micros = u64::try_from(value.as_micros()).unwrap_or(u64::MAX);
+        self.buckets[bucket_index(micros)].fetch_add(1, Ordering::Relaxed);
+        self.sum_micros.fetch_add(micros, Ordering::Relaxed);
+        self.max_micros.fetch_max(micros, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> HistogramSnapshot {
+        let counts = self.counts();
+        summarize(
+            &counts,
+            self.sum_micros.load(Ordering::Relaxed),
+            self.max_micros.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Marks the current contents so `snapshot_since` can describe only what
+    /// is recorded afterwards.
+    pub fn checkpoint(&self) -> HistogramCheckpoint {
+        HistogramCheckpoint {
+            counts: self.counts(),
+            sum_micros: self.sum_micros.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Distribution of the values recorded after `checkpoint`. The maximum is
+    /// the upper bound of the highest bucket, as the exact one isn't kept.
+    pub fn snapshot_since(&self, checkpoint: &HistogramCheckpoint) -> HistogramSnapshot {
+        let counts: Vec<u64> = self
+            .counts()
+            .iter()
+            .zip(&checkpoint.counts)
+            .map(|(now, then)| now.saturating_sub(*then))
+            .collect();
+        let max_micros = counts
+            .iter()
+            .rposition(|count| *count > 0)
+            .map_or(0, bucket_upper_bound);
+        let sum_micros = self
+            .sum_micros
+            .load(Ordering::Relaxed)
+            .saturating_sub(checkpoint.sum_micros);
+        summarize(&counts, sum_micros, max_micros)
+    }
+
+    fn counts(&self) -> Vec<u64> {
+        self.buckets
+            .iter()
+            .map(|bucket| bucket.load(Ordering::Relaxed))
+            .collect()
+    }
+}
+
+fn summarize(counts: &[u64], sum_micros: u64, max_micros: u64) -> HistogramSnapshot {
+    // Buckets are read one by one while others keep recording, so derive
+    // the total from what was actually read.
+    let count: u64 = counts.iter().sum();
+    if count == 0 {
+        return HistogramSnapshot::default();
+    }
+    let percentile = |quantile: f64| {
+        let rank = ((count as f64 * quantile).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (idx, bucket) in counts.iter().enumerate() {
+            seen += bucket;
+            if seen >= rank {
+                return micros_to_ms(bucket_upper_bound(idx).min(max_micros));
+            }
+        }
+        micros_to_ms(max_micros)
+    };
+
+    HistogramSnapshot {
+        count,
+        mean_ms: micros_to_ms(sum_micros) / count as f64,
+        p50_ms: percentile(0.50),
+        p95_ms: percentile(0.95),
+        p99_ms: percentile(0.99),
+        max_ms: micros_to_ms(max_micros),
+        buckets: counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(idx, count)| (idx, *count))
+            .collect(),
+        sum_micros,
+        max_micros,
+    }
+}
+
+fn bucket_index(micros: u64) -> usize {
+    if micros < SUB_BUCKETS as u64 {
+        return micros as usize;
+    }
+    let shift = (63 - micros.leading_zeros() - SUB_BUCKET_BITS) as usize;
+    let sub = (micros >> shift) as usize & (SUB_BUCKETS - 1);
+    ((shift + 1) * SUB_BUCKETS + sub).min(BUCKETS - 1)
+}
+
+/// Largest value that maps to bucket `idx`.
+fn bucket_upper_bound(idx: usize) -> u64 {
+    if idx < SUB_BUCKETS {
+        return idx as u64;
+    }
+    let shift = idx / SUB_BUCKETS - 1;
+    let sub = (idx % SUB_BUCKETS) as u64;
+    ((SUB_BUCKETS as u64 + sub + 1) << shift) - 1
+}
+
+fn micros_to_ms(micros: u64) -> f64 {
+    micros as f64 / 1000.0
+}
+
+#[derive(Debug, Clone)]
+pub struct HistogramCheckpoint {
+    counts: Vec<u64>,
+    sum_micros: u64,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct HistogramSnapshot {
+    pub count: u64,
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+    /// Non-empty buckets as `(index, count)`, so snapshots from separate
+    /// histograms can be merged exactly.
+    #[serde(default)]
+    buckets: Vec<(usize, u64)>,
+    #[serde(default)]
+    sum_micros: u64,
+    #[serde(default)]
+    max_micros: u64,
+}
+
+impl HistogramSnapshot {
+    /// Combines snapshots of separate histograms, e.g. from several agents,
+    /// as if every value had been recorded into one.
+    pub fn merge<'a>(snapshots: impl IntoIterator<Item = &'a HistogramSnapshot>) -> Self {
+        let mut counts = vec![0; BUCKETS];
+        let (mut sum_micros, mut max_micros) = (0u64, 0);
+        for snapshot in snapshots {
+            for &(idx, count) in &snapshot.buckets {
+                counts[idx.min(BUCKETS - 1)] += count;
+            }
+            sum_micros = sum_micros.saturating_add(snapshot.sum_micros);
+            max_micros = max_micros.max(snapshot.max_micros);
+        }
+        summarize(&counts, sum_micros, max_micros)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn histogram(values_ms: impl IntoIterator<Item = u64>) -> Histogram {
+        let histogram = Histogram::default();
+        for value in values_ms {
+            histogram.record(Duration::from_millis(value));
+        }
+        histogram
+    }
+
+    #[test]
+    fn merge_matches_a_single_histogram() {
+        // One agent with many fast messages, another with a few slow ones:
+        // averaging their percentiles would put p50 far from either.
+        let fast = histogram((0..990).map(|i| 10 + i % 5));
+        let slow = histogram((0..10).map(|i| 1000 + i));
+        let combined = histogram(
+            (0..990)
+                .map(|i| 10 + i % 5)
+                .chain((0..10).map(|i| 1000 + i)),
+        );
+
+        let merged = HistogramSnapshot::merge([&fast.snapshot(), &slow.snapshot()]);
+        let expected = combined.snapshot();
+        assert_eq!(merged.count, 1000);
+        assert_eq!(merged.p50_ms, expected.p50_ms);
+        assert_eq!(merged.p95_ms, expected.p95_ms);
+        assert_eq!(merged.p99_ms, expected.p99_ms);
+        assert_eq!(merged.max_ms, expected.max_ms);
+        assert_eq!(merged.mean_ms, expected.mean_ms);
+        assert!(merged.p50_ms < 20.0, "{merged:?}");
+    }
+
+    #[test]
+    fn merge_survives_the_protocol() {
+        let snapshot = histogram([5, 50, 500]).snapshot();
+        let json = serde_json::to_string(&snapshot).unwrap();
+        let decoded: HistogramSnapshot = serde_json::from_str(&json).unwrap();
+
+        let merged = HistogramSnapshot::merge([&decoded, &HistogramSnapshot::default()]);
+        assert_eq!(merged.count, 3);
+        assert_eq!(merged.p99_ms, snapshot.p99_ms);
+        assert_eq!(merged.max_ms, 500.0);
+    }
+
+    #[test]
+    fn merge_of_nothing_is_empty() {
+        let merged = HistogramSnapshot::merge([]);
+        assert_eq!(merged.count, 0);
+        assert_eq!(merged.p99_ms, 0.0);
+    }
+}
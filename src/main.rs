@@ -1,26 +1,71 @@
+mod agent;
+mod api;
 mod bind_tracker;
+mod churn;
 mod config;
+mod connection;
+mod control;
+mod coordinator;
+mod correlation;
+mod deliver_resp;
+mod dlr;
+mod events;
+mod histogram;
 mod metrics;
+mod mo;
+mod operations;
+mod outbind;
 mod progress;
+mod protocol;
+mod rate_limiter;
+mod report;
+mod run;
+mod scenario;
+mod search;
+mod side_channel;
+mod timed_index;
+mod tls;
 mod worker;
 
-use std::{path::PathBuf, sync::Arc, sync::atomic::AtomicU64};
+use std::{path::PathBuf, sync::Arc};
 
 use anyhow::Result;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use tokio_util::sync::CancellationToken;
 
-use crate::{
-    bind_tracker::BindTracker, config::Config, metrics::Metrics, progress::spawn_progress_task,
-    worker::spawn_bind,
-};
+use crate::{config::Config, run::Run};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about = "SMPP load generator", long_about = None)]
 struct Cli {
     /// Path to the TOML configuration file
-    #[arg(short, long, default_value = "config.toml")]
+    #[arg(short, long, default_value = "config.toml", global = true)]
     config: PathBuf,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Split the configured load across agents and combine their results
+    Coordinator {
+        /// Agent address (host:port); repeat for every agent
+        #[arg(long = "agent", required = true)]
+        agents: Vec<String>,
+        /// Shared secret the agents were started with
+        #[arg(long, env = "SMPP_PERF_TOKEN", hide_env_values = true)]
+        token: String,
+    },
+    /// Wait for a coordinator and run the share of the load it assigns
+    Agent {
+        /// Address to accept the coordinator on
+        #[arg(long, default_value = "127.0.0.1:7700")]
+        listen: String,
+        /// Shared secret a coordinator must present
+        #[arg(long, env = "SMPP_PERF_TOKEN", hide_env_values = true)]
+        token: String,
+    },
 }
 
 #[tokio::main]
@@ -30,55 +75,20 @@ async fn main() -> Result<()> {
         .init();
 
     let cli = Cli::parse();
-    let config = Arc::new(Config::from_file(&cli.config)?);
-
-    if config.load.binds == 0 {
-        tracing::warn!("Configuration requested 0 binds. No traffic will be generated.");
-    }
-
-    let metrics = Arc::new(Metrics::new(config.load.binds));
-    let tracker = Arc::new(BindTracker::new(config.load.binds));
-    let shutdown = CancellationToken::new();
-    let messages_sent = Arc::new(AtomicU64::new(0));
-    let messages_limit = config.load.messages_count;
-
-    let progress_handle = spawn_progress_task(
-        metrics.clone(),
-        tracker.clone(),
-        Arc::new(config.smpp.clone()),
-        Arc::new(config.message.clone()),
-        shutdown.clone(),
-    );
-
-    let mut tasks = Vec::new();
-    for idx in 0..config.load.binds {
-        let task = tokio::spawn(spawn_bind(
-            idx,
-            config.clone(),
-            metrics.clone(),
-            tracker.clone(),
-            shutdown.clone(),
-            messages_sent.clone(),
-            messages_limit,
-        ));
-        tasks.push(task);
-    }
-
-    tokio::select! {
-        _ = tokio::signal::ctrl_c() => {
-            println!("\nCtrl+C received. Stopping load test...");
-            shutdown.cancel();
+    match cli.command {
+        None => {
+            let config = Arc::new(Config::from_file(&cli.config)?);
+            if let Some(churn) = &config.churn {
+                return churn::run(&config, churn).await;
+            }
+            Run::new(config)
+                .execute(CancellationToken::new(), true)
+                .await?;
+            Ok(())
         }
-        _ = shutdown.cancelled() => {}
-    }
-
-    for task in tasks {
-        let _ = task.await;
+        Some(Command::Coordinator { agents, token }) => {
+            coordinator::run(&cli.config, agents, token).await
+        }
+        Some(Command::Agent { listen, token }) => agent::serve(&listen, &token).await,
     }
-
-    shutdown.cancel();
-    let _ = progress_handle.await;
-
-    println!("Load test stopped.");
-    Ok(())
 }
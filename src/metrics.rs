@@ -1,70 +1,579 @@
 use std::{
-    sync::atomic::{AtomicU64, Ordering},
+    collections::BTreeMap,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
     time::Duration,
 };
 
 use rusmpp::values::MessageState;
+use serde::{Deserialize, Serialize};
 
+use crate::{
+    config::AccountConfig,
+    deliver_resp::Reply,
+    histogram::{Histogram, HistogramCheckpoint, HistogramSnapshot},
+    operations::Operation,
+};
+
+/// Which statistics a record counts toward. Receipts, expiries and
+/// evictions belong to the phase their message was submitted in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Warmup,
+    Measured,
+}
+
+/// Run metrics, recorded into the warm-up bucket until `end_warmup` and
+/// into the measurement bucket afterwards. Receipts follow their message.
 #[derive(Debug)]
 pub struct Metrics {
+    account_names: Vec<String>,
+    dlr_pending: AtomicU64,
+    dlr_pending_peak: AtomicU64,
+    warmup: MetricsBucket,
+    measured: MetricsBucket,
+    warming_up: AtomicBool,
+}
+
+/// Everything recorded during one part of the run.
+#[derive(Debug)]
+struct MetricsBucket {
     total_attempts: AtomicU64,
     total_success: AtomicU64,
     total_error: AtomicU64,
     total_latency_micros: AtomicU64,
+    operations: [OperationMetrics; Operation::ALL.len()],
+    /// `err:` values of matched receipts and how often each was seen.
+    dlr_error_codes: Mutex<BTreeMap<String, u64>>,
+    delays: DelayHistograms,
+    lifecycle: LifecycleHistograms,
+    /// `data_coding` of received MO messages and how often each was seen.
+    mo_data_coding: Mutex<BTreeMap<u8, u64>>,
+    mo_max_bytes: AtomicU64,
+    deliver_resp: DeliverRespMetrics,
+    open_loop: OpenLoopMetrics,
+    /// Every PDU the SMSC initiated, by command name.
+    incoming: Mutex<BTreeMap<String, u64>>,
+    outbind_sessions: AtomicU64,
+    outbind_rejected: AtomicU64,
     per_bind: Vec<BindMetrics>,
 }
 
-impl Metrics {
-    pub fn new(bind_count: usize) -> Self {
-        let per_bind = (0..bind_count).map(|_| BindMetrics::default()).collect();
+/// How `deliver_sm` were answered and how the SMSC retried the rejected ones.
+#[derive(Debug, Default)]
+struct DeliverRespMetrics {
+    accepted: AtomicU64,
+    rejected: AtomicU64,
+    dropped: AtomicU64,
+    /// Every redelivery of a rejected or dropped `deliver_sm`.
+    redeliveries: AtomicU64,
+    /// Distinct `deliver_sm` redelivered at least once.
+    redelivered_messages: AtomicU64,
+    /// Redelivered `deliver_sm` that were finally accepted.
+    recovered: AtomicU64,
+    /// Time between an unsuccessful response and the next attempt.
+    retry_interval: Histogram,
+}
 
+/// Open-loop generator: how often the schedule outran the in-flight window.
+#[derive(Debug, Default)]
+struct OpenLoopMetrics {
+    scheduled: AtomicU64,
+    /// Sends that had to wait for a free in-flight slot.
+    delayed: AtomicU64,
+    /// Scheduled sends discarded because the backlog was full.
+    dropped: AtomicU64,
+    backlog_peak: AtomicU64,
+    /// Intended send time to actual send.
+    send_lag: Histogram,
+}
+
+/// Where the time between `submit_sm` and the final receipt goes.
+#[derive(Debug, Default)]
+struct DelayHistograms {
+    /// ESME_ROK responses only; errors such as throttling answer faster or
+    /// time out and would skew it.
+    submit_to_resp: Histogram,
+    resp_to_delivered: Histogram,
+    resp_to_undelivered: Histogram,
+    submit_to_delivered: Histogram,
+    submit_to_undelivered: Histogram,
+}
+
+/// How long each step of a bind's session took.
+#[derive(Debug, Default)]
+struct LifecycleHistograms {
+    connect: Histogram,
+    bind: Histogram,
+    bound: Histogram,
+    unbind: Histogram,
+}
+
+impl Metrics {
+    /// Allocates bind slots account by account, matching the global bind
+    /// indices assigned in `run.rs`, followed by `extra_binds` slots that
+    /// belong to no account (the outbind listener). Without a `warmup`
+    /// everything is measured from the start.
+    pub fn new(accounts: &[AccountConfig], extra_binds: usize, warmup: bool) -> Self {
         Self {
-            total_attempts: AtomicU64::new(0),
-            total_success: AtomicU64::new(0),
-            total_error: AtomicU64::new(0),
-            total_latency_micros: AtomicU64::new(0),
-            per_bind,
+            account_names: accounts
+                .iter()
+                .map(|account| account.name.clone())
+                .collect(),
+            dlr_pending: AtomicU64::new(0),
+            dlr_pending_peak: AtomicU64::new(0),
+            warmup: MetricsBucket::new(accounts, extra_binds),
+            measured: MetricsBucket::new(accounts, extra_binds),
+            warming_up: AtomicBool::new(warmup),
+        }
+    }
+
+    /// Sends everything recorded from now on to the measurement bucket.
+    pub fn end_warmup(&self) {
+        self.warming_up.store(false, Ordering::Relaxed);
+    }
+
+    pub fn warming_up(&self) -> bool {
+        self.warming_up.load(Ordering::Relaxed)
+    }
+
+    /// The phase anything recorded now belongs to.
+    pub fn phase(&self) -> Phase {
+        if self.warming_up() {
+            Phase::Warmup
+        } else {
+            Phase::Measured
+        }
+    }
+
+    fn bucket(&self) -> &MetricsBucket {
+        self.bucket_of(self.phase())
+    }
+
+    fn bucket_of(&self, phase: Phase) -> &MetricsBucket {
+        match phase {
+            Phase::Warmup => &self.warmup,
+            Phase::Measured => &self.measured,
         }
     }
 
     pub fn record_success(&self, bind_idx: usize, latency: Duration) {
-        self.total_attempts.fetch_add(1, Ordering::Relaxed);
-        self.total_success.fetch_add(1, Ordering::Relaxed);
-        self.add_latency(latency);
+        let bucket = self.bucket();
+        bucket.total_attempts.fetch_add(1, Ordering::Relaxed);
+        bucket.total_success.fetch_add(1, Ordering::Relaxed);
+        bucket.add_latency(latency);
+        bucket.delays.submit_to_resp.record(latency);
 
-        if let Some(bind) = self.per_bind.get(bind_idx) {
+        if let Some(bind) = bucket.per_bind.get(bind_idx) {
             bind.record_success(latency);
         }
     }
 
     pub fn record_error(&self, bind_idx: usize, latency: Duration) {
-        self.total_attempts.fetch_add(1, Ordering::Relaxed);
-        self.total_error.fetch_add(1, Ordering::Relaxed);
-        self.add_latency(latency);
+        let bucket = self.bucket();
+        bucket.total_attempts.fetch_add(1, Ordering::Relaxed);
+        bucket.total_error.fetch_add(1, Ordering::Relaxed);
+        bucket.add_latency(latency);
 
-        if let Some(bind) = self.per_bind.get(bind_idx) {
+        if let Some(bind) = bucket.per_bind.get(bind_idx) {
             bind.record_error(latency);
         }
     }
 
-    pub fn record_dlr(&self, bind_idx: usize, delay: Duration) {
-        if let Some(bind) = self.per_bind.get(bind_idx) {
+    /// Records the outcome of any SMPP request, including `submit_sm`, under
+    /// its own command type.
+    pub fn record_operation(&self, operation: Operation, ok: bool, latency: Duration) {
+        let bucket = self.bucket();
+        bucket.operations[operation.index()].record(ok, latency);
+    }
+
+    /// `delay` runs from the `submit_sm_resp` to the receipt.
+    pub fn record_dlr(&self, phase: Phase, bind_idx: usize, delay: Duration) {
+        let bucket = self.bucket_of(phase);
+        if let Some(bind) = bucket.per_bind.get(bind_idx) {
             bind.record_dlr(delay);
         }
     }
 
-    pub fn record_dlr_status(&self, bind_idx: usize, delivered: bool, failed: bool) {
-        if let Some(bind) = self.per_bind.get(bind_idx) {
-            bind.record_dlr_status(delivered, failed);
+    pub fn record_dlr_state(&self, phase: Phase, bind_idx: usize, state: MessageState) {
+        let bucket = self.bucket_of(phase);
+        if let Some(bind) = bucket.per_bind.get(bind_idx) {
+            bind.record_dlr_state(state);
+        }
+    }
+
+    /// Time from `submit_sm` and from its `submit_sm_resp` to a final
+    /// state; anything else is ignored.
+    pub fn record_delivery_time(
+        &self,
+        phase: Phase,
+        state: MessageState,
+        since_submit: Duration,
+        since_resp: Duration,
+    ) {
+        let delays = &self.bucket_of(phase).delays;
+        match state {
+            MessageState::Delivered => {
+                delays.submit_to_delivered.record(since_submit);
+                delays.resp_to_delivered.record(since_resp);
+            }
+            MessageState::Undeliverable
+            | MessageState::Expired
+            | MessageState::Rejected
+            | MessageState::Deleted => {
+                delays.submit_to_undelivered.record(since_submit);
+                delays.resp_to_undelivered.record(since_resp);
+            }
+            _ => {}
+        }
+    }
+
+    /// Submit-to-done time reported by the SMSC in the receipt dates.
+    pub fn record_dlr_smsc_delay(&self, phase: Phase, bind_idx: usize, delay: Duration) {
+        let bucket = self.bucket_of(phase);
+        if let Some(bind) = bucket.per_bind.get(bind_idx) {
+            bind.dlr_smsc_delay_samples.fetch_add(1, Ordering::Relaxed);
+            let capped = u64::try_from(delay.as_micros()).unwrap_or(u64::MAX);
+            bind.dlr_smsc_delay_micros.fetch_add(capped, Ordering::Relaxed);
+        }
+    }
+
+    pub fn record_dlr_error_code(&self, phase: Phase, code: &str) {
+        let bucket = self.bucket_of(phase);
+        let mut codes = bucket
+            .dlr_error_codes
+            .lock()
+            .expect("DLR error code lock poisoned");
+        match codes.get_mut(code) {
+            Some(count) => *count += 1,
+            None => {
+                codes.insert(code.to_string(), 1);
+            }
+        }
+    }
+
+    /// A receipt that failed validation against the submitted message.
+    pub fn record_dlr_invalid(&self, phase: Phase, bind_idx: usize) {
+        let bucket = self.bucket_of(phase);
+        if let Some(bind) = bucket.per_bind.get(bind_idx) {
+            bind.dlr_invalid.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// A mobile-originated `deliver_sm`. `matched` is `None` when no
+    /// `mo.expected_pattern` is configured.
+    pub fn record_mo(&self, bind_idx: usize, bytes: usize, data_coding: u8, matched: Option<bool>) {
+        let bucket = self.bucket();
+        let bytes = bytes as u64;
+        bucket.mo_max_bytes.fetch_max(bytes, Ordering::Relaxed);
+        *bucket
+            .mo_data_coding
+            .lock()
+            .expect("MO data_coding lock poisoned")
+            .entry(data_coding)
+            .or_default() += 1;
+        if let Some(bind) = bucket.per_bind.get(bind_idx) {
+            bind.mo_received.fetch_add(1, Ordering::Relaxed);
+            bind.mo_bytes.fetch_add(bytes, Ordering::Relaxed);
+            if matched == Some(false) {
+                bind.mo_mismatch.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub fn record_deliver_reply(&self, reply: Reply) {
+        let bucket = self.bucket();
+        let counter = match reply {
+            Reply::Accept => &bucket.deliver_resp.accepted,
+            Reply::Reject(_) => &bucket.deliver_resp.rejected,
+            Reply::Drop => &bucket.deliver_resp.dropped,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A `deliver_sm` came back after `previous_attempts` unsuccessful ones.
+    pub fn record_redelivery(&self, previous_attempts: u32, interval: Duration) {
+        let bucket = self.bucket();
+        let deliver_resp = &bucket.deliver_resp;
+        deliver_resp.redeliveries.fetch_add(1, Ordering::Relaxed);
+        if previous_attempts == 1 {
+            deliver_resp.redelivered_messages.fetch_add(1, Ordering::Relaxed);
+        }
+        deliver_resp.retry_interval.record(interval);
+    }
+
+    pub fn record_redelivery_recovered(&self) {
+        let bucket = self.bucket();
+        bucket
+            .deliver_resp
+            .recovered
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A send released by the open-loop schedule; `delayed` when the
+    /// in-flight window was full at its intended time.
+    pub fn record_open_loop_scheduled(&self, delayed: bool, backlog: usize) {
+        let bucket = self.bucket();
+        bucket.open_loop.scheduled.fetch_add(1, Ordering::Relaxed);
+        if delayed {
+            bucket.open_loop.delayed.fetch_add(1, Ordering::Relaxed);
+        }
+        bucket
+            .open_loop
+            .backlog_peak
+            .fetch_max(backlog as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_open_loop_dropped(&self) {
+        let bucket = self.bucket();
+        bucket.open_loop.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_open_loop_send(&self, lag: Duration) {
+        let bucket = self.bucket();
+        bucket.open_loop.send_lag.record(lag);
+    }
+
+    pub fn record_incoming(&self, command: String) {
+        *self
+            .bucket()
+            .incoming
+            .lock()
+            .expect("incoming command lock poisoned")
+            .entry(command)
+            .or_default() += 1;
+    }
+
+    /// An SMSC connection on the outbind listener; `accepted` is false when
+    /// its `outbind` or our `bind_receiver` failed.
+    pub fn record_outbind(&self, accepted: bool) {
+        let bucket = self.bucket();
+        let counter = if accepted {
+            &bucket.outbind_sessions
+        } else {
+            &bucket.outbind_rejected
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_server_unbind(&self, bind_idx: usize) {
+        let bucket = self.bucket();
+        if let Some(bind) = bucket.per_bind.get(bind_idx) {
+            bind.server_unbinds.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// The connection dropped without an `unbind` from either side.
+    pub fn record_disconnect(&self, bind_idx: usize) {
+        let bucket = self.bucket();
+        if let Some(bind) = bucket.per_bind.get(bind_idx) {
+            bind.disconnects.fetch_add(1, Ordering::Relaxed);
         }
     }
 
-    pub fn record_dlr_state(&self, bind_idx: usize, state: MessageState) {
-        if let Some(bind) = self.per_bind.get(bind_idx) {
+    pub fn record_reconnect(&self, bind_idx: usize) {
+        let bucket = self.bucket();
+        if let Some(bind) = bucket.per_bind.get(bind_idx) {
+            bind.reconnects.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// TCP (and TLS) connect time of a bind's session.
+    pub fn record_connect(&self, latency: Duration) {
+        self.bucket().lifecycle.connect.record(latency);
+    }
+
+    /// Bind request to its `bind_resp`.
+    pub fn record_bind(&self, latency: Duration) {
+        self.bucket().lifecycle.bind.record(latency);
+    }
+
+    /// How long a session stayed bound, recorded when it ends.
+    pub fn record_bound(&self, duration: Duration) {
+        self.bucket().lifecycle.bound.record(duration);
+    }
+
+    /// Our `unbind` to its `unbind_resp`.
+    pub fn record_unbind(&self, latency: Duration) {
+        self.bucket().lifecycle.unbind.record(latency);
+    }
+
+    /// Account owning the given bind.
+    pub fn account_of(&self, bind_idx: usize) -> usize {
+        self.measured
+            .per_bind
+            .get(bind_idx)
+            .map_or(0, |bind| bind.account)
+    }
+
+    /// An ENROUTE or SCHEDULED receipt; the message is still awaiting its final state.
+    pub fn record_dlr_intermediate(&self, phase: Phase, bind_idx: usize, state: MessageState) {
+        let bucket = self.bucket_of(phase);
+        if let Some(bind) = bucket.per_bind.get(bind_idx) {
+            bind.dlr_intermediate.fetch_add(1, Ordering::Relaxed);
             bind.record_dlr_state(state);
         }
     }
 
+    /// A receipt after the final one reporting a different state.
+    pub fn record_dlr_illegal_transition(&self, phase: Phase, bind_idx: usize) {
+        let bucket = self.bucket_of(phase);
+        if let Some(bind) = bucket.per_bind.get(bind_idx) {
+            bind.dlr_illegal_transition.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// A receipt whose message ID was never submitted in this run.
+    pub fn record_dlr_orphan(&self, bind_idx: usize) {
+        let bucket = self.bucket();
+        if let Some(bind) = bucket.per_bind.get(bind_idx) {
+            bind.dlr_orphan.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// A `deliver_sm` flagged as a receipt that carried no message ID.
+    pub fn record_dlr_malformed(&self, bind_idx: usize) {
+        let bucket = self.bucket();
+        if let Some(bind) = bucket.per_bind.get(bind_idx) {
+            bind.dlr_malformed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// A further receipt for a message whose receipt was already counted.
+    pub fn record_dlr_duplicate(&self, phase: Phase, bind_idx: usize) {
+        let bucket = self.bucket_of(phase);
+        if let Some(bind) = bucket.per_bind.get(bind_idx) {
+            bind.dlr_duplicate.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// A receipt whose TLVs and text carried different IDs or states.
+    pub fn record_dlr_conflict(&self, bind_idx: usize) {
+        let bucket = self.bucket();
+        if let Some(bind) = bucket.per_bind.get(bind_idx) {
+            bind.dlr_conflict.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// A receipt never arrived within `dlr.timeout_secs`.
+    pub fn record_dlr_expired(&self, phase: Phase, bind_idx: usize) {
+        let bucket = self.bucket_of(phase);
+        if let Some(bind) = bucket.per_bind.get(bind_idx) {
+            bind.dlr_missing.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// A pending message was dropped because the correlation store was full.
+    pub fn record_dlr_evicted(&self, phase: Phase, bind_idx: usize) {
+        let bucket = self.bucket_of(phase);
+        if let Some(bind) = bucket.per_bind.get(bind_idx) {
+            bind.dlr_evicted.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn observe_dlr_pending(&self, pending: usize) {
+        let pending = pending as u64;
+        self.dlr_pending.store(pending, Ordering::Relaxed);
+        self.dlr_pending_peak.fetch_max(pending, Ordering::Relaxed);
+    }
+
+    /// Messages still waiting for their delivery receipt.
+    pub fn dlr_pending(&self) -> u64 {
+        self.dlr_pending.load(Ordering::Relaxed)
+    }
+
+    pub fn record_enquire_link(&self, bind_idx: usize, rtt: Duration) {
+        let bucket = self.bucket();
+        if let Some(bind) = bucket.per_bind.get(bind_idx) {
+            bind.record_enquire_link(rtt);
+        }
+    }
+
+    pub fn record_enquire_link_timeout(&self, bind_idx: usize) {
+        let bucket = self.bucket();
+        if let Some(bind) = bucket.per_bind.get(bind_idx) {
+            bind.enquire_link_timeouts.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn record_enquire_link_error(&self, bind_idx: usize) {
+        let bucket = self.bucket();
+        if let Some(bind) = bucket.per_bind.get(bind_idx) {
+            bind.enquire_link_errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Marks the start of a measurement window, see `window_since`.
+    pub fn checkpoint(&self) -> MetricsCheckpoint {
+        MetricsCheckpoint {
+            warmup: self.warmup.checkpoint(),
+            measured: self.measured.checkpoint(),
+        }
+    }
+
+    /// Submit results and response latency since `checkpoint`, whichever
+    /// bucket they went to.
+    pub fn window_since(&self, checkpoint: &MetricsCheckpoint) -> WindowSnapshot {
+        let warmup = self.warmup.window_since(&checkpoint.warmup);
+        let measured = self.measured.window_since(&checkpoint.measured);
+        WindowSnapshot {
+            attempts: warmup.attempts + measured.attempts,
+            err: warmup.err + measured.err,
+            // Exact unless the window spans the end of the warm-up.
+            latency: HistogramSnapshot::merge([&warmup.latency, &measured.latency]),
+        }
+    }
+
+    /// The measurement window: everything since the warm-up ended.
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        self.measured.snapshot(self)
+    }
+
+    /// What was recorded during the warm-up.
+    pub fn warmup_snapshot(&self) -> MetricsSnapshot {
+        self.warmup.snapshot(self)
+    }
+}
+
+impl MetricsBucket {
+    fn new(accounts: &[AccountConfig], extra_binds: usize) -> Self {
+        let per_bind = accounts
+            .iter()
+            .enumerate()
+            .flat_map(|(account, config)| {
+                (0..config.load.bind_slots()).map(move |_| BindMetrics {
+                    account,
+                    ..BindMetrics::default()
+                })
+            })
+            .chain((0..extra_binds).map(|_| BindMetrics {
+                account: accounts.len(),
+                ..BindMetrics::default()
+            }))
+            .collect();
+
+        Self {
+            total_attempts: AtomicU64::new(0),
+            total_success: AtomicU64::new(0),
+            total_error: AtomicU64::new(0),
+            total_latency_micros: AtomicU64::new(0),
+            operations: Default::default(),
+            dlr_error_codes: Mutex::new(BTreeMap::new()),
+            delays: DelayHistograms::default(),
+            lifecycle: LifecycleHistograms::default(),
+            mo_data_coding: Mutex::new(BTreeMap::new()),
+            mo_max_bytes: AtomicU64::new(0),
+            deliver_resp: DeliverRespMetrics::default(),
+            open_loop: OpenLoopMetrics::default(),
+            incoming: Mutex::new(BTreeMap::new()),
+            outbind_sessions: AtomicU64::new(0),
+            outbind_rejected: AtomicU64::new(0),
+            per_bind,
+        }
+    }
+
     fn add_latency(&self, latency: Duration) {
         let micros = latency.as_micros();
         let capped = u64::try_from(micros).unwrap_or(u64::MAX);
@@ -72,7 +581,34 @@ impl Metrics {
             .fetch_add(capped, Ordering::Relaxed);
     }
 
-    pub fn snapshot(&self) -> MetricsSnapshot {
+    fn checkpoint(&self) -> BucketCheckpoint {
+        BucketCheckpoint {
+            attempts: self.total_attempts.load(Ordering::Relaxed),
+            err: self.total_error.load(Ordering::Relaxed),
+            latency: self.delays.submit_to_resp.checkpoint(),
+        }
+    }
+
+    fn window_since(&self, checkpoint: &BucketCheckpoint) -> WindowSnapshot {
+        WindowSnapshot {
+            attempts: self
+                .total_attempts
+                .load(Ordering::Relaxed)
+                .saturating_sub(checkpoint.attempts),
+            err: self
+                .total_error
+                .load(Ordering::Relaxed)
+                .saturating_sub(checkpoint.err),
+            latency: self
+                .delays
+                .submit_to_resp
+                .snapshot_since(&checkpoint.latency),
+        }
+    }
+
+    /// Takes the account names and the receipt gauges, which belong to the
+    /// whole run, from `metrics`.
+    fn snapshot(&self, metrics: &Metrics) -> MetricsSnapshot {
         let attempts = self.total_attempts.load(Ordering::Relaxed);
         let ok = self.total_success.load(Ordering::Relaxed);
         let err = self.total_error.load(Ordering::Relaxed);
@@ -83,20 +619,134 @@ impl Metrics {
             (latency as f64 / attempts as f64) / 1000.0
         };
 
-        let bind_snapshots = self.per_bind.iter().map(BindMetrics::snapshot).collect();
+        let bind_snapshots: Vec<BindSnapshot> =
+            self.per_bind.iter().map(BindMetrics::snapshot).collect();
+        let per_account = metrics
+            .account_names
+            .iter()
+            .enumerate()
+            .map(|(account, name)| AccountSnapshot::aggregate(account, name, &bind_snapshots))
+            .collect();
+        let operations = Operation::ALL
+            .iter()
+            .map(|op| self.operations[op.index()].snapshot(*op))
+            .collect();
+
+        let mut dlr_error_codes: Vec<(String, u64)> = self
+            .dlr_error_codes
+            .lock()
+            .expect("DLR error code lock poisoned")
+            .iter()
+            .map(|(code, count)| (code.clone(), *count))
+            .collect();
+        dlr_error_codes.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+
+        let mo_data_coding = self
+            .mo_data_coding
+            .lock()
+            .expect("MO data_coding lock poisoned")
+            .iter()
+            .map(|(data_coding, count)| (*data_coding, *count))
+            .collect();
+
+        let mut incoming: Vec<(String, u64)> = self
+            .incoming
+            .lock()
+            .expect("incoming command lock poisoned")
+            .iter()
+            .map(|(command, count)| (command.clone(), *count))
+            .collect();
+        incoming.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
 
         MetricsSnapshot {
             attempts,
             ok,
             err,
             avg_latency_ms,
+            operations,
+            dlr_pending: metrics.dlr_pending.load(Ordering::Relaxed),
+            dlr_pending_peak: metrics.dlr_pending_peak.load(Ordering::Relaxed),
+            dlr_error_codes,
+            delays: DelaySnapshot {
+                submit_to_resp: self.delays.submit_to_resp.snapshot(),
+                resp_to_delivered: self.delays.resp_to_delivered.snapshot(),
+                resp_to_undelivered: self.delays.resp_to_undelivered.snapshot(),
+                submit_to_delivered: self.delays.submit_to_delivered.snapshot(),
+                submit_to_undelivered: self.delays.submit_to_undelivered.snapshot(),
+            },
+            lifecycle: LifecycleSnapshot {
+                connect: self.lifecycle.connect.snapshot(),
+                bind: self.lifecycle.bind.snapshot(),
+                bound: self.lifecycle.bound.snapshot(),
+                unbind: self.lifecycle.unbind.snapshot(),
+            },
+            mo_data_coding,
+            mo_max_bytes: self.mo_max_bytes.load(Ordering::Relaxed),
+            deliver_resp: DeliverRespSnapshot {
+                accepted: self.deliver_resp.accepted.load(Ordering::Relaxed),
+                rejected: self.deliver_resp.rejected.load(Ordering::Relaxed),
+                dropped: self.deliver_resp.dropped.load(Ordering::Relaxed),
+                redeliveries: self.deliver_resp.redeliveries.load(Ordering::Relaxed),
+                redelivered_messages: self.deliver_resp.redelivered_messages.load(Ordering::Relaxed),
+                recovered: self.deliver_resp.recovered.load(Ordering::Relaxed),
+                retry_interval: self.deliver_resp.retry_interval.snapshot(),
+            },
+            open_loop: OpenLoopSnapshot {
+                scheduled: self.open_loop.scheduled.load(Ordering::Relaxed),
+                delayed: self.open_loop.delayed.load(Ordering::Relaxed),
+                dropped: self.open_loop.dropped.load(Ordering::Relaxed),
+                backlog_peak: self.open_loop.backlog_peak.load(Ordering::Relaxed),
+                send_lag: self.open_loop.send_lag.snapshot(),
+            },
+            incoming,
+            outbind_sessions: self.outbind_sessions.load(Ordering::Relaxed),
+            outbind_rejected: self.outbind_rejected.load(Ordering::Relaxed),
+            per_account,
             per_bind: bind_snapshots,
         }
     }
 }
 
+#[derive(Default, Debug)]
+struct OperationMetrics {
+    attempts: AtomicU64,
+    success: AtomicU64,
+    error: AtomicU64,
+    latency_micros: AtomicU64,
+}
+
+impl OperationMetrics {
+    fn record(&self, ok: bool, latency: Duration) {
+        self.attempts.fetch_add(1, Ordering::Relaxed);
+        if ok {
+            self.success.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.error.fetch_add(1, Ordering::Relaxed);
+        }
+        let capped = u64::try_from(latency.as_micros()).unwrap_or(u64::MAX);
+        self.latency_micros.fetch_add(capped, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self, operation: Operation) -> OperationSnapshot {
+        let attempts = self.attempts.load(Ordering::Relaxed);
+        let latency = self.latency_micros.load(Ordering::Relaxed);
+        OperationSnapshot {
+            operation,
+            attempts,
+            ok: self.success.load(Ordering::Relaxed),
+            err: self.error.load(Ordering::Relaxed),
+            avg_latency_ms: if attempts == 0 {
+                0.0
+            } else {
+                (latency as f64 / attempts as f64) / 1000.0
+            },
+        }
+    }
+}
+
 #[derive(Default, Debug)]
 struct BindMetrics {
+    account: usize,
     attempts: AtomicU64,
     success: AtomicU64,
     error: AtomicU64,
@@ -110,6 +760,27 @@ struct BindMetrics {
     dlr_expired: AtomicU64,
     dlr_deleted: AtomicU64,
     dlr_accepted: AtomicU64,
+    dlr_missing: AtomicU64,
+    dlr_evicted: AtomicU64,
+    dlr_orphan: AtomicU64,
+    dlr_malformed: AtomicU64,
+    dlr_duplicate: AtomicU64,
+    dlr_conflict: AtomicU64,
+    dlr_intermediate: AtomicU64,
+    dlr_illegal_transition: AtomicU64,
+    dlr_invalid: AtomicU64,
+    dlr_smsc_delay_micros: AtomicU64,
+    dlr_smsc_delay_samples: AtomicU64,
+    mo_received: AtomicU64,
+    mo_bytes: AtomicU64,
+    mo_mismatch: AtomicU64,
+    enquire_link_ok: AtomicU64,
+    enquire_link_timeouts: AtomicU64,
+    enquire_link_errors: AtomicU64,
+    enquire_link_rtt_micros: AtomicU64,
+    server_unbinds: AtomicU64,
+    disconnects: AtomicU64,
+    reconnects: AtomicU64,
 }
 
 impl BindMetrics {
@@ -138,16 +809,6 @@ impl BindMetrics {
         self.dlr_latency_micros.fetch_add(capped, Ordering::Relaxed);
     }
 
-    fn record_dlr_status(&self, delivered: bool, failed: bool) {
-        if delivered {
-            self.dlr_delivered.fetch_add(1, Ordering::Relaxed);
-        } else if failed {
-            self.dlr_failed.fetch_add(1, Ordering::Relaxed);
-        } else {
-            self.dlr_unknown.fetch_add(1, Ordering::Relaxed);
-        }
-    }
-
     fn record_dlr_state(&self, state: MessageState) {
         match state {
             MessageState::Enroute => { self.dlr_enroute.fetch_add(1, Ordering::Relaxed); }
@@ -164,6 +825,13 @@ impl BindMetrics {
         }
     }
 
+    fn record_enquire_link(&self, rtt: Duration) {
+        self.enquire_link_ok.fetch_add(1, Ordering::Relaxed);
+        let capped = u64::try_from(rtt.as_micros()).unwrap_or(u64::MAX);
+        self.enquire_link_rtt_micros
+            .fetch_add(capped, Ordering::Relaxed);
+    }
+
     fn snapshot(&self) -> BindSnapshot {
         let attempts = self.attempts.load(Ordering::Relaxed);
         let ok = self.success.load(Ordering::Relaxed);
@@ -181,8 +849,23 @@ impl BindMetrics {
         } else {
             (dlr_latency as f64 / dlr as f64) / 1000.0
         };
+        let smsc_delay_samples = self.dlr_smsc_delay_samples.load(Ordering::Relaxed);
+        let smsc_delay = self.dlr_smsc_delay_micros.load(Ordering::Relaxed);
+        let avg_smsc_delay_ms = if smsc_delay_samples == 0 {
+            0.0
+        } else {
+            (smsc_delay as f64 / smsc_delay_samples as f64) / 1000.0
+        };
+        let enquire_link_ok = self.enquire_link_ok.load(Ordering::Relaxed);
+        let enquire_link_rtt = self.enquire_link_rtt_micros.load(Ordering::Relaxed);
+        let avg_enquire_link_ms = if enquire_link_ok == 0 {
+            0.0
+        } else {
+            (enquire_link_rtt as f64 / enquire_link_ok as f64) / 1000.0
+        };
 
         BindSnapshot {
+            account: self.account,
             attempts,
             ok,
             err,
@@ -196,24 +879,394 @@ impl BindMetrics {
             dlr_expired: self.dlr_expired.load(Ordering::Relaxed),
             dlr_deleted: self.dlr_deleted.load(Ordering::Relaxed),
             dlr_accepted: self.dlr_accepted.load(Ordering::Relaxed),
+            dlr_missing: self.dlr_missing.load(Ordering::Relaxed),
+            dlr_evicted: self.dlr_evicted.load(Ordering::Relaxed),
+            dlr_orphan: self.dlr_orphan.load(Ordering::Relaxed),
+            dlr_malformed: self.dlr_malformed.load(Ordering::Relaxed),
+            dlr_duplicate: self.dlr_duplicate.load(Ordering::Relaxed),
+            dlr_conflict: self.dlr_conflict.load(Ordering::Relaxed),
+            dlr_intermediate: self.dlr_intermediate.load(Ordering::Relaxed),
+            dlr_illegal_transition: self.dlr_illegal_transition.load(Ordering::Relaxed),
+            dlr_invalid: self.dlr_invalid.load(Ordering::Relaxed),
+            dlr_smsc_delay_samples: smsc_delay_samples,
+            avg_smsc_delay_ms,
+            mo_received: self.mo_received.load(Ordering::Relaxed),
+            mo_bytes: self.mo_bytes.load(Ordering::Relaxed),
+            mo_mismatch: self.mo_mismatch.load(Ordering::Relaxed),
+            enquire_link_ok,
+            enquire_link_timeouts: self.enquire_link_timeouts.load(Ordering::Relaxed),
+            enquire_link_errors: self.enquire_link_errors.load(Ordering::Relaxed),
+            avg_enquire_link_ms,
+            server_unbinds: self.server_unbinds.load(Ordering::Relaxed),
+            disconnects: self.disconnects.load(Ordering::Relaxed),
+            reconnects: self.reconnects.load(Ordering::Relaxed),
         }
     }
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct MetricsSnapshot {
     pub attempts: u64,
     pub ok: u64,
     pub err: u64,
     pub avg_latency_ms: f64,
+    pub operations: Vec<OperationSnapshot>,
+    /// Messages currently awaiting a receipt.
+    pub dlr_pending: u64,
+    /// Highest `dlr_pending` seen during the run.
+    pub dlr_pending_peak: u64,
+    /// Receipt `err:` codes, most frequent first.
+    pub dlr_error_codes: Vec<(String, u64)>,
+    pub delays: DelaySnapshot,
+    pub lifecycle: LifecycleSnapshot,
+    /// MO messages per `data_coding`.
+    pub mo_data_coding: Vec<(u8, u64)>,
+    /// Largest MO body seen, in bytes.
+    pub mo_max_bytes: u64,
+    pub deliver_resp: DeliverRespSnapshot,
+    pub open_loop: OpenLoopSnapshot,
+    /// SMSC-initiated PDUs per command, most frequent first.
+    pub incoming: Vec<(String, u64)>,
+    /// Outbind connections that ended up bound as receiver.
+    pub outbind_sessions: u64,
+    /// Outbind connections refused (bad credentials, no `outbind`, bind failure).
+    pub outbind_rejected: u64,
+    pub per_account: Vec<AccountSnapshot>,
     pub per_bind: Vec<BindSnapshot>,
 }
 
+impl MetricsSnapshot {
+    /// Average SMSC-reported submit-to-done time across all binds.
+    pub fn avg_smsc_delay_ms(&self) -> f64 {
+        let samples: u64 = self.per_bind.iter().map(|bind| bind.dlr_smsc_delay_samples).sum();
+        if samples == 0 {
+            return 0.0;
+        }
+        let total_ms: f64 = self
+            .per_bind
+            .iter()
+            .map(|bind| bind.avg_smsc_delay_ms * bind.dlr_smsc_delay_samples as f64)
+            .sum();
+        total_ms / samples as f64
+    }
+
+    /// Keep-alive totals across all binds.
+    pub fn enquire_link(&self) -> EnquireLinkSummary {
+        let mut summary = EnquireLinkSummary::default();
+        let mut rtt_ms = 0.0;
+        for bind in &self.per_bind {
+            summary.ok += bind.enquire_link_ok;
+            summary.timeouts += bind.enquire_link_timeouts;
+            summary.errors += bind.enquire_link_errors;
+            rtt_ms += bind.avg_enquire_link_ms * bind.enquire_link_ok as f64;
+        }
+        if summary.ok > 0 {
+            summary.avg_rtt_ms = rtt_ms / summary.ok as f64;
+        }
+        summary
+    }
+
+    /// Combines the snapshots of agents that each ran a share of the same
+    /// accounts. Counters add up, averages are weighted by their sample
+    /// counts, and the binds of all agents are listed one after another.
+    /// Peaks are summed, which overstates them if they didn't coincide.
+    pub fn merge(snapshots: &[MetricsSnapshot]) -> MetricsSnapshot {
+        fn merged_counts<K: Ord + Clone>(lists: impl Iterator<Item = Vec<(K, u64)>>) -> Vec<(K, u64)> {
+            let mut counts = BTreeMap::new();
+            for (key, count) in lists.flatten() {
+                *counts.entry(key).or_insert(0) += count;
+            }
+            counts.into_iter().collect()
+        }
+        fn by_count<K>(mut counts: Vec<(K, u64)>) -> Vec<(K, u64)> {
+            counts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+            counts
+        }
+        let sum = |field: fn(&MetricsSnapshot) -> u64| snapshots.iter().map(field).sum::<u64>();
+        let histogram = |field: fn(&MetricsSnapshot) -> &HistogramSnapshot| {
+            HistogramSnapshot::merge(snapshots.iter().map(field))
+        };
+
+        let attempts = sum(|s| s.attempts);
+        let latency_ms: f64 = snapshots
+            .iter()
+            .map(|s| s.avg_latency_ms * s.attempts as f64)
+            .sum();
+
+        let operations = Operation::ALL
+            .iter()
+            .map(|&operation| {
+                let mut merged = OperationSnapshot {
+                    operation,
+                    attempts: 0,
+                    ok: 0,
+                    err: 0,
+                    avg_latency_ms: 0.0,
+                };
+                let mut latency_ms = 0.0;
+                for op in snapshots
+                    .iter()
+                    .flat_map(|s| &s.operations)
+                    .filter(|op| op.operation == operation)
+                {
+                    merged.attempts += op.attempts;
+                    merged.ok += op.ok;
+                    merged.err += op.err;
+                    latency_ms += op.avg_latency_ms * op.attempts as f64;
+                }
+                if merged.attempts > 0 {
+                    merged.avg_latency_ms = latency_ms / merged.attempts as f64;
+                }
+                merged
+            })
+            .collect();
+
+        let per_bind: Vec<BindSnapshot> = snapshots
+            .iter()
+            .flat_map(|s| s.per_bind.iter().copied())
+            .collect();
+        let per_account = snapshots
+            .first()
+            .map(|first| {
+                first
+                    .per_account
+                    .iter()
+                    .enumerate()
+                    .map(|(account, snapshot)| {
+                        AccountSnapshot::aggregate(account, &snapshot.name, &per_bind)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        MetricsSnapshot {
+            attempts,
+            ok: sum(|s| s.ok),
+            err: sum(|s| s.err),
+            avg_latency_ms: if attempts == 0 {
+                0.0
+            } else {
+                latency_ms / attempts as f64
+            },
+            operations,
+            dlr_pending: sum(|s| s.dlr_pending),
+            dlr_pending_peak: sum(|s| s.dlr_pending_peak),
+            dlr_error_codes: by_count(merged_counts(
+                snapshots.iter().map(|s| s.dlr_error_codes.clone()),
+            )),
+            delays: DelaySnapshot {
+                submit_to_resp: histogram(|s| &s.delays.submit_to_resp),
+                resp_to_delivered: histogram(|s| &s.delays.resp_to_delivered),
+                resp_to_undelivered: histogram(|s| &s.delays.resp_to_undelivered),
+                submit_to_delivered: histogram(|s| &s.delays.submit_to_delivered),
+                submit_to_undelivered: histogram(|s| &s.delays.submit_to_undelivered),
+            },
+            lifecycle: LifecycleSnapshot {
+                connect: histogram(|s| &s.lifecycle.connect),
+                bind: histogram(|s| &s.lifecycle.bind),
+                bound: histogram(|s| &s.lifecycle.bound),
+                unbind: histogram(|s| &s.lifecycle.unbind),
+            },
+            mo_data_coding: merged_counts(snapshots.iter().map(|s| s.mo_data_coding.clone())),
+            mo_max_bytes: snapshots.iter().map(|s| s.mo_max_bytes).max().unwrap_or(0),
+            deliver_resp: DeliverRespSnapshot {
+                accepted: sum(|s| s.deliver_resp.accepted),
+                rejected: sum(|s| s.deliver_resp.rejected),
+                dropped: sum(|s| s.deliver_resp.dropped),
+                redeliveries: sum(|s| s.deliver_resp.redeliveries),
+                redelivered_messages: sum(|s| s.deliver_resp.redelivered_messages),
+                recovered: sum(|s| s.deliver_resp.recovered),
+                retry_interval: histogram(|s| &s.deliver_resp.retry_interval),
+            },
+            open_loop: OpenLoopSnapshot {
+                scheduled: sum(|s| s.open_loop.scheduled),
+                delayed: sum(|s| s.open_loop.delayed),
+                dropped: sum(|s| s.open_loop.dropped),
+                backlog_peak: snapshots
+                    .iter()
+                    .map(|s| s.open_loop.backlog_peak)
+                    .max()
+                    .unwrap_or(0),
+                send_lag: histogram(|s| &s.open_loop.send_lag),
+            },
+            incoming: by_count(merged_counts(snapshots.iter().map(|s| s.incoming.clone()))),
+            outbind_sessions: sum(|s| s.outbind_sessions),
+            outbind_rejected: sum(|s| s.outbind_rejected),
+            per_account,
+            per_bind,
+        }
+    }
+
+    /// Session interruptions across all binds.
+    pub fn sessions(&self) -> SessionSummary {
+        let mut summary = SessionSummary::default();
+        for bind in &self.per_bind {
+            summary.server_unbinds += bind.server_unbinds;
+            summary.disconnects += bind.disconnects;
+            summary.reconnects += bind.reconnects;
+        }
+        summary
+    }
+}
+
+/// Delay distributions across all binds.
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct DelaySnapshot {
+    /// `submit_sm` to an ESME_ROK `submit_sm_resp`.
+    pub submit_to_resp: HistogramSnapshot,
+    /// `submit_sm_resp` to a DELIVRD receipt.
+    pub resp_to_delivered: HistogramSnapshot,
+    /// `submit_sm_resp` to an UNDELIV, EXPIRED, REJECTD or DELETED receipt.
+    pub resp_to_undelivered: HistogramSnapshot,
+    /// `submit_sm` to a DELIVRD receipt.
+    pub submit_to_delivered: HistogramSnapshot,
+    /// `submit_sm` to an UNDELIV, EXPIRED, REJECTD or DELETED receipt.
+    pub submit_to_undelivered: HistogramSnapshot,
+}
+
+/// Session step durations across all binds.
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct LifecycleSnapshot {
+    pub connect: HistogramSnapshot,
+    /// Bind request to `bind_resp`.
+    pub bind: HistogramSnapshot,
+    /// Time from `bind_resp` until the session ended.
+    pub bound: HistogramSnapshot,
+    /// `unbind` to `unbind_resp`, for sessions we ended.
+    pub unbind: HistogramSnapshot,
+}
+
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct DeliverRespSnapshot {
+    pub accepted: u64,
+    pub rejected: u64,
+    pub dropped: u64,
+    pub redeliveries: u64,
+    pub redelivered_messages: u64,
+    pub recovered: u64,
+    pub retry_interval: HistogramSnapshot,
+}
+
+#[derive(Debug, Clone)]
+pub struct MetricsCheckpoint {
+    warmup: BucketCheckpoint,
+    measured: BucketCheckpoint,
+}
+
+#[derive(Debug, Clone)]
+struct BucketCheckpoint {
+    attempts: u64,
+    err: u64,
+    latency: HistogramCheckpoint,
+}
+
+/// Submit results over a window of the run.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct WindowSnapshot {
+    pub attempts: u64,
+    pub err: u64,
+    /// Response time of the successful submits.
+    pub latency: HistogramSnapshot,
+}
+
+impl WindowSnapshot {
+    pub fn error_percent(&self) -> f64 {
+        if self.attempts == 0 {
+            0.0
+        } else {
+            self.err as f64 / self.attempts as f64 * 100.0
+        }
+    }
+}
+
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct OpenLoopSnapshot {
+    pub scheduled: u64,
+    pub delayed: u64,
+    pub dropped: u64,
+    pub backlog_peak: u64,
+    pub send_lag: HistogramSnapshot,
+}
+
 #[derive(Default, Clone, Copy)]
+pub struct EnquireLinkSummary {
+    pub ok: u64,
+    pub timeouts: u64,
+    pub errors: u64,
+    pub avg_rtt_ms: f64,
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct SessionSummary {
+    pub server_unbinds: u64,
+    pub disconnects: u64,
+    pub reconnects: u64,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct OperationSnapshot {
+    pub operation: Operation,
+    pub attempts: u64,
+    pub ok: u64,
+    pub err: u64,
+    pub avg_latency_ms: f64,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AccountSnapshot {
+    pub name: String,
+    pub binds: usize,
+    pub attempts: u64,
+    pub ok: u64,
+    pub err: u64,
+    pub avg_latency_ms: f64,
+    pub dlr_received: u64,
+    pub dlr_delivered: u64,
+    pub dlr_failed: u64,
+    pub dlr_missing: u64,
+}
+
+impl AccountSnapshot {
+    fn aggregate(account: usize, name: &str, binds: &[BindSnapshot]) -> Self {
+        let mut snapshot = AccountSnapshot {
+            name: name.to_string(),
+            binds: 0,
+            attempts: 0,
+            ok: 0,
+            err: 0,
+            avg_latency_ms: 0.0,
+            dlr_received: 0,
+            dlr_delivered: 0,
+            dlr_failed: 0,
+            dlr_missing: 0,
+        };
+        let mut latency_ms = 0.0;
+        for bind in binds.iter().filter(|bind| bind.account == account) {
+            snapshot.binds += 1;
+            snapshot.attempts += bind.attempts;
+            snapshot.ok += bind.ok;
+            snapshot.err += bind.err;
+            snapshot.dlr_received += bind.dlr_received;
+            snapshot.dlr_delivered += bind.dlr_delivered;
+            snapshot.dlr_failed += bind.dlr_failed;
+            snapshot.dlr_missing += bind.dlr_missing;
+            latency_ms += bind.avg_latency_ms * bind.attempts as f64;
+        }
+        if snapshot.attempts > 0 {
+            snapshot.avg_latency_ms = latency_ms / snapshot.attempts as f64;
+        }
+        snapshot
+    }
+}
+
+#[derive(Default, Clone, Copy, Serialize, Deserialize)]
 pub struct BindSnapshot {
+    pub account: usize,
     pub attempts: u64,
     pub ok: u64,
     pub err: u64,
     pub avg_latency_ms: f64,
+    /// Messages that reached a final state, one per message.
     pub dlr_received: u64,
     pub avg_dlr_delay_ms: f64,
     pub dlr_delivered: u64,
@@ -223,4 +1276,119 @@ pub struct BindSnapshot {
     pub dlr_expired: u64,
     pub dlr_deleted: u64,
     pub dlr_accepted: u64,
+    /// Receipts that never arrived within the DLR timeout.
+    pub dlr_missing: u64,
+    /// Pending messages dropped because the correlation store was full.
+    pub dlr_evicted: u64,
+    /// Receipts for message IDs this run never submitted.
+    pub dlr_orphan: u64,
+    /// `deliver_sm`s flagged as receipts that carried no message ID.
+    pub dlr_malformed: u64,
+    /// Additional receipts for a message that was already counted.
+    pub dlr_duplicate: u64,
+    /// Receipts whose TLVs and text disagreed.
+    pub dlr_conflict: u64,
+    /// ENROUTE/SCHEDULED receipts; they don't count towards `dlr_received`.
+    pub dlr_intermediate: u64,
+    /// Receipts that changed a message's state after its final receipt.
+    pub dlr_illegal_transition: u64,
+    /// Receipts inconsistent with the submitted message (text, sub/dlvrd, dates).
+    pub dlr_invalid: u64,
+    /// Receipts carrying both a submit and a done date.
+    pub dlr_smsc_delay_samples: u64,
+    /// Average submit-to-done time according to the SMSC's receipt dates.
+    pub avg_smsc_delay_ms: f64,
+    /// Mobile-originated `deliver_sm` received on this bind.
+    pub mo_received: u64,
+    /// Total MO body bytes.
+    pub mo_bytes: u64,
+    /// MO bodies that didn't match `mo.expected_pattern`.
+    pub mo_mismatch: u64,
+    pub enquire_link_ok: u64,
+    pub enquire_link_timeouts: u64,
+    pub enquire_link_errors: u64,
+    pub avg_enquire_link_ms: f64,
+    /// `unbind` requests received from the SMSC.
+    pub server_unbinds: u64,
+    /// Connections lost without an `unbind`.
+    pub disconnects: u64,
+    /// Reconnect attempts after a server unbind or lost connection.
+    pub reconnects: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn submit_to_resp_counts_only_successful_responses() {
+        let metrics = Metrics::new(&[], 0, false);
+        let checkpoint = metrics.checkpoint();
+        metrics.record_success(0, ms(10));
+        metrics.record_success(0, ms(12));
+        // A throttled submit answered at once and one that timed out.
+        metrics.record_error(0, ms(1));
+        metrics.record_error(0, ms(5000));
+
+        let snapshot = metrics.snapshot();
+        assert_eq!((snapshot.attempts, snapshot.ok, snapshot.err), (4, 2, 2));
+        let resp = &snapshot.delays.submit_to_resp;
+        assert_eq!(resp.count, 2);
+        assert!(resp.max_ms < 13.0, "{:?}", resp);
+
+        let window = metrics.window_since(&checkpoint);
+        assert_eq!((window.attempts, window.err), (4, 2));
+        assert_eq!(window.latency.count, 2);
+    }
+
+    #[test]
+    fn receipt_delays_are_split_by_final_state() {
+        let metrics = Metrics::new(&[], 0, false);
+        let cases = [
+            (MessageState::Delivered, ms(1000), ms(900)),
+            (MessageState::Delivered, ms(2000), ms(1900)),
+            (MessageState::Undeliverable, ms(3000), ms(2900)),
+            (MessageState::Expired, ms(4000), ms(3900)),
+            (MessageState::Rejected, ms(5000), ms(4900)),
+            (MessageState::Deleted, ms(6000), ms(5900)),
+            // Not final for the split: left out of both.
+            (MessageState::Unknown, ms(7000), ms(6900)),
+            (MessageState::Accepted, ms(8000), ms(7900)),
+        ];
+        for (state, since_submit, since_resp) in cases {
+            metrics.record_delivery_time(Phase::Measured, state, since_submit, since_resp);
+        }
+
+        let delays = metrics.snapshot().delays;
+        let counts = [
+            delays.submit_to_delivered.count,
+            delays.resp_to_delivered.count,
+            delays.submit_to_undelivered.count,
+            delays.resp_to_undelivered.count,
+        ];
+        assert_eq!(counts, [2, 2, 4, 4]);
+        assert!(delays.resp_to_delivered.max_ms < 2000.0);
+        assert!(delays.resp_to_undelivered.max_ms < 6000.0);
+        assert!(delays.submit_to_undelivered.max_ms >= 6000.0);
+    }
+
+    #[test]
+    fn receipt_delays_survive_merging() {
+        let agent = |state| {
+            let metrics = Metrics::new(&[], 0, false);
+            metrics.record_delivery_time(Phase::Measured, state, ms(200), ms(100));
+            metrics.snapshot()
+        };
+        let merged = MetricsSnapshot::merge(&[
+            agent(MessageState::Delivered),
+            agent(MessageState::Expired),
+            agent(MessageState::Delivered),
+        ]);
+        assert_eq!(merged.delays.resp_to_delivered.count, 2);
+        assert_eq!(merged.delays.resp_to_undelivered.count, 1);
+    }
 }
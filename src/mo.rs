@@ -0,0 +1,51 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use regex::Regex;
+use rusmpp::pdus::DeliverSm;
+
+use crate::{config::MoConfig, dlr::UCS2_DATA_CODING, metrics::Metrics};
+
+/// Accounts mobile-originated `deliver_sm` received on TRX/RX binds.
+pub struct MoTracker {
+    metrics: Arc<Metrics>,
+    expected_pattern: Option<Regex>,
+}
+
+impl MoTracker {
+    pub fn new(config: &MoConfig, metrics: Arc<Metrics>) -> Result<Self> {
+        Ok(Self {
+            metrics,
+            expected_pattern: config.expected_pattern()?,
+        })
+    }
+
+    pub fn record(&self, bind: usize, deliver: &DeliverSm) {
+        let body = deliver.short_message().bytes();
+        let data_coding = u8::from(deliver.data_coding);
+        let matched = self.expected_pattern.as_ref().map(|pattern| {
+            let text = decode_body(body, data_coding);
+            let matched = pattern.is_match(&text);
+            if !matched {
+                tracing::debug!(bind, %text, "MO body does not match mo.expected_pattern");
+            }
+            matched
+        });
+        self.metrics
+            .record_mo(bind, body.len(), data_coding, matched);
+    }
+}
+
+/// UCS2 bodies are UTF-16BE; everything else is compared as (lossy) UTF-8,
+/// which covers the ASCII subset of GSM 7-bit and Latin-1.
+fn decode_body(body: &[u8], data_coding: u8) -> String {
+    if data_coding == UCS2_DATA_CODING {
+        let units: Vec<u16> = body
+            .chunks_exact(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    } else {
+        String::from_utf8_lossy(body).into_owned()
+    }
+}
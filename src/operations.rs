@@ -0,0 +1,315 @@
+use std::{collections::VecDeque, fmt, str::FromStr, time::Duration};
+
+use anyhow::Result;
+use futures::{FutureExt, future::BoxFuture};
+use rand::Rng;
+use rusmpp::{
+    CommandId,
+    pdus::{CancelSm, QuerySm, ReplaceSm, SubmitSm},
+    types::{COctetString, OctetString},
+    values::{RegisteredDelivery, ServiceType},
+};
+use rusmppc::error::Error as ClientError;
+use serde::{Deserialize, Serialize};
+use tokio::{sync::watch, time::Instant};
+
+use crate::{
+    config::{MessageConfig, OperationMix},
+    control::ControlState,
+    side_channel::SideChannel,
+};
+
+/// How many accepted message IDs each bind remembers as operation targets.
+const RECENT_IDS_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    Submit,
+    Query,
+    Replace,
+    Cancel,
+}
+
+impl Operation {
+    pub const ALL: [Operation; 4] = [
+        Operation::Submit,
+        Operation::Query,
+        Operation::Replace,
+        Operation::Cancel,
+    ];
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Operation::Submit => "submit_sm",
+            Operation::Query => "query_sm",
+            Operation::Replace => "replace_sm",
+            Operation::Cancel => "cancel_sm",
+        }
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+pub struct OperationOutcome {
+    pub operation: Operation,
+    /// Message profile (`ControlState::profile`) the PDU was built from.
+    pub profile: usize,
+    /// `message_id` returned by the SMSC for `submit_sm`, `None` for other operations.
+    pub result: Result<Option<String>, ClientError>,
+    /// Message targeted by `query_sm`, `replace_sm` or `cancel_sm`.
+    pub target: Option<String>,
+    pub latency: Duration,
+}
+
+/// Builds the next PDU for a bind according to the configured operation mix.
+pub struct Dispatcher {
+    client: rusmppc::Client,
+    /// Carries `query_sm`, `replace_sm` and `cancel_sm`, which the client
+    /// has no methods for.
+    side_channel: SideChannel,
+    submit_template: SubmitSm,
+    message: MessageConfig,
+    /// Every message this bind can switch to, indexed by
+    /// `ControlState::profile`.
+    profiles: Vec<(SubmitSm, MessageConfig)>,
+    profile: usize,
+    control: watch::Receiver<ControlState>,
+    mix: OperationMix,
+    /// Accepted message IDs per profile, so operations target messages with
+    /// the addresses of the profile they are built from.
+    recent_ids: Vec<VecDeque<String>>,
+}
+
+impl Dispatcher {
+    /// `profiles` must not be empty; the first one is used until the
+    /// controls select another.
+    pub fn new(
+        client: rusmppc::Client,
+        side_channel: SideChannel,
+        profiles: Vec<(SubmitSm, MessageConfig)>,
+        mix: OperationMix,
+        control: watch::Receiver<ControlState>,
+    ) -> Self {
+        let (submit_template, message) = profiles[0].clone();
+        let recent_ids = vec![VecDeque::new(); profiles.len()];
+        Self {
+            client,
+            side_channel,
+            submit_template,
+            message,
+            profiles,
+            profile: 0,
+            control,
+            mix,
+            recent_ids,
+        }
+    }
+
+    /// Picks up a message profile switched by the controls.
+    fn follow_profile(&mut self) {
+        let profile = self.control.borrow().profile;
+        if profile == self.profile {
+            return;
+        }
+        if let Some((submit_template, message)) = self.profiles.get(profile) {
+            self.submit_template = submit_template.clone();
+            self.message = message.clone();
+            self.profile = profile;
+        }
+    }
+
+    /// Whether messages accepted from `profile` should wait for a delivery
+    /// receipt.
+    pub fn requests_dlr(&self, profile: usize) -> bool {
+        self.profiles
+            .get(profile)
+            .is_some_and(|(_, message)| message.request_dlr)
+    }
+
+    /// Remembers a message accepted from `profile` so later operations built
+    /// from the same profile can target it.
+    pub fn remember(&mut self, profile: usize, message_id: String) {
+        let Some(recent_ids) = self.recent_ids.get_mut(profile) else {
+            return;
+        };
+        if recent_ids.len() == RECENT_IDS_CAPACITY {
+            recent_ids.pop_front();
+        }
+        recent_ids.push_back(message_id);
+    }
+
+    /// Stops targeting a message, e.g. once it was cancelled.
+    pub fn forget(&mut self, profile: usize, message_id: &str) {
+        if let Some(recent_ids) = self.recent_ids.get_mut(profile) {
+            recent_ids.retain(|id| id != message_id);
+        }
+    }
+
+    /// Issues the next operation. `is_pending` tells whether a message ID is
+    /// still awaiting its delivery receipt in the correlation index.
+    pub fn next(
+        &mut self,
+        is_pending: impl Fn(&str) -> bool,
+    ) -> BoxFuture<'static, OperationOutcome> {
+        self.follow_profile();
+        let operation = self.pick();
+        if operation == Operation::Submit {
+            return self.submit();
+        }
+
+        match self.pick_target(is_pending) {
+            Some(message_id) => match self.targeted(operation, &message_id) {
+                Ok(future) => future,
+                Err(err) => {
+                    tracing::warn!(?err, %operation, "Failed to build PDU, sending submit_sm instead");
+                    self.submit()
+                }
+            },
+            None => self.submit(),
+        }
+    }
+
+    fn pick(&self) -> Operation {
+        // Validated with the configuration.
+        let total = self.mix.total_weight().unwrap_or(u32::MAX);
+        if total == self.mix.submit_sm {
+            return Operation::Submit;
+        }
+
+        let mut roll = rand::rng().random_range(0..total);
+        for (operation, weight) in [
+            (Operation::Submit, self.mix.submit_sm),
+            (Operation::Query, self.mix.query_sm),
+            (Operation::Replace, self.mix.replace_sm),
+            (Operation::Cancel, self.mix.cancel_sm),
+        ] {
+            if roll < weight {
+                return operation;
+            }
+            roll -= weight;
+        }
+        Operation::Submit
+    }
+
+    fn pick_target(&mut self, is_pending: impl Fn(&str) -> bool) -> Option<String> {
+        let recent_ids = &mut self.recent_ids[self.profile];
+        // Forget targets whose receipt already arrived, then choose among the rest.
+        while !recent_ids.is_empty() {
+            let idx = rand::rng().random_range(0..recent_ids.len());
+            // Without receipts nothing is ever resolved, so every ID stays a target.
+            if !self.message.request_dlr || is_pending(&recent_ids[idx]) {
+                return Some(recent_ids[idx].clone());
+            }
+            recent_ids.swap_remove_back(idx);
+        }
+        None
+    }
+
+    fn submit(&self) -> BoxFuture<'static, OperationOutcome> {
+        let client = self.client.clone();
+        let submit = self.submit_template.clone();
+        timed(Operation::Submit, self.profile, None, async move {
+            client
+                .submit_sm(submit)
+                .await
+                .map(|resp| Some(resp.message_id().as_str().to_string()))
+        })
+    }
+
+    fn targeted(
+        &self,
+        operation: Operation,
+        message_id: &str,
+    ) -> Result<BoxFuture<'static, OperationOutcome>> {
+        let side_channel = self.side_channel.clone();
+        let message = &self.message;
+        let target = Some(message_id.to_string());
+        let id = COctetString::from_str(message_id)?;
+        let source_addr = COctetString::from_str(&message.source_addr)?;
+
+        Ok(match operation {
+            Operation::Query => {
+                let pdu = QuerySm::builder()
+                    .message_id(id)
+                    .source_addr_ton(message.source_ton())
+                    .source_addr_npi(message.source_npi())
+                    .source_addr(source_addr)
+                    .build();
+                timed(operation, self.profile, target, async move {
+                    side_channel
+                        .request(pdu, CommandId::QuerySmResp)
+                        .await
+                        .map(|_| None)
+                })
+            }
+            Operation::Replace => {
+                let pdu = ReplaceSm::builder()
+                    .message_id(id)
+                    .source_addr_ton(message.source_ton())
+                    .source_addr_npi(message.source_npi())
+                    .source_addr(source_addr)
+                    .registered_delivery(if message.request_dlr {
+                        RegisteredDelivery::request_all()
+                    } else {
+                        RegisteredDelivery::default()
+                    })
+                    .short_message(OctetString::from_str(&message.body)?)
+                    .build();
+                timed(operation, self.profile, target, async move {
+                    side_channel
+                        .request(pdu, CommandId::ReplaceSmResp)
+                        .await
+                        .map(|_| None)
+                })
+            }
+            Operation::Cancel => {
+                let pdu = CancelSm::builder()
+                    .service_type(ServiceType::default())
+                    .message_id(id)
+                    .source_addr_ton(message.source_ton())
+                    .source_addr_npi(message.source_npi())
+                    .source_addr(source_addr)
+                    .dest_addr_ton(message.destination_ton())
+                    .dest_addr_npi(message.destination_npi())
+                    .destination_addr(COctetString::from_str(&message.destination_addr)?)
+                    .build();
+                timed(operation, self.profile, target, async move {
+                    side_channel
+                        .request(pdu, CommandId::CancelSmResp)
+                        .await
+                        .map(|_| None)
+                })
+            }
+            Operation::Submit => self.submit(),
+        })
+    }
+}
+
+fn timed(
+    operation: Operation,
+    profile: usize,
+    target: Option<String>,
+    request: impl Future<Output = Result<Option<String>, ClientError>> + Send + 'static,
+) -> BoxFuture<'static, OperationOutcome> {
+    async move {
+        let start = Instant::now();
+        let result = request.await;
+        OperationOutcome {
+            operation,
+            profile,
+            result,
+            target,
+            latency: start.elapsed(),
+        }
+    }
+    .boxed()
+}
@@ -0,0 +1,335 @@
+use std::{net::SocketAddr, str::FromStr, sync::Arc};
+
+use anyhow::{Context, Result, anyhow, bail};
+use futures::StreamExt;
+use rusmpp::{
+    Pdu,
+    pdus::BindReceiver,
+    types::COctetString,
+    values::{Npi, Ton},
+};
+use rusmppc::Event;
+use tokio::{
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+    time::{self, Instant},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    bind_tracker::{BindState, BindTracker, format_ms},
+    config::OutbindConfig,
+    connection::{self, EventStream},
+    events::{Inbound, SessionEnd},
+    metrics::Metrics,
+};
+
+/// Accepts SMSC connections that start with `outbind`, answers each with a
+/// `bind_receiver` and feeds what arrives into the shared `Inbound` handler,
+/// so receipts are correlated with the transmitter binds' submits. All
+/// sessions share the bind slot `idx`.
+pub async fn spawn_listener(
+    config: OutbindConfig,
+    idx: usize,
+    metrics: Arc<Metrics>,
+    tracker: Arc<BindTracker>,
+    inbound: Arc<Inbound>,
+    shutdown: CancellationToken,
+) -> Result<JoinHandle<()>> {
+    let listener = TcpListener::bind(&config.listen)
+        .await
+        .with_context(|| format!("failed to listen for outbind on {}", config.listen))?;
+    tracing::info!(listen = %config.listen, "Waiting for outbind connections");
+    let sessions = Arc::new(Sessions {
+        config,
+        idx,
+        metrics,
+        tracker,
+        inbound,
+        shutdown,
+    });
+
+    Ok(tokio::spawn(async move {
+        loop {
+            let (stream, peer) = tokio::select! {
+                _ = sessions.shutdown.cancelled() => break,
+                accepted = listener.accept() => match accepted {
+                    Ok(accepted) => accepted,
+                    Err(err) => {
+                        tracing::warn!(?err, "Failed to accept outbind connection");
+                        continue;
+                    }
+                },
+            };
+            tokio::spawn(sessions.clone().serve(stream, peer));
+        }
+    }))
+}
+
+/// Everything an outbind session needs, shared by all connections.
+struct Sessions {
+    config: OutbindConfig,
+    idx: usize,
+    metrics: Arc<Metrics>,
+    tracker: Arc<BindTracker>,
+    inbound: Arc<Inbound>,
+    shutdown: CancellationToken,
+}
+
+impl Sessions {
+    async fn serve(self: Arc<Self>, stream: TcpStream, peer: SocketAddr) {
+        let idx = self.idx;
+        self.tracker.set_state(idx, BindState::Connecting).await;
+        match self.run(stream).await {
+            Ok(()) => {}
+            Err(err) => {
+                tracing::warn!(%peer, error = ?err, "Outbind session failed");
+                self.metrics.record_outbind(false);
+                self.tracker
+                    .transition(
+                        idx,
+                        BindState::Error(err.to_string()),
+                        Some(format!("from {peer}: {err:#}")),
+                    )
+                    .await;
+            }
+        }
+    }
+
+    /// Records the session's timings and final state, like `run_bind`.
+    async fn run(&self, stream: TcpStream) -> Result<()> {
+        let config = &self.config;
+        let (client, side_channel, mut events) =
+            connection::accept(stream, config.response_timeout());
+        let bind_timeout = config.bind_timeout();
+
+        let (system_id, password) = time::timeout(bind_timeout, wait_for_outbind(&mut events))
+            .await
+            .map_err(|_| anyhow!("no outbind within {bind_timeout:?}"))??;
+        self.metrics.record_incoming("outbind".to_string());
+        if system_id != config.system_id || password != config.password {
+            client.close().await.ok();
+            bail!("outbind rejected: unexpected credentials for system_id '{system_id}'");
+        }
+
+        let start = Instant::now();
+        time::timeout(
+            bind_timeout,
+            client.bind_receiver(build_bind_rx_pdu(config)?),
+        )
+        .await
+        .map_err(|_| anyhow!("bind timed out after {bind_timeout:?}"))?
+        .context("failed to bind as RX after outbind")?;
+        let bind_latency = start.elapsed();
+        self.metrics.record_bind(bind_latency);
+        self.metrics.record_outbind(true);
+        self.tracker
+            .transition(
+                self.idx,
+                BindState::Bound,
+                Some(format!("outbind, bind {}", format_ms(bind_latency))),
+            )
+            .await;
+        let bound_at = Instant::now();
+
+        let session = self.shutdown.child_token();
+        let end = self
+            .inbound
+            .clone()
+            .spawn(self.idx, client.clone(), side_channel, events, session)
+            .await
+            .unwrap_or(SessionEnd::Finished);
+        let bound = bound_at.elapsed();
+        self.metrics.record_bound(bound);
+        let mut detail = format!("bound {:.1} s", bound.as_secs_f64());
+        let state = if end == SessionEnd::Finished {
+            let start = Instant::now();
+            if client.unbind().await.is_ok() {
+                let unbind = start.elapsed();
+                self.metrics.record_unbind(unbind);
+                detail = format!("{detail}, unbind {}", format_ms(unbind));
+            }
+            BindState::Stopped
+        } else {
+            BindState::Unbound(end.reason().to_string())
+        };
+        client.close().await.ok();
+        self.tracker.transition(self.idx, state, Some(detail)).await;
+        Ok(())
+    }
+}
+
+/// Returns the `system_id` and `password` of the first PDU, which must be
+/// an `outbind`.
+async fn wait_for_outbind(events: &mut EventStream) -> Result<(String, String)> {
+    while let Some(event) = events.next().await {
+        match event {
+            Event::Incoming(command) => match command.pdu() {
+                Some(Pdu::Outbind(outbind)) => {
+                    return Ok((outbind.system_id.to_string(), outbind.password.to_string()));
+                }
+                _ => bail!("expected outbind, got {:?}", command.id()),
+            },
+            Event::Error(err) => {
+                tracing::warn!(?err, "Background error while waiting for outbind");
+            }
+        }
+    }
+    bail!("connection closed before outbind")
+}
+
+fn build_bind_rx_pdu(config: &OutbindConfig) -> Result<BindReceiver> {
+    Ok(BindReceiver::builder()
+        .system_id(COctetString::from_str(&config.system_id)?)
+        .password(COctetString::from_str(&config.password)?)
+        .system_type(match config.system_type.as_deref() {
+            Some(system_type) => COctetString::from_str(system_type)?,
+            None => COctetString::empty(),
+        })
+        .interface_version(config.interface_version())
+        .addr_ton(Ton::default())
+        .addr_npi(Npi::default())
+        .address_range(COctetString::empty())
+        .build())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::SinkExt;
+    use rusmpp::{
+        Command, CommandId, CommandStatus,
+        pdus::{BindReceiverResp, Outbind},
+        tokio_codec::CommandCodec,
+    };
+    use tokio_util::codec::Framed;
+
+    use super::*;
+    use crate::{
+        config::{DeliverRespConfig, DlrConfig, MoConfig},
+        correlation::CorrelationStore,
+        deliver_resp::DeliverResponder,
+        dlr::DlrTracker,
+        mo::MoTracker,
+    };
+
+    type Smsc = Framed<TcpStream, CommandCodec>;
+
+    fn config() -> OutbindConfig {
+        OutbindConfig {
+            listen: "127.0.0.1:0".to_string(),
+            system_id: "smsc".to_string(),
+            password: "secret".to_string(),
+            system_type: None,
+            interface_version: 0x34,
+            bind_timeout_ms: 2_000,
+            response_timeout_ms: 2_000,
+        }
+    }
+
+    fn sessions() -> Arc<Sessions> {
+        let metrics = Arc::new(Metrics::new(&[], 1, false));
+        let dlr = DlrConfig::default();
+        let store = Arc::new(CorrelationStore::new(&dlr, metrics.clone()).unwrap());
+        let inbound = Inbound::new(
+            Arc::new(DlrTracker::new(store, metrics.clone(), &[])),
+            Arc::new(MoTracker::new(&MoConfig::default(), metrics.clone()).unwrap()),
+            Arc::new(DeliverResponder::new(
+                &DeliverRespConfig::default(),
+                metrics.clone(),
+            )),
+            metrics.clone(),
+        );
+        Arc::new(Sessions {
+            config: config(),
+            idx: 0,
+            metrics,
+            tracker: Arc::new(BindTracker::new(1)),
+            inbound: Arc::new(inbound),
+            shutdown: CancellationToken::new(),
+        })
+    }
+
+    /// Connects an SMSC to a session and sends `outbind` with `password`.
+    async fn outbind(sessions: &Arc<Sessions>, password: &str) -> (Smsc, JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let smsc = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (stream, peer) = listener.accept().await.unwrap();
+        let session = tokio::spawn(sessions.clone().serve(stream, peer));
+
+        let mut smsc = Framed::new(smsc, CommandCodec::new());
+        let pdu = Outbind::builder()
+            .system_id(COctetString::from_str("smsc").unwrap())
+            .password(COctetString::from_str(password).unwrap())
+            .build();
+        smsc.send(command(1, pdu)).await.unwrap();
+        (smsc, session)
+    }
+
+    fn command(sequence_number: u32, pdu: impl Into<Pdu>) -> Command {
+        Command::builder()
+            .status(CommandStatus::EsmeRok)
+            .sequence_number(sequence_number)
+            .pdu(pdu.into())
+    }
+
+    async fn state(sessions: &Sessions) -> BindState {
+        sessions.tracker.snapshot().await[0].state.clone()
+    }
+
+    #[tokio::test]
+    async fn outbind_is_answered_with_bind_receiver() {
+        let sessions = sessions();
+        let (mut smsc, session) = outbind(&sessions, "secret").await;
+
+        let bind = smsc.next().await.unwrap().unwrap();
+        match bind.pdu() {
+            Some(Pdu::BindReceiver(bind)) => {
+                assert_eq!(bind.system_id.to_string(), "smsc");
+                assert_eq!(bind.password.to_string(), "secret");
+            }
+            pdu => panic!("expected bind_receiver, got {pdu:?}"),
+        }
+        smsc.send(command(bind.sequence_number(), BindReceiverResp::default()))
+            .await
+            .unwrap();
+        while !matches!(state(&sessions).await, BindState::Bound) {
+            time::sleep(Duration::from_millis(5)).await;
+        }
+
+        // Stopping the run unbinds the session.
+        sessions.shutdown.cancel();
+        let unbind = smsc.next().await.unwrap().unwrap();
+        assert_eq!(unbind.id(), CommandId::Unbind);
+        smsc.send(command(unbind.sequence_number(), Pdu::UnbindResp))
+            .await
+            .unwrap();
+        session.await.unwrap();
+
+        assert!(matches!(state(&sessions).await, BindState::Stopped));
+        let snapshot = sessions.metrics.snapshot();
+        assert_eq!(snapshot.outbind_sessions, 1);
+        assert_eq!(snapshot.outbind_rejected, 0);
+    }
+
+    #[tokio::test]
+    async fn bad_credentials_are_rejected() {
+        let sessions = sessions();
+        let (mut smsc, session) = outbind(&sessions, "wrong").await;
+
+        // The connection is closed without a bind_receiver.
+        assert!(smsc.next().await.is_none());
+        session.await.unwrap();
+
+        match state(&sessions).await {
+            BindState::Error(err) => assert!(err.contains("unexpected credentials"), "{err}"),
+            state => panic!("expected an error, got {state:?}"),
+        }
+        let snapshot = sessions.metrics.snapshot();
+        assert_eq!(snapshot.outbind_sessions, 0);
+        assert_eq!(snapshot.outbind_rejected, 1);
+    }
+}
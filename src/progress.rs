@@ -9,28 +9,40 @@ use tokio::{task::JoinHandle, time};
 use tokio_util::sync::CancellationToken;
 
 use crate::{
-    bind_tracker::{BindState, BindStatus, BindTracker},
-    config::{MessageConfig, SmppConfig},
-    metrics::{BindSnapshot, Metrics},
+    bind_tracker::{BindEvent, BindState, BindStatus, BindTracker},
+    config::{AccountConfig, Config},
+    control::{ControlState, RunControl},
+    histogram::HistogramSnapshot,
+    metrics::{AccountSnapshot, BindSnapshot, Metrics},
+    operations::Operation,
+    scenario::ScenarioRunner,
+    search::CapacitySearch,
 };
 
+/// Latest bind state changes listed on the dashboard, across all binds.
+const RECENT_BIND_EVENTS: usize = 5;
+
 pub fn spawn_progress_task(
     metrics: Arc<Metrics>,
     tracker: Arc<BindTracker>,
-    smpp: Arc<SmppConfig>,
-    message: Arc<MessageConfig>,
+    config: Arc<Config>,
+    search: Option<Arc<CapacitySearch>>,
+    scenario: Option<Arc<ScenarioRunner>>,
+    control: Arc<RunControl>,
     shutdown: CancellationToken,
 ) -> JoinHandle<()> {
     tokio::spawn(async move {
         let mut throughput = ThroughputTracker::new();
+        let search = search.as_deref();
+        let scenario = scenario.as_deref();
         loop {
             tokio::select! {
                 _ = shutdown.cancelled() => {
-                    render(&metrics, &tracker, &smpp, &message, &mut throughput).await;
+                    render(&metrics, &tracker, &config, search, scenario, &control, &mut throughput).await;
                     break;
                 }
                 _ = time::sleep(Duration::from_millis(500)) => {
-                    render(&metrics, &tracker, &smpp, &message, &mut throughput).await;
+                    render(&metrics, &tracker, &config, search, scenario, &control, &mut throughput).await;
                 }
             }
         }
@@ -69,14 +81,21 @@ impl Tracker {
 }
 
 struct ThroughputTracker {
+    /// When the dashboard started, just before the first bind.
+    started: Instant,
     total: Tracker,
+    mo: Tracker,
+    per_account: Vec<Tracker>,
     per_bind: Vec<Tracker>,
 }
 
 impl ThroughputTracker {
     fn new() -> Self {
         Self {
+            started: Instant::now(),
             total: Tracker::new(),
+            mo: Tracker::new(),
+            per_account: Vec::new(),
             per_bind: Vec::new(),
         }
     }
@@ -85,6 +104,17 @@ impl ThroughputTracker {
         self.total.compute_tps(attempts)
     }
 
+    fn mo_tps(&mut self, received: u64) -> f64 {
+        self.mo.compute_tps(received)
+    }
+
+    fn account_tps(&mut self, idx: usize, attempts: u64) -> f64 {
+        if idx >= self.per_account.len() {
+            self.per_account.resize_with(idx + 1, Tracker::new);
+        }
+        self.per_account[idx].compute_tps(attempts)
+    }
+
     fn bind_tps(&mut self, idx: usize, attempts: u64) -> f64 {
         if idx >= self.per_bind.len() {
             self.per_bind.resize_with(idx + 1, Tracker::new);
@@ -96,11 +126,19 @@ impl ThroughputTracker {
 async fn render(
     metrics: &Metrics,
     tracker: &BindTracker,
-    smpp: &SmppConfig,
-    message: &MessageConfig,
+    config: &Config,
+    search: Option<&CapacitySearch>,
+    scenario: Option<&ScenarioRunner>,
+    control: &RunControl,
     throughput: &mut ThroughputTracker,
 ) {
-    let snapshot = metrics.snapshot();
+    // Until the warm-up ends everything lands in its own bucket.
+    let warming_up = metrics.warming_up();
+    let snapshot = if warming_up {
+        metrics.warmup_snapshot()
+    } else {
+        metrics.snapshot()
+    };
     let statuses = tracker.snapshot().await;
     let total_tps = throughput.total_tps(snapshot.attempts);
 
@@ -116,7 +154,9 @@ async fn render(
         (snapshot.err as f64 / snapshot.attempts as f64) * 100.0
     };
 
-    let mut stdout = std::io::stdout();
+    // Built in memory so it can go out with \r\n, which raw mode (keyboard
+    // controls) no longer adds.
+    let mut stdout = Vec::new();
     let _ = write!(stdout, "\x1B[2J\x1B[H"); // Clear screen + move cursor home.
 
     writeln!(stdout, "{}", "SMPP Load Test Dashboard".bold()).ok();
@@ -136,12 +176,311 @@ async fn render(
         .join(" ");
 
     writeln!(stdout, "Bind states: {bind_bar}").ok();
-    writeln!(stdout, "").ok();
+    let state = control.state();
+    let (active_binds, bind_slots) = control.bind_counts();
+    writeln!(
+        stdout,
+        "Controls: {} | rate {}{} | binds {active_binds}/{bind_slots}{} | [+/-] rate [p] pause [b/B] add/remove bind [q] stop",
+        if state.paused {
+            "PAUSED".yellow().to_string()
+        } else {
+            "running".green().to_string()
+        },
+        state.rate_percent,
+        if control.rate_locked() { "% (search)" } else { "%" },
+        control
+            .current_stage()
+            .map_or_else(String::new, |stage| format!(" | stage {stage}"))
+    )
+    .ok();
+    if warming_up {
+        let left = config
+            .warmup()
+            .unwrap_or_default()
+            .saturating_sub(throughput.started.elapsed());
+        writeln!(
+            stdout,
+            "{} {}s left, not counted in the final report",
+            "Warm-up:".yellow(),
+            left.as_secs()
+        )
+        .ok();
+    }
+    writeln!(stdout).ok();
+
+    writeln!(
+        stdout,
+        "Messages: {} | OK: {} ({success_pct:.1}%) | Err: {} ({error_pct:.1}%)",
+        snapshot.attempts.to_string().bold(),
+        snapshot.ok.green(),
+        snapshot.err.red()
+    )
+    .ok();
+    writeln!(
+        stdout,
+        "Average latency: {:.2} ms | Total TPS: {:.1}",
+        snapshot.avg_latency_ms, total_tps
+    )
+    .ok();
+    if let Some(search) = search {
+        let result = search.result();
+        let last = result.steps.last().map_or_else(
+            || "-".to_string(),
+            |step| {
+                format!(
+                    "{} TPS {}",
+                    step.target_tps,
+                    if step.passed() { "passed" } else { "failed" }
+                )
+            },
+        );
+        writeln!(
+            stdout,
+            "Capacity search: step {} at {} | last {} | sustainable {}",
+            result.steps.len() + usize::from(result.current_tps.is_some()),
+            result
+                .current_tps
+                .map_or_else(|| "done".to_string(), |tps| format!("{tps} TPS")),
+            last,
+            result
+                .sustainable_tps
+                .map_or_else(|| "-".to_string(), |tps| format!("{tps} TPS"))
+        )
+        .ok();
+    }
+    if let Some(scenario) = scenario {
+        let result = scenario.result();
+        let done = result.phases.len();
+        writeln!(
+            stdout,
+            "Scenario: phase {} of {} | {} | failed {}",
+            (done + usize::from(result.current.is_some())).min(result.total_phases),
+            result.total_phases,
+            result.current.as_deref().unwrap_or("done"),
+            if result.failed() > 0 {
+                result.failed().red().to_string()
+            } else {
+                "0".to_string()
+            }
+        )
+        .ok();
+    }
+    let enquire_link = snapshot.enquire_link();
+    writeln!(
+        stdout,
+        "Enquire link: OK {} | Timeouts {} | Err {} | Avg RTT {:.2} ms",
+        enquire_link.ok, enquire_link.timeouts, enquire_link.errors, enquire_link.avg_rtt_ms
+    )
+    .ok();
+
+    writeln!(
+        stdout,
+        "DLR index: pending {} | peak {} | missing {} | evicted {} | orphan {} | malformed {} | duplicate {} | TLV/text conflict {}",
+        snapshot.dlr_pending,
+        snapshot.dlr_pending_peak,
+        snapshot.per_bind.iter().map(|bind| bind.dlr_missing).sum::<u64>(),
+        snapshot.per_bind.iter().map(|bind| bind.dlr_evicted).sum::<u64>(),
+        snapshot.per_bind.iter().map(|bind| bind.dlr_orphan).sum::<u64>(),
+        snapshot.per_bind.iter().map(|bind| bind.dlr_malformed).sum::<u64>(),
+        snapshot.per_bind.iter().map(|bind| bind.dlr_duplicate).sum::<u64>(),
+        snapshot.per_bind.iter().map(|bind| bind.dlr_conflict).sum::<u64>()
+    )
+    .ok();
+
+    let delays = &snapshot.delays;
+    writeln!(
+        stdout,
+        "Delay p50/p95/p99 (ms): submit→resp {} | resp→DELIVRD {} | resp→UNDELIV/EXPIRED {}",
+        format_percentiles(&delays.submit_to_resp),
+        format_percentiles(&delays.resp_to_delivered),
+        format_percentiles(&delays.resp_to_undelivered)
+    )
+    .ok();
     writeln!(
         stdout,
-        "Target: {}:{} | system_id={} | password={} | system_type={} | bind_type={}",
+        "Delivery p50/p95/p99 (ms): submit→DELIVRD {} ({}) | submit→UNDELIV/EXPIRED {} ({})",
+        format_percentiles(&delays.submit_to_delivered),
+        delays.submit_to_delivered.count,
+        format_percentiles(&delays.submit_to_undelivered),
+        delays.submit_to_undelivered.count
+    )
+    .ok();
+
+    let error_codes: Vec<String> = snapshot
+        .dlr_error_codes
+        .iter()
+        .take(5)
+        .map(|(code, count)| format!("{code}×{count}"))
+        .collect();
+    writeln!(
+        stdout,
+        "DLR receipts: intermediate {} | illegal transitions {} | SMSC submit→done avg {:.0} ms | invalid {} | err codes: {}",
+        snapshot.per_bind.iter().map(|bind| bind.dlr_intermediate).sum::<u64>(),
+        snapshot.per_bind.iter().map(|bind| bind.dlr_illegal_transition).sum::<u64>(),
+        snapshot.avg_smsc_delay_ms(),
+        snapshot.per_bind.iter().map(|bind| bind.dlr_invalid).sum::<u64>(),
+        if error_codes.is_empty() { "-".to_string() } else { error_codes.join(", ") }
+    )
+    .ok();
+
+    let mo_received: u64 = snapshot.per_bind.iter().map(|bind| bind.mo_received).sum();
+    let mo_tps = throughput.mo_tps(mo_received);
+    if mo_received > 0 {
+        let mo_bytes: u64 = snapshot.per_bind.iter().map(|bind| bind.mo_bytes).sum();
+        let data_codings: Vec<String> = snapshot
+            .mo_data_coding
+            .iter()
+            .map(|(data_coding, count)| format!("{data_coding:#04x}×{count}"))
+            .collect();
+        writeln!(
+            stdout,
+            "MO: received {} | TPS {:.1} | avg body {:.0} B (max {}) | pattern mismatches {} | data_coding {}",
+            mo_received,
+            mo_tps,
+            mo_bytes as f64 / mo_received as f64,
+            snapshot.mo_max_bytes,
+            snapshot.per_bind.iter().map(|bind| bind.mo_mismatch).sum::<u64>(),
+            data_codings.join(", ")
+        )
+        .ok();
+    }
+
+    let open_loop = &snapshot.open_loop;
+    if open_loop.scheduled > 0 {
+        writeln!(
+            stdout,
+            "Open loop: scheduled {} | delayed by full window {} ({:.1}%) | dropped {} | backlog peak {} | send lag p50/p95/p99 {} ms",
+            open_loop.scheduled,
+            open_loop.delayed,
+            open_loop.delayed as f64 / open_loop.scheduled as f64 * 100.0,
+            open_loop.dropped,
+            open_loop.backlog_peak,
+            format_percentiles(&open_loop.send_lag)
+        )
+        .ok();
+    }
+
+    let deliver_resp = &snapshot.deliver_resp;
+    if deliver_resp.rejected + deliver_resp.dropped + deliver_resp.redeliveries > 0 {
+        writeln!(
+            stdout,
+            "deliver_sm_resp: OK {} | rejected {} | dropped {} | redeliveries {} ({} msgs, recovered {}) | retry interval p50/p95/p99 {} ms",
+            deliver_resp.accepted,
+            deliver_resp.rejected,
+            deliver_resp.dropped,
+            deliver_resp.redeliveries,
+            deliver_resp.redelivered_messages,
+            deliver_resp.recovered,
+            format_percentiles(&deliver_resp.retry_interval)
+        )
+        .ok();
+    }
+
+    if !snapshot.incoming.is_empty() {
+        let incoming: Vec<String> = snapshot
+            .incoming
+            .iter()
+            .map(|(command, count)| format!("{command} {count}"))
+            .collect();
+        let sessions = snapshot.sessions();
+        writeln!(
+            stdout,
+            "Incoming: {} | Sessions: server unbinds {} | connection lost {} | reconnects {}",
+            incoming.join(", "),
+            sessions.server_unbinds,
+            sessions.disconnects,
+            sessions.reconnects
+        )
+        .ok();
+    }
+
+    let lifecycle = &snapshot.lifecycle;
+    writeln!(
+        stdout,
+        "Session p50/p95/p99 (ms): connect {} | bind {} | unbind {} | avg time bound {:.1} s",
+        format_percentiles(&lifecycle.connect),
+        format_percentiles(&lifecycle.bind),
+        format_percentiles(&lifecycle.unbind),
+        lifecycle.bound.mean_ms / 1000.0
+    )
+    .ok();
+    let mut events: Vec<(usize, &BindEvent)> = statuses
+        .iter()
+        .enumerate()
+        .flat_map(|(idx, status)| status.history.iter().map(move |event| (idx, event)))
+        .collect();
+    events.sort_by_key(|(_, event)| event.at);
+    for (idx, event) in events.iter().rev().take(RECENT_BIND_EVENTS).rev() {
+        writeln!(stdout, "  bind {idx}: {event}").ok();
+    }
+
+    if let (Some(outbind), Some(outbind_idx)) = (&config.outbind, config.outbind_bind()) {
+        let state = statuses
+            .get(outbind_idx)
+            .map_or_else(|| "-".to_string(), |status| status.state.to_string());
+        writeln!(
+            stdout,
+            "Outbind {}: {} | sessions {} | rejected {}",
+            outbind.listen, state, snapshot.outbind_sessions, snapshot.outbind_rejected
+        )
+        .ok();
+    }
+
+    let operations: Vec<String> = snapshot
+        .operations
+        .iter()
+        .filter(|op| op.attempts > 0 && op.operation != Operation::Submit)
+        .map(|op| {
+            format!(
+                "{} OK {} Err {} Avg {:.2} ms",
+                op.operation, op.ok, op.err, op.avg_latency_ms
+            )
+        })
+        .collect();
+    if !operations.is_empty() {
+        writeln!(stdout, "Other commands: {}", operations.join(" | ")).ok();
+    }
+
+    for (account_idx, account) in config.accounts.iter().enumerate() {
+        let Some(account_snapshot) = snapshot.per_account.get(account_idx) else {
+            continue;
+        };
+        let account_tps = throughput.account_tps(account_idx, account_snapshot.attempts);
+        writeln!(stdout).ok();
+        render_account_header(&mut stdout, account, account_snapshot, account_tps, state).ok();
+
+        for (idx, status) in statuses.iter().enumerate() {
+            let bind_snapshot = snapshot.per_bind.get(idx).copied().unwrap_or_default();
+            if bind_snapshot.account != account_idx {
+                continue;
+            }
+            let bind_tps = throughput.bind_tps(idx, bind_snapshot.attempts);
+            render_bind_line(&mut stdout, idx, status, bind_snapshot, bind_tps).ok();
+        }
+    }
+
+    let frame = String::from_utf8_lossy(&stdout).replace('\n', "\r\n");
+    let mut stdout = std::io::stdout();
+    stdout.write_all(frame.as_bytes()).ok();
+    stdout.flush().ok();
+}
+
+fn render_account_header(
+    stdout: &mut impl Write,
+    account: &AccountConfig,
+    snapshot: &AccountSnapshot,
+    tps: f64,
+    control: ControlState,
+) -> std::io::Result<()> {
+    let smpp = &account.smpp;
+    let message = &account.message;
+    writeln!(
+        stdout,
+        "{} -> {}:{}{} | system_id={} | password={} | system_type={} | bind_type={}",
+        format!("Account {}", account.name).bold(),
         smpp.host,
         smpp.port,
+        if smpp.tls_enabled() { " (TLS)" } else { "" },
         smpp.system_id,
         smpp.password,
         smpp.system_type
@@ -151,51 +490,40 @@ async fn render(
         match smpp.bind_type {
             crate::config::BindType::Tx => "TX",
             crate::config::BindType::Trx => "TRX",
+            crate::config::BindType::Rx => "RX",
         }
-    )
-    .ok();
-    writeln!(stdout, "").ok();
-
+    )?;
     writeln!(
         stdout,
-        "Source: {} (TON {} / NPI {}) | Destination: {} (TON {} / NPI {})",
+        "  Source: {} (TON {} / NPI {}) | Destination: {} (TON {} / NPI {})",
         message.source_addr,
         message.source_ton,
         message.source_npi,
         message.destination_addr,
         message.destination_ton,
         message.destination_npi
-    )
-    .ok();
-    writeln!(stdout, "").ok();
-
+    )?;
     writeln!(
         stdout,
-        "Messages: {} | OK: {} ({success_pct:.1}%) | Err: {} ({error_pct:.1}%)",
-        snapshot.attempts.to_string().bold(),
+        "  Binds {} | Messages {} | OK {} | Err {} | TPS {:.1}{} | Avg {:.2} ms | DLR {} (DEL {} / Failed {})",
+        snapshot.binds,
+        snapshot.attempts,
         snapshot.ok.green(),
-        snapshot.err.red()
-    )
-    .ok();
-    writeln!(
-        stdout,
-        "Average latency: {:.2} ms | Total TPS: {:.1}",
-        snapshot.avg_latency_ms, total_tps
+        snapshot.err.red(),
+        tps,
+        account
+            .load
+            .total_tps()
+            .map_or_else(String::new, |target| format!(" / {}", control.scale_tps(target))),
+        snapshot.avg_latency_ms,
+        snapshot.dlr_received,
+        snapshot.dlr_delivered,
+        snapshot.dlr_failed
     )
-    .ok();
-
-    writeln!(stdout, "\nPer-bind stats:").ok();
-    for (idx, status) in statuses.iter().enumerate() {
-        let bind_snapshot = snapshot.per_bind.get(idx).copied().unwrap_or_default();
-        let bind_tps = throughput.bind_tps(idx, bind_snapshot.attempts);
-        render_bind_line(&mut stdout, idx, status, bind_snapshot, bind_tps).ok();
-    }
-
-    stdout.flush().ok();
 }
 
 fn render_bind_line(
-    stdout: &mut std::io::Stdout,
+    stdout: &mut impl Write,
     idx: usize,
     status: &BindStatus,
     snapshot: BindSnapshot,
@@ -213,7 +541,7 @@ fn render_bind_line(
     };
     writeln!(
         stdout,
-        "{} -> TPS {:>8.1} | Avg {:>6.2} ms | OK {:>8} | Err {:>8} | DLR {:>8} ({:.1}%) Avg {:>6.2} ms | [ENR {:>5} DEL {:>5} EXP {:>5} DELT {:>5} UNDL {:>5} ACC {:>5} UNK {:>5}] | Last ID {}",
+        "{} -> TPS {:>8.1} | Avg {:>6.2} ms | OK {:>8} | Err {:>8} | DLR {:>8} ({:.1}%) Avg {:>6.2} ms | [ENR {:>5} DEL {:>5} EXP {:>5} DELT {:>5} UNDL {:>5} ACC {:>5} UNK {:>5} MISS {:>5}] | MO {:>6} | Last ID {}",
         format_state(idx, &status.state),
         tps,
         snapshot.avg_latency_ms,
@@ -229,15 +557,30 @@ fn render_bind_line(
         snapshot.dlr_failed,
         snapshot.dlr_accepted,
         snapshot.dlr_unknown,
+        snapshot.dlr_missing,
+        snapshot.mo_received,
         last_id
     )
 }
 
+pub fn format_percentiles(histogram: &HistogramSnapshot) -> String {
+    if histogram.count == 0 {
+        return "-".to_string();
+    }
+    format!(
+        "{:.1}/{:.1}/{:.1}",
+        histogram.p50_ms, histogram.p95_ms, histogram.p99_ms
+    )
+}
+
 fn format_state(idx: usize, state: &BindState) -> String {
     match state {
         BindState::Pending => format!("[{}]", format!("P{idx}").dimmed()),
+        BindState::Idle => format!("[{}]", format!("I{idx}").blue()),
         BindState::Connecting => format!("[{}]", format!("C{idx}").yellow()),
         BindState::Bound => format!("[{}]", format!("B{idx}").green()),
+        BindState::Unbound(_) => format!("[{}]", format!("U{idx}").magenta()),
+        BindState::Stopped => format!("[{}]", format!("S{idx}").dimmed()),
         BindState::Error(err) => {
             let trimmed = if err.len() > 24 {
                 format!("{}…", &err[..24])
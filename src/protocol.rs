@@ -0,0 +1,169 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines},
+    net::tcp::{OwnedReadHalf, OwnedWriteHalf},
+};
+
+use crate::metrics::MetricsSnapshot;
+
+/// Coordinator to agent. Messages are JSON objects, one per line, tagged
+/// with their `type`.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ToAgent {
+    /// The coordinator's configuration file and which share of it to run.
+    /// `token` must match the one the agent was started with.
+    Prepare {
+        token: String,
+        config: String,
+        index: usize,
+        count: usize,
+    },
+    /// Sent to every agent once all of them are ready.
+    Start,
+    Stop,
+}
+
+/// Agent to coordinator.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ToCoordinator {
+    /// The share was accepted; `binds` of them will be started.
+    Ready {
+        binds: usize,
+    },
+    /// Sent periodically while the run is going.
+    Progress {
+        metrics: MetricsSnapshot,
+        bound: usize,
+    },
+    Finished {
+        metrics: MetricsSnapshot,
+        elapsed_secs: f64,
+    },
+    Failed {
+        error: String,
+    },
+}
+
+/// Reads newline-delimited messages from one side of the connection.
+pub struct Reader {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+}
+
+impl Reader {
+    pub fn new(half: OwnedReadHalf) -> Self {
+        Self {
+            lines: BufReader::new(half).lines(),
+        }
+    }
+
+    /// `None` once the peer has closed the connection. Cancel safe.
+    pub async fn next<T: DeserializeOwned>(&mut self) -> Result<Option<T>> {
+        match self.lines.next_line().await? {
+            Some(line) => Ok(Some(
+                serde_json::from_str(&line).context("invalid message from peer")?,
+            )),
+            None => Ok(None),
+        }
+    }
+}
+
+pub async fn send<T: Serialize>(writer: &mut OwnedWriteHalf, message: &T) -> Result<()> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    writer.write_all(&line).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::{TcpListener, TcpStream};
+
+    use super::*;
+
+    /// Both ends of a loopback connection.
+    async fn connection() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        (client, server)
+    }
+
+    #[tokio::test]
+    async fn messages_round_trip_one_per_line() {
+        let (coordinator, agent) = connection().await;
+        let (_, mut writer) = coordinator.into_split();
+        let (reader, _agent_writer) = agent.into_split();
+        let mut reader = Reader::new(reader);
+
+        let prepare = ToAgent::Prepare {
+            token: "secret".to_string(),
+            config: "[smpp]\nhost = \"127.0.0.1\"\n".to_string(),
+            index: 1,
+            count: 3,
+        };
+        send(&mut writer, &prepare).await.unwrap();
+        send(&mut writer, &ToAgent::Start).await.unwrap();
+        drop(writer);
+
+        match reader.next().await.unwrap() {
+            Some(ToAgent::Prepare {
+                token,
+                config,
+                index,
+                count,
+            }) => {
+                assert_eq!(token, "secret");
+                assert_eq!(config, "[smpp]\nhost = \"127.0.0.1\"\n");
+                assert_eq!((index, count), (1, 3));
+            }
+            _ => panic!("expected prepare"),
+        }
+        assert!(matches!(reader.next().await.unwrap(), Some(ToAgent::Start)));
+        assert!(reader.next::<ToAgent>().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn messages_are_tagged_with_their_type() {
+        let (coordinator, agent) = connection().await;
+        let (reader, _) = coordinator.into_split();
+        let (_, mut writer) = agent.into_split();
+        let mut reader = Reader::new(reader);
+
+        writer
+            .write_all(
+                b"{\"type\":\"ready\",\"binds\":4}\n{\"type\":\"failed\",\"error\":\"boom\"}\n",
+            )
+            .await
+            .unwrap();
+        assert!(matches!(
+            reader.next().await.unwrap(),
+            Some(ToCoordinator::Ready { binds: 4 })
+        ));
+        match reader.next().await.unwrap() {
+            Some(ToCoordinator::Failed { error }) => assert_eq!(error, "boom"),
+            _ => panic!("expected failed"),
+        }
+    }
+
+    #[tokio::test]
+    async fn garbage_is_an_error() {
+        let (coordinator, agent) = connection().await;
+        let (reader, _) = coordinator.into_split();
+        let (_, mut writer) = agent.into_split();
+        let mut reader = Reader::new(reader);
+
+        writer.write_all(b"{\"type\":\"dance\"}\n").await.unwrap();
+        let Err(err) = reader.next::<ToCoordinator>().await else {
+            panic!("expected an error");
+        };
+        assert!(
+            format!("{err:#}").contains("invalid message from peer"),
+            "{err:#}"
+        );
+    }
+}
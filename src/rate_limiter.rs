@@ -0,0 +1,255 @@
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use rand::Rng;
+use tokio::time::Instant;
+
+use crate::config::Arrival;
+
+/// How much unused rate may accumulate, so binds that were briefly blocked
+/// can catch up without the aggregate bursting far above the target.
+const MAX_BURST: Duration = Duration::from_millis(100);
+/// Keeps a pathological exponential sample (or a tiny rate) from stalling a
+/// bind for longer than any realistic test.
+const MAX_INTERVAL_SECS: f64 = 3600.0;
+
+/// Token bucket shared by all binds of an account to hold `load.total_tps`
+/// regardless of how many of them are currently bound.
+#[derive(Debug)]
+pub struct TokenBucket {
+    state: Mutex<BucketState>,
+    /// Open-loop binds currently pacing themselves at a share of the rate.
+    sharers: AtomicUsize,
+}
+
+#[derive(Debug)]
+struct BucketState {
+    rate: f64,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(tps: f64) -> Self {
+        Self {
+            state: Mutex::new(BucketState {
+                rate: tps,
+                tokens: 0.0,
+                refilled_at: Instant::now(),
+            }),
+            sharers: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.state.lock().expect("token bucket lock poisoned").rate
+    }
+
+    /// Changes the rate from now on; tokens already accrued are kept.
+    pub fn set_rate(&self, tps: f64) {
+        let mut state = self.state.lock().expect("token bucket lock poisoned");
+        state.refill(Instant::now());
+        state.rate = tps.max(0.0);
+    }
+
+    /// Takes up to `wanted` whole tokens. Tokens a bind can't use (because
+    /// its in-flight window is full) stay in the bucket for the others.
+    pub fn take(&self, wanted: usize) -> usize {
+        if wanted == 0 {
+            return 0;
+        }
+        let mut state = self.state.lock().expect("token bucket lock poisoned");
+        state.refill(Instant::now());
+        let taken = (state.tokens.floor() as usize).min(wanted);
+        state.tokens -= taken as f64;
+        taken
+    }
+
+    /// Registers an open-loop bind, which follows its schedule instead of
+    /// taking tokens, until the returned share is dropped.
+    pub fn share(self: &Arc<Self>) -> BucketShare {
+        self.sharers.fetch_add(1, Ordering::Relaxed);
+        BucketShare {
+            bucket: self.clone(),
+        }
+    }
+}
+
+/// An open-loop bind's claim on its account's bucket.
+#[derive(Debug)]
+pub struct BucketShare {
+    bucket: Arc<TokenBucket>,
+}
+
+impl BucketShare {
+    /// The bucket's current rate split evenly across the binds sharing it,
+    /// so it follows the controls, the capacity search and binds that come
+    /// and go.
+    pub fn tps(&self) -> f64 {
+        let sharers = self.bucket.sharers.load(Ordering::Relaxed).max(1);
+        self.bucket.rate() / sharers as f64
+    }
+}
+
+impl Drop for BucketShare {
+    fn drop(&mut self) {
+        self.bucket.sharers.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl BucketState {
+    fn refill(&mut self, now: Instant) {
+        let capacity = (self.rate * MAX_BURST.as_secs_f64()).max(1.0);
+        let elapsed = now.saturating_duration_since(self.refilled_at);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.rate).min(capacity);
+        self.refilled_at = now;
+    }
+}
+
+/// Inter-arrival times of the open-loop generator for one bind.
+#[derive(Debug, Clone, Copy)]
+pub struct ArrivalSchedule {
+    arrival: Arrival,
+    tps: f64,
+    mean_secs: f64,
+}
+
+impl ArrivalSchedule {
+    pub fn new(arrival: Arrival, tps: f64) -> Self {
+        Self {
+            arrival,
+            tps,
+            mean_secs: 1.0 / tps.max(f64::MIN_POSITIVE),
+        }
+    }
+
+    /// Changes the target rate; returns whether it differs from the old one.
+    pub fn set_tps(&mut self, tps: f64) -> bool {
+        if tps == self.tps {
+            return false;
+        }
+        *self = Self::new(self.arrival, tps);
+        true
+    }
+
+    pub fn next_interval(&self) -> Duration {
+        let secs = match self.arrival {
+            Arrival::Poisson => {
+                let sample: f64 = rand::rng().random_range(0.0..1.0);
+                -self.mean_secs * (1.0 - sample).ln()
+            }
+            Arrival::Batched | Arrival::Uniform => self.mean_secs,
+        };
+        Duration::from_secs_f64(secs.min(MAX_INTERVAL_SECS))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::time;
+
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn bucket_accrues_at_its_rate() {
+        let bucket = TokenBucket::new(1000.0);
+        assert_eq!(bucket.take(10), 0);
+        time::advance(Duration::from_millis(10)).await;
+        assert_eq!(bucket.take(3), 3);
+        // Tokens a bind can't use stay for the others.
+        assert_eq!(bucket.take(10), 7);
+        assert_eq!(bucket.take(10), 0);
+        assert_eq!(bucket.take(0), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn bucket_caps_bursts() {
+        let bucket = TokenBucket::new(100.0);
+        time::advance(Duration::from_secs(10)).await;
+        assert_eq!(bucket.take(1000), 10);
+
+        // At least one token accrues, however low the rate.
+        let slow = TokenBucket::new(1.0);
+        time::advance(Duration::from_secs(10)).await;
+        assert_eq!(slow.take(10), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn set_rate_keeps_accrued_tokens() {
+        let bucket = TokenBucket::new(100.0);
+        time::advance(Duration::from_millis(50)).await;
+        bucket.set_rate(60.0);
+        assert_eq!(bucket.take(10), 5);
+
+        bucket.set_rate(-5.0);
+        assert_eq!(bucket.rate(), 0.0);
+        time::advance(Duration::from_secs(1)).await;
+        assert_eq!(bucket.take(10), 0);
+
+        bucket.set_rate(200.0);
+        time::advance(Duration::from_millis(50)).await;
+        assert_eq!(bucket.take(100), 10);
+    }
+
+    #[test]
+    fn shares_split_the_rate() {
+        let bucket = Arc::new(TokenBucket::new(90.0));
+        let first = bucket.share();
+        assert_eq!(first.tps(), 90.0);
+        let second = bucket.share();
+        let third = bucket.share();
+        assert_eq!(first.tps(), 30.0);
+        drop(third);
+        assert_eq!(second.tps(), 45.0);
+        bucket.set_rate(30.0);
+        assert_eq!(first.tps(), 15.0);
+        drop(second);
+        assert_eq!(first.tps(), 30.0);
+    }
+
+    #[test]
+    fn even_arrivals() {
+        for arrival in [Arrival::Uniform, Arrival::Batched] {
+            let schedule = ArrivalSchedule::new(arrival, 50.0);
+            for _ in 0..10 {
+                assert_eq!(schedule.next_interval(), Duration::from_millis(20));
+            }
+        }
+    }
+
+    #[test]
+    fn idle_schedule_is_capped() {
+        for tps in [0.0, 1e-9] {
+            let schedule = ArrivalSchedule::new(Arrival::Uniform, tps);
+            assert_eq!(
+                schedule.next_interval(),
+                Duration::from_secs_f64(MAX_INTERVAL_SECS)
+            );
+        }
+    }
+
+    #[test]
+    fn poisson_arrivals_average_the_rate() {
+        const SAMPLES: u32 = 20_000;
+        let schedule = ArrivalSchedule::new(Arrival::Poisson, 100.0);
+        let intervals: Vec<Duration> = (0..SAMPLES).map(|_| schedule.next_interval()).collect();
+        let mean = intervals.iter().sum::<Duration>().as_secs_f64() / f64::from(SAMPLES);
+        assert!((mean - 0.01).abs() < 0.0005, "mean interval {mean}");
+        // Exponential gaps vary: some well below the mean, some well above.
+        assert!(intervals.iter().any(|gap| *gap < Duration::from_millis(2)));
+        assert!(intervals.iter().any(|gap| *gap > Duration::from_millis(30)));
+    }
+
+    #[test]
+    fn set_tps_reports_changes() {
+        let mut schedule = ArrivalSchedule::new(Arrival::Uniform, 10.0);
+        assert!(!schedule.set_tps(10.0));
+        assert!(schedule.set_tps(20.0));
+        assert_eq!(schedule.next_interval(), Duration::from_millis(50));
+    }
+}
@@ -0,0 +1,486 @@
+use std::{io::Write, time::Duration};
+
+use owo_colors::OwoColorize;
+
+use crate::{
+    bind_tracker::{BindState, BindStatus},
+    churn::ChurnSnapshot,
+    control::StageSnapshot,
+    metrics::MetricsSnapshot,
+    scenario::ScenarioResult,
+    search::SearchResult,
+};
+
+/// Prints the end-of-run summary once every bind has stopped.
+pub fn print_summary(snapshot: &MetricsSnapshot, elapsed: Duration) {
+    let mut stdout = std::io::stdout();
+    write_summary(&mut stdout, snapshot, elapsed).ok();
+    stdout.flush().ok();
+}
+
+/// Prints what was recorded during the warm-up, which the summary leaves out.
+pub fn print_warmup(snapshot: &MetricsSnapshot, warmup: Duration) {
+    let mut stdout = std::io::stdout();
+    write_warmup(&mut stdout, snapshot, warmup).ok();
+    stdout.flush().ok();
+}
+
+fn write_warmup(
+    out: &mut impl Write,
+    snapshot: &MetricsSnapshot,
+    warmup: Duration,
+) -> std::io::Result<()> {
+    writeln!(out, "\n{}", "Warm-up (excluded from the report)".bold())?;
+    writeln!(
+        out,
+        "Duration: {:.1} s | Messages: {} | OK: {} | Err: {} | Avg latency: {:.2} ms | p99: {:.1} ms",
+        warmup.as_secs_f64(),
+        snapshot.attempts,
+        snapshot.ok.green(),
+        snapshot.err.red(),
+        snapshot.avg_latency_ms,
+        snapshot.delays.submit_to_resp.p99_ms
+    )
+}
+
+/// Prints the connection churn totals, latencies and failure reasons.
+pub fn print_churn(snapshot: &ChurnSnapshot, elapsed: Duration) {
+    let mut stdout = std::io::stdout();
+    write_churn(&mut stdout, snapshot, elapsed).ok();
+    stdout.flush().ok();
+}
+
+fn write_churn(
+    out: &mut impl Write,
+    snapshot: &ChurnSnapshot,
+    elapsed: Duration,
+) -> std::io::Result<()> {
+    let secs = elapsed.as_secs_f64();
+    let finished = snapshot.completed + snapshot.failed;
+    let rate = if secs > 0.0 {
+        finished as f64 / secs
+    } else {
+        0.0
+    };
+
+    writeln!(out)?;
+    writeln!(out, "{}", "Churn report".bold())?;
+    writeln!(out, "{}", "-".repeat(80))?;
+    writeln!(
+        out,
+        "Duration: {secs:.1} s | Cycles: {} | OK: {} | Failed: {} | Skipped (all tasks busy): {} | Avg {rate:.1} cycles/s",
+        snapshot.started,
+        snapshot.completed.green(),
+        snapshot.failed.red(),
+        snapshot.skipped
+    )?;
+    if snapshot.submit_ok + snapshot.submit_err > 0 {
+        writeln!(
+            out,
+            "Messages: OK {} | Err {}",
+            snapshot.submit_ok.green(),
+            snapshot.submit_err.red()
+        )?;
+    }
+
+    writeln!(
+        out,
+        "\n  {:<24} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+        "", "count", "mean ms", "p50 ms", "p95 ms", "p99 ms", "max ms"
+    )?;
+    for (label, histogram) in [
+        ("TCP connect", &snapshot.connect),
+        ("bind→bind_resp", &snapshot.bind),
+        ("unbind→unbind_resp", &snapshot.unbind),
+    ] {
+        writeln!(
+            out,
+            "  {label:<24} {:>10} {:>10.2} {:>10.2} {:>10.2} {:>10.2} {:>10.2}",
+            histogram.count,
+            histogram.mean_ms,
+            histogram.p50_ms,
+            histogram.p95_ms,
+            histogram.p99_ms,
+            histogram.max_ms
+        )?;
+    }
+
+    if !snapshot.failures.is_empty() {
+        writeln!(out, "\nFailures:")?;
+        for (reason, count) in &snapshot.failures {
+            writeln!(out, "  {reason:<32} {count:>10}")?;
+        }
+    }
+    Ok(())
+}
+
+/// Prints the state history of every bind that was interrupted or failed;
+/// binds that bound once and were stopped by us are only counted.
+pub fn print_bind_history(statuses: &[BindStatus]) {
+    let mut stdout = std::io::stdout();
+    write_bind_history(&mut stdout, statuses).ok();
+    stdout.flush().ok();
+}
+
+fn write_bind_history(out: &mut impl Write, statuses: &[BindStatus]) -> std::io::Result<()> {
+    let troubled = |status: &BindStatus| {
+        status
+            .history
+            .iter()
+            .any(|event| matches!(event.state, BindState::Unbound(_) | BindState::Error(_)))
+    };
+    let uneventful = statuses.iter().filter(|status| !troubled(status)).count();
+    writeln!(out, "\n{}", "Bind history".bold())?;
+    for (idx, status) in statuses.iter().enumerate() {
+        if !troubled(status) {
+            continue;
+        }
+        writeln!(out, "  Bind {idx}:")?;
+        for event in &status.history {
+            writeln!(out, "    {event}")?;
+        }
+    }
+    writeln!(out, "  {uneventful} binds without interruptions or errors")
+}
+
+/// Prints the capacity search steps and the highest sustainable rate.
+pub fn print_search(result: &SearchResult) {
+    let mut stdout = std::io::stdout();
+    write_search(&mut stdout, result).ok();
+    stdout.flush().ok();
+}
+
+fn write_search(out: &mut impl Write, result: &SearchResult) -> std::io::Result<()> {
+    writeln!(out, "\n{}", "Capacity search".bold())?;
+    writeln!(
+        out,
+        "  {:>10} {:>12} {:>10} {:>8} {:>10}  result",
+        "target", "achieved", "sent", "err %", "p99 ms"
+    )?;
+    for step in &result.steps {
+        writeln!(
+            out,
+            "  {:>10} {:>12.1} {:>10} {:>8.2} {:>10.2}  {}",
+            step.target_tps,
+            step.achieved_tps,
+            step.attempts,
+            step.error_percent,
+            step.p99_ms,
+            if step.passed() {
+                "pass".green().to_string()
+            } else {
+                format!("{} ({})", "fail".red(), step.failures.join(", "))
+            }
+        )?;
+    }
+    match result.sustainable_tps {
+        Some(tps) => writeln!(out, "Highest sustainable rate: {} TPS", tps.bold())?,
+        None => writeln!(out, "No step met the limits.")?,
+    }
+    Ok(())
+}
+
+/// Prints one line per scenario phase with its assertion results.
+pub fn print_scenario(result: &ScenarioResult) {
+    let mut stdout = std::io::stdout();
+    write_scenario(&mut stdout, result).ok();
+    stdout.flush().ok();
+}
+
+fn write_scenario(out: &mut impl Write, result: &ScenarioResult) -> std::io::Result<()> {
+    writeln!(out, "\n{}", "Scenario".bold())?;
+    writeln!(
+        out,
+        "  {:<20} {:>10} {:>12} {:>10} {:>8} {:>10} {:>12}  result",
+        "phase", "secs", "achieved", "sent", "err %", "p99 ms", "dlr pending"
+    )?;
+    for phase in &result.phases {
+        let stage = &phase.stage;
+        writeln!(
+            out,
+            "  {:<20} {:>10.1} {:>12.1} {:>10} {:>8.2} {:>10.2} {:>12}  {}",
+            stage.name,
+            stage.duration_secs,
+            stage.achieved_tps,
+            stage.window.attempts,
+            stage.window.error_percent(),
+            stage.window.latency.p99_ms,
+            phase.dlr_pending,
+            if phase.passed() {
+                "pass".green().to_string()
+            } else {
+                format!("{} ({})", "fail".red(), phase.failures.join(", "))
+            }
+        )?;
+    }
+    let skipped = result.total_phases - result.phases.len();
+    if skipped > 0 {
+        writeln!(
+            out,
+            "  {skipped} phase(s) not completed before the run stopped"
+        )?;
+    }
+    match result.failed() {
+        0 => writeln!(out, "All completed phases {}", "passed".green())?,
+        failed => writeln!(out, "{} phase(s) {}", failed, "failed".red())?,
+    }
+    Ok(())
+}
+
+/// Prints one line per stage started through the control API.
+pub fn print_stages(stages: &[StageSnapshot]) {
+    let mut stdout = std::io::stdout();
+    write_stages(&mut stdout, stages).ok();
+    stdout.flush().ok();
+}
+
+fn write_stages(out: &mut impl Write, stages: &[StageSnapshot]) -> std::io::Result<()> {
+    writeln!(out, "\n{}", "Stages".bold())?;
+    writeln!(
+        out,
+        "  {:<20} {:>10} {:>12} {:>10} {:>8} {:>10} {:>10}",
+        "stage", "secs", "achieved", "sent", "err %", "p50 ms", "p99 ms"
+    )?;
+    for stage in stages {
+        writeln!(
+            out,
+            "  {:<20} {:>10.1} {:>12.1} {:>10} {:>8.2} {:>10.2} {:>10.2}",
+            stage.name,
+            stage.duration_secs,
+            stage.achieved_tps,
+            stage.window.attempts,
+            stage.window.error_percent(),
+            stage.window.latency.p50_ms,
+            stage.window.latency.p99_ms
+        )?;
+    }
+    Ok(())
+}
+
+fn write_summary(
+    out: &mut impl Write,
+    snapshot: &MetricsSnapshot,
+    elapsed: Duration,
+) -> std::io::Result<()> {
+    let secs = elapsed.as_secs_f64();
+    let avg_tps = if secs > 0.0 {
+        snapshot.attempts as f64 / secs
+    } else {
+        0.0
+    };
+
+    writeln!(out)?;
+    writeln!(out, "{}", "Final report".bold())?;
+    writeln!(out, "{}", "-".repeat(80))?;
+    writeln!(
+        out,
+        "Duration: {secs:.1} s | Messages: {} | OK: {} | Err: {} | Avg TPS: {avg_tps:.1} | Avg latency: {:.2} ms",
+        snapshot.attempts,
+        snapshot.ok.green(),
+        snapshot.err.red(),
+        snapshot.avg_latency_ms
+    )?;
+
+    let (dlr_received, dlr_delivered, dlr_failed) =
+        snapshot
+            .per_bind
+            .iter()
+            .fold((0, 0, 0), |(received, delivered, failed), bind| {
+                (
+                    received + bind.dlr_received,
+                    delivered + bind.dlr_delivered,
+                    failed + bind.dlr_failed,
+                )
+            });
+    writeln!(
+        out,
+        "DLRs (final, one per message): {dlr_received} | Delivered: {dlr_delivered} | Failed: {dlr_failed}"
+    )?;
+    writeln!(
+        out,
+        "DLR index: still pending {} | peak {} | missing (timed out) {} | evicted {}",
+        snapshot.dlr_pending,
+        snapshot.dlr_pending_peak,
+        snapshot.per_bind.iter().map(|bind| bind.dlr_missing).sum::<u64>(),
+        snapshot.per_bind.iter().map(|bind| bind.dlr_evicted).sum::<u64>()
+    )?;
+    writeln!(
+        out,
+        "DLR anomalies: orphan {} | malformed {} | duplicate {} | TLV/text conflict {} | illegal transitions {}",
+        snapshot.per_bind.iter().map(|bind| bind.dlr_orphan).sum::<u64>(),
+        snapshot.per_bind.iter().map(|bind| bind.dlr_malformed).sum::<u64>(),
+        snapshot.per_bind.iter().map(|bind| bind.dlr_duplicate).sum::<u64>(),
+        snapshot.per_bind.iter().map(|bind| bind.dlr_conflict).sum::<u64>(),
+        snapshot.per_bind.iter().map(|bind| bind.dlr_illegal_transition).sum::<u64>()
+    )?;
+    writeln!(
+        out,
+        "DLR receipts: intermediate {} | SMSC submit→done avg {:.0} ms | invalid {}",
+        snapshot.per_bind.iter().map(|bind| bind.dlr_intermediate).sum::<u64>(),
+        snapshot.avg_smsc_delay_ms(),
+        snapshot.per_bind.iter().map(|bind| bind.dlr_invalid).sum::<u64>()
+    )?;
+    if !snapshot.dlr_error_codes.is_empty() {
+        writeln!(out, "DLR error codes:")?;
+        for (code, count) in &snapshot.dlr_error_codes {
+            writeln!(out, "  err:{code:<8} {count:>10}")?;
+        }
+    }
+
+    let mo_received: u64 = snapshot.per_bind.iter().map(|bind| bind.mo_received).sum();
+    if mo_received > 0 {
+        let mo_bytes: u64 = snapshot.per_bind.iter().map(|bind| bind.mo_bytes).sum();
+        let mo_tps = if secs > 0.0 { mo_received as f64 / secs } else { 0.0 };
+        writeln!(
+            out,
+            "MO: received {mo_received} | Avg TPS {mo_tps:.1} | avg body {:.0} B (max {}) | pattern mismatches {}",
+            mo_bytes as f64 / mo_received as f64,
+            snapshot.mo_max_bytes,
+            snapshot.per_bind.iter().map(|bind| bind.mo_mismatch).sum::<u64>()
+        )?;
+        for (data_coding, count) in &snapshot.mo_data_coding {
+            writeln!(out, "  data_coding {data_coding:#04x} {count:>10}")?;
+        }
+    }
+
+    let open_loop = &snapshot.open_loop;
+    if open_loop.scheduled > 0 {
+        writeln!(
+            out,
+            "Open loop: scheduled {} | delayed by full window {} ({:.2}%) | dropped {} | backlog peak {}",
+            open_loop.scheduled,
+            open_loop.delayed,
+            open_loop.delayed as f64 / open_loop.scheduled as f64 * 100.0,
+            open_loop.dropped,
+            open_loop.backlog_peak
+        )?;
+    }
+
+    let deliver_resp = &snapshot.deliver_resp;
+    if deliver_resp.rejected + deliver_resp.dropped + deliver_resp.redeliveries > 0 {
+        writeln!(
+            out,
+            "deliver_sm_resp: OK {} | rejected {} | dropped {} | redeliveries {} ({} msgs, recovered {})",
+            deliver_resp.accepted,
+            deliver_resp.rejected,
+            deliver_resp.dropped,
+            deliver_resp.redeliveries,
+            deliver_resp.redelivered_messages,
+            deliver_resp.recovered
+        )?;
+    }
+
+    if !snapshot.incoming.is_empty() {
+        writeln!(out, "Incoming PDUs:")?;
+        for (command, count) in &snapshot.incoming {
+            writeln!(out, "  {command:<20} {count:>10}")?;
+        }
+    }
+    if snapshot.outbind_sessions + snapshot.outbind_rejected > 0 {
+        writeln!(
+            out,
+            "Outbind: sessions {} | rejected {}",
+            snapshot.outbind_sessions, snapshot.outbind_rejected
+        )?;
+    }
+    let sessions = snapshot.sessions();
+    writeln!(
+        out,
+        "Sessions: server unbinds {} | connection lost {} | reconnects {}",
+        sessions.server_unbinds, sessions.disconnects, sessions.reconnects
+    )?;
+
+    writeln!(out, "\nDelay breakdown:")?;
+    writeln!(
+        out,
+        "  {:<24} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+        "", "count", "mean ms", "p50 ms", "p95 ms", "p99 ms", "max ms"
+    )?;
+    let delays = &snapshot.delays;
+    for (label, histogram) in [
+        ("submit→resp (OK)", &delays.submit_to_resp),
+        ("resp→DELIVRD", &delays.resp_to_delivered),
+        ("resp→UNDELIV/EXPIRED", &delays.resp_to_undelivered),
+        ("submit→DELIVRD", &delays.submit_to_delivered),
+        ("submit→UNDELIV/EXPIRED", &delays.submit_to_undelivered),
+        ("redelivery interval", &deliver_resp.retry_interval),
+        ("open-loop send lag", &snapshot.open_loop.send_lag),
+    ] {
+        writeln!(
+            out,
+            "  {label:<24} {:>10} {:>10.2} {:>10.2} {:>10.2} {:>10.2} {:>10.2}",
+            histogram.count,
+            histogram.mean_ms,
+            histogram.p50_ms,
+            histogram.p95_ms,
+            histogram.p99_ms,
+            histogram.max_ms
+        )?;
+    }
+
+    writeln!(out, "\nSession lifecycle:")?;
+    writeln!(
+        out,
+        "  {:<24} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+        "", "count", "mean ms", "p50 ms", "p95 ms", "p99 ms", "max ms"
+    )?;
+    let lifecycle = &snapshot.lifecycle;
+    for (label, histogram) in [
+        ("TCP connect", &lifecycle.connect),
+        ("bind→bind_resp", &lifecycle.bind),
+        ("time bound", &lifecycle.bound),
+        ("unbind→unbind_resp", &lifecycle.unbind),
+    ] {
+        writeln!(
+            out,
+            "  {label:<24} {:>10} {:>10.2} {:>10.2} {:>10.2} {:>10.2} {:>10.2}",
+            histogram.count,
+            histogram.mean_ms,
+            histogram.p50_ms,
+            histogram.p95_ms,
+            histogram.p99_ms,
+            histogram.max_ms
+        )?;
+    }
+
+    let enquire_link = snapshot.enquire_link();
+    writeln!(
+        out,
+        "Enquire link: OK {} | Timeouts {} | Err {} | Avg RTT {:.2} ms",
+        enquire_link.ok, enquire_link.timeouts, enquire_link.errors, enquire_link.avg_rtt_ms
+    )?;
+
+    if snapshot.per_account.len() > 1 {
+        writeln!(out, "\nPer-account stats:")?;
+        for account in &snapshot.per_account {
+            writeln!(
+                out,
+                "  {:<16} -> Binds {:>4} | Sent {:>8} | OK {:>8} | Err {:>8} | Avg {:>6.2} ms | DLR {:>8} (DEL {} / Failed {})",
+                account.name,
+                account.binds,
+                account.attempts,
+                account.ok,
+                account.err,
+                account.avg_latency_ms,
+                account.dlr_received,
+                account.dlr_delivered,
+                account.dlr_failed
+            )?;
+        }
+    }
+
+    writeln!(out, "\nPer-command stats:")?;
+    for op in snapshot.operations.iter().filter(|op| op.attempts > 0) {
+        writeln!(
+            out,
+            "  {:<10} -> Sent {:>8} | OK {:>8} | Err {:>8} | Avg {:>6.2} ms",
+            op.operation.name(),
+            op.attempts,
+            op.ok,
+            op.err,
+            op.avg_latency_ms
+        )?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,314 @@
+use std::{
+    sync::{Arc, atomic::AtomicU64},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Result, bail};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    api,
+    bind_tracker::{BindState, BindTracker},
+    config::Config,
+    connection::Connector,
+    control::{self, BindLauncher, RunControl},
+    correlation::CorrelationStore,
+    deliver_resp::DeliverResponder,
+    dlr::DlrTracker,
+    events::Inbound,
+    metrics::Metrics,
+    mo::MoTracker,
+    outbind,
+    progress::spawn_progress_task,
+    rate_limiter::TokenBucket,
+    report,
+    scenario::ScenarioRunner,
+    search::CapacitySearch,
+    worker::{BindContext, spawn_bind},
+};
+
+/// One load test, from the first bind to the final report. The metrics and
+/// bind states are public so an agent can report them while it runs.
+pub struct Run {
+    pub config: Arc<Config>,
+    pub metrics: Arc<Metrics>,
+    pub tracker: Arc<BindTracker>,
+}
+
+impl Run {
+    pub fn new(config: Arc<Config>) -> Self {
+        Self {
+            metrics: Arc::new(Metrics::new(
+                &config.accounts,
+                config.bind_slots() - config.total_binds(),
+                config.warmup().is_some(),
+            )),
+            tracker: Arc::new(BindTracker::new(config.bind_slots())),
+            config,
+        }
+    }
+
+    /// Runs until Ctrl+C, `shutdown` or the controls stop it, then prints the
+    /// report. `interactive` adds the dashboard and keyboard controls.
+    pub async fn execute(self, shutdown: CancellationToken, interactive: bool) -> Result<Duration> {
+        let Run {
+            config,
+            metrics,
+            tracker,
+        } = self;
+        // Each account's TLS setup is loaded once, before anything starts.
+        let connectors = config
+            .accounts
+            .iter()
+            .map(|account| Connector::new(&account.smpp).map(Arc::new))
+            .collect::<Result<Vec<_>>>()?;
+        let sent_index = Arc::new(CorrelationStore::new(&config.dlr, metrics.clone())?);
+        let sweeper_handle = sent_index.clone().spawn_sweeper(shutdown.clone());
+        let dlr_tracker = Arc::new(DlrTracker::new(
+            sent_index.clone(),
+            metrics.clone(),
+            &config.accounts,
+        ));
+        let mo_tracker = Arc::new(MoTracker::new(&config.mo, metrics.clone())?);
+        let responder = Arc::new(DeliverResponder::new(&config.deliver_resp, metrics.clone()));
+        let responder_sweeper = responder.clone().spawn_sweeper(shutdown.clone());
+        let inbound = Arc::new(Inbound::new(
+            dlr_tracker,
+            mo_tracker,
+            responder,
+            metrics.clone(),
+        ));
+
+        let outbind_handle = match (&config.outbind, config.outbind_bind()) {
+            (Some(outbind), Some(outbind_idx)) => Some(
+                outbind::spawn_listener(
+                    outbind.clone(),
+                    outbind_idx,
+                    metrics.clone(),
+                    tracker.clone(),
+                    inbound.clone(),
+                    shutdown.clone(),
+                )
+                .await?,
+            ),
+            _ => None,
+        };
+
+        // Each account holds its own total_tps (or its share of the capacity
+        // search's rate), shared by whichever of its binds are up.
+        let rate_limiters: Vec<Option<Arc<TokenBucket>>> = config
+            .accounts
+            .iter()
+            .map(|account| match (&config.search, account.load.total_tps()) {
+                (Some(_), _) => Some(Arc::new(TokenBucket::new(0.0))),
+                (None, tps) => tps.map(|tps| Arc::new(TokenBucket::new(f64::from(tps)))),
+            })
+            .collect();
+        let search = config.search.clone().map(|search| {
+            Arc::new(CapacitySearch::new(
+                search,
+                &config.accounts,
+                &rate_limiters,
+                metrics.clone(),
+            ))
+        });
+
+        // Everything a bind slot needs, so binds can also be started at runtime.
+        let mut slots = Vec::new();
+        for ((account, connector), rate_limiter) in
+            config.accounts.iter().zip(connectors).zip(&rate_limiters)
+        {
+            let account = Arc::new(account.clone());
+            // Each account enforces its own messages_count.
+            let messages_sent = Arc::new(AtomicU64::new(0));
+            for _ in 0..account.load.bind_slots() {
+                slots.push((
+                    account.clone(),
+                    connector.clone(),
+                    messages_sent.clone(),
+                    rate_limiter.clone(),
+                ));
+            }
+        }
+        let slot_count = slots.len();
+        let launcher: BindLauncher = {
+            let (metrics, tracker, sent_index, inbound) = (
+                metrics.clone(),
+                tracker.clone(),
+                sent_index.clone(),
+                inbound.clone(),
+            );
+            Box::new(move |idx, stop, control| {
+                let (account, connector, messages_sent, rate_limiter) = slots[idx].clone();
+                let ctx = BindContext {
+                    idx,
+                    account,
+                    connector,
+                    metrics: metrics.clone(),
+                    tracker: tracker.clone(),
+                    sent_index: sent_index.clone(),
+                    inbound: inbound.clone(),
+                    rate_limiter,
+                    control,
+                    messages_sent,
+                };
+                Box::pin(spawn_bind(ctx, stop))
+            })
+        };
+        // The capacity search steers the buckets itself.
+        let buckets = match &config.search {
+            Some(_) => Vec::new(),
+            None => config
+                .accounts
+                .iter()
+                .zip(&rate_limiters)
+                .filter_map(|(account, bucket)| bucket.clone().zip(account.load.total_tps()))
+                .map(|(bucket, tps)| (bucket, f64::from(tps)))
+                .collect(),
+        };
+        let control = Arc::new(RunControl::new(
+            slot_count,
+            buckets,
+            config.search.is_some(),
+            launcher,
+            tracker.clone(),
+            metrics.clone(),
+            shutdown.clone(),
+        ));
+        let scenario = config.scenario.clone().map(|scenario| {
+            Arc::new(ScenarioRunner::new(
+                scenario,
+                config.configured_tps(),
+                control.clone(),
+                metrics.clone(),
+            ))
+        });
+        let api_handle = match &config.api {
+            Some(api) => Some(
+                api::spawn_server(
+                    api,
+                    metrics.clone(),
+                    tracker.clone(),
+                    control.clone(),
+                    shutdown.clone(),
+                )
+                .await?,
+            ),
+            None => None,
+        };
+
+        let (progress_handle, keyboard_handle) = if interactive {
+            (
+                Some(spawn_progress_task(
+                    metrics.clone(),
+                    tracker.clone(),
+                    config.clone(),
+                    search.clone(),
+                    scenario.clone(),
+                    control.clone(),
+                    shutdown.clone(),
+                )),
+                control::spawn_keyboard(control.clone(), shutdown.clone()),
+            )
+        } else {
+            (None, None)
+        };
+
+        let started = Instant::now();
+        let mut idx = 0;
+        for account in &config.accounts {
+            for slot in 0..account.load.bind_slots() {
+                if slot < account.load.binds {
+                    control.start_bind(idx);
+                } else {
+                    tracker.set_state(idx, BindState::Idle).await;
+                }
+                idx += 1;
+            }
+        }
+        let warmup_handle = config.warmup().map(|warmup| {
+            let (metrics, shutdown) = (metrics.clone(), shutdown.clone());
+            tokio::spawn(async move {
+                tokio::select! {
+                    _ = shutdown.cancelled() => {}
+                    _ = tokio::time::sleep(warmup) => {
+                        metrics.end_warmup();
+                        tracing::info!("Warm-up finished, measuring from now on");
+                    }
+                }
+            })
+        });
+        let search_handle = search.clone().map(|search| search.spawn(shutdown.clone()));
+        let scenario_handle = scenario
+            .clone()
+            .map(|scenario| scenario.spawn(shutdown.clone()));
+
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {
+                println!("\nCtrl+C received. Stopping load test...");
+                shutdown.cancel();
+            }
+            _ = shutdown.cancelled() => {}
+        }
+
+        control.join().await;
+
+        shutdown.cancel();
+        if let Some(progress_handle) = progress_handle {
+            let _ = progress_handle.await;
+        }
+        let _ = sweeper_handle.await;
+        let _ = responder_sweeper.await;
+        if let Some(outbind_handle) = outbind_handle {
+            let _ = outbind_handle.await;
+        }
+        if let Some(warmup_handle) = warmup_handle {
+            let _ = warmup_handle.await;
+        }
+        if let Some(search_handle) = search_handle {
+            let _ = search_handle.await;
+        }
+        if let Some(scenario_handle) = scenario_handle {
+            let _ = scenario_handle.await;
+        }
+        if let Some(api_handle) = api_handle {
+            let _ = api_handle.await;
+        }
+        // Leaves raw mode before the report is printed.
+        if let Some(keyboard_handle) = keyboard_handle {
+            let _ = keyboard_handle.await;
+        }
+
+        let elapsed = started.elapsed();
+        // The summary rates cover the measurement window only.
+        let warmup = config.warmup().map(|warmup| warmup.min(elapsed));
+        report::print_summary(&metrics.snapshot(), elapsed - warmup.unwrap_or_default());
+        if let Some(warmup) = warmup {
+            report::print_warmup(&metrics.warmup_snapshot(), warmup);
+        }
+        report::print_bind_history(&tracker.snapshot().await);
+        if let Some(search) = &search {
+            report::print_search(&search.result());
+        }
+        let scenario = scenario.map(|scenario| scenario.result());
+        if let Some(scenario) = &scenario {
+            report::print_scenario(scenario);
+        } else {
+            let stages = control.stages();
+            if !stages.is_empty() {
+                report::print_stages(&stages);
+            }
+        }
+        println!("Load test stopped.");
+        if let Some(scenario) = scenario
+            && scenario.failed() > 0
+        {
+            bail!(
+                "{} of {} scenario phases failed their assertions",
+                scenario.failed(),
+                scenario.phases.len()
+            );
+        }
+        Ok(elapsed)
+    }
+}
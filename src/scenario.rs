@@ -0,0 +1,388 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::{
+    task::JoinHandle,
+    time::{self, Instant},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    config::{PhaseAssertions, PhaseConfig, PhaseUntil, ScenarioConfig},
+    control::{RunControl, StageSnapshot},
+    metrics::Metrics,
+};
+
+/// How often an `until` condition is checked.
+const UNTIL_POLL: Duration = Duration::from_millis(100);
+
+/// Outcome of one phase.
+#[derive(Debug, Clone)]
+pub struct PhaseResult {
+    pub stage: StageSnapshot,
+    /// Receipts still outstanding when the phase ended.
+    pub dlr_pending: u64,
+    /// Assertions the phase broke; empty when it passed.
+    pub failures: Vec<String>,
+}
+
+impl PhaseResult {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ScenarioResult {
+    pub phases: Vec<PhaseResult>,
+    /// Name of the phase currently running.
+    pub current: Option<String>,
+    pub total_phases: usize,
+}
+
+impl ScenarioResult {
+    pub fn failed(&self) -> usize {
+        self.phases.iter().filter(|phase| !phase.passed()).count()
+    }
+}
+
+/// Plays the configured phases through the run's controls, each one
+/// measured as a stage and judged by its assertions.
+pub struct ScenarioRunner {
+    config: ScenarioConfig,
+    /// Aggregate configured rate that phase `tps` values are relative to.
+    configured_tps: f64,
+    control: Arc<RunControl>,
+    metrics: Arc<Metrics>,
+    result: Mutex<ScenarioResult>,
+}
+
+impl ScenarioRunner {
+    pub fn new(
+        config: ScenarioConfig,
+        configured_tps: f64,
+        control: Arc<RunControl>,
+        metrics: Arc<Metrics>,
+    ) -> Self {
+        let result = ScenarioResult {
+            total_phases: config.phases.len(),
+            ..ScenarioResult::default()
+        };
+        Self {
+            config,
+            configured_tps,
+            control,
+            metrics,
+            result: Mutex::new(result),
+        }
+    }
+
+    pub fn result(&self) -> ScenarioResult {
+        self.result.lock().expect("scenario lock poisoned").clone()
+    }
+
+    /// Stops the run after the last phase when `stop_at_end` is set.
+    pub fn spawn(self: Arc<Self>, shutdown: CancellationToken) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            for phase in &self.config.phases {
+                if !self.play(phase, &shutdown).await {
+                    return;
+                }
+            }
+            self.result.lock().expect("scenario lock poisoned").current = None;
+            tracing::info!(failed = self.result().failed(), "Scenario finished");
+            if self.config.stop_at_end {
+                shutdown.cancel();
+            }
+        })
+    }
+
+    /// Applies, holds and judges one phase. False when the run was stopped
+    /// before the phase completed.
+    async fn play(&self, phase: &PhaseConfig, shutdown: &CancellationToken) -> bool {
+        self.apply(phase);
+        self.control.start_stage(phase.name.clone());
+        self.result.lock().expect("scenario lock poisoned").current = Some(phase.name.clone());
+
+        let deadline = Instant::now() + phase.duration();
+        let mut poll = time::interval(UNTIL_POLL);
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => return false,
+                _ = time::sleep_until(deadline) => break,
+                _ = poll.tick(), if phase.until.is_some() => {
+                    if self.reached(phase.until) {
+                        break;
+                    }
+                }
+            }
+        }
+
+        let Some(stage) = self.control.end_stage() else {
+            return false;
+        };
+        let dlr_pending = self.metrics.dlr_pending();
+        let failures = judge(&phase.assertions, &stage, dlr_pending);
+        tracing::info!(
+            phase = %phase.name,
+            passed = failures.is_empty(),
+            ?failures,
+            "Scenario phase done"
+        );
+        self.result
+            .lock()
+            .expect("scenario lock poisoned")
+            .phases
+            .push(PhaseResult {
+                stage,
+                dlr_pending,
+                failures,
+            });
+        true
+    }
+
+    fn apply(&self, phase: &PhaseConfig) {
+        if let Some(percent) = phase.rate_percent(self.configured_tps)
+            && let Err(err) = self.control.set_rate_percent(percent)
+        {
+            tracing::warn!(phase = %phase.name, "Rate unchanged: {err}");
+        }
+        if let Some(binds) = phase.binds {
+            let running = self.control.set_binds(binds);
+            if running != binds {
+                tracing::warn!(phase = %phase.name, binds, running, "Bind target not reached");
+            }
+        }
+        if let Some(paused) = phase.paused {
+            self.control.set_paused(paused);
+        }
+        // Validated with the configuration.
+        if let Some(profile) = phase
+            .profile
+            .as_deref()
+            .and_then(|name| self.config.profile_index(name))
+        {
+            self.control.set_profile(profile);
+        }
+    }
+
+    fn reached(&self, until: Option<PhaseUntil>) -> bool {
+        match until {
+            Some(PhaseUntil::DlrDrained) => self.metrics.dlr_pending() == 0,
+            None => false,
+        }
+    }
+}
+
+fn judge(assertions: &PhaseAssertions, stage: &StageSnapshot, dlr_pending: u64) -> Vec<String> {
+    let mut failures = Vec::new();
+    let error_percent = stage.window.error_percent();
+    if let Some(max) = assertions.max_error_percent
+        && error_percent > max
+    {
+        failures.push(format!("errors {error_percent:.2}% > {max:.2}%"));
+    }
+    let p99_ms = stage.window.latency.p99_ms;
+    if let Some(max) = assertions.max_p99_ms
+        && p99_ms > max
+    {
+        failures.push(format!("p99 {p99_ms:.1} ms > {max:.1} ms"));
+    }
+    if let Some(min) = assertions.min_tps
+        && stage.achieved_tps < min
+    {
+        failures.push(format!("{:.1} TPS < {min:.1} TPS", stage.achieved_tps));
+    }
+    if let Some(max) = assertions.max_dlr_pending
+        && dlr_pending > max
+    {
+        failures.push(format!("{dlr_pending} receipts pending > {max}"));
+    }
+    failures
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::{
+        bind_tracker::BindTracker,
+        config::{MessageConfig, PhaseUntil},
+        metrics::WindowSnapshot,
+        rate_limiter::TokenBucket,
+    };
+
+    use super::*;
+
+    fn phase(name: &str, duration_secs: u64) -> PhaseConfig {
+        PhaseConfig {
+            name: name.to_string(),
+            duration_secs,
+            until: None,
+            rate_percent: None,
+            tps: None,
+            binds: None,
+            paused: None,
+            profile: None,
+            assertions: PhaseAssertions::default(),
+        }
+    }
+
+    fn stage(attempts: u64, err: u64, achieved_tps: f64) -> StageSnapshot {
+        StageSnapshot {
+            name: "phase".to_string(),
+            duration_secs: 10.0,
+            achieved_tps,
+            window: WindowSnapshot {
+                attempts,
+                err,
+                ..WindowSnapshot::default()
+            },
+        }
+    }
+
+    #[test]
+    fn judge_reports_each_broken_assertion() {
+        let assertions = PhaseAssertions {
+            max_error_percent: Some(1.0),
+            max_p99_ms: None,
+            min_tps: Some(90.0),
+            max_dlr_pending: Some(10),
+        };
+        let cases: &[(StageSnapshot, u64, &[&str])] = &[
+            (stage(1000, 10, 100.0), 10, &[]),
+            (stage(1000, 11, 100.0), 0, &["errors 1.10% > 1.00%"]),
+            (stage(0, 0, 0.0), 0, &["0.0 TPS < 90.0 TPS"]),
+            (
+                stage(1000, 500, 50.0),
+                11,
+                &[
+                    "errors 50.00% > 1.00%",
+                    "50.0 TPS < 90.0 TPS",
+                    "11 receipts pending > 10",
+                ],
+            ),
+        ];
+        for (stage, dlr_pending, expected) in cases {
+            assert_eq!(
+                judge(&assertions, stage, *dlr_pending),
+                *expected,
+                "{stage:?} {dlr_pending}"
+            );
+        }
+    }
+
+    #[test]
+    fn judge_without_assertions_passes() {
+        let failures = judge(&PhaseAssertions::default(), &stage(10, 10, 0.0), 1000);
+        assert!(failures.is_empty(), "{failures:?}");
+    }
+
+    fn runner(phases: Vec<PhaseConfig>, shutdown: &CancellationToken) -> Arc<ScenarioRunner> {
+        let metrics = Arc::new(Metrics::new(&[], 0, false));
+        let control = RunControl::new(
+            0,
+            vec![(Arc::new(TokenBucket::new(100.0)), 100.0)],
+            false,
+            Box::new(|_, _, _| Box::pin(async {})),
+            Arc::new(BindTracker::new(0)),
+            metrics.clone(),
+            shutdown.clone(),
+        );
+        let profile = MessageConfig {
+            source_addr: "1".to_string(),
+            source_ton: 0,
+            source_npi: 0,
+            destination_addr: "2".to_string(),
+            destination_ton: 0,
+            destination_npi: 0,
+            body: "bulk".to_string(),
+            service_type: None,
+            request_dlr: true,
+            data_coding: 0,
+        };
+        let config = ScenarioConfig {
+            stop_at_end: true,
+            profiles: BTreeMap::from([("bulk".to_string(), profile)]),
+            phases,
+        };
+        Arc::new(ScenarioRunner::new(
+            config,
+            100.0,
+            Arc::new(control),
+            metrics,
+        ))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn phases_apply_their_settings_in_order() {
+        let warm = PhaseConfig {
+            tps: Some(50),
+            ..phase("warm", 10)
+        };
+        let peak = PhaseConfig {
+            rate_percent: Some(300),
+            profile: Some("bulk".to_string()),
+            ..phase("peak", 10)
+        };
+        let drain = PhaseConfig {
+            paused: Some(true),
+            until: Some(PhaseUntil::DlrDrained),
+            ..phase("drain", 60)
+        };
+        let shutdown = CancellationToken::new();
+        let runner = runner(vec![warm, peak, drain], &shutdown);
+        let started = Instant::now();
+        let task = runner.clone().spawn(shutdown.clone());
+
+        time::sleep(Duration::from_secs(5)).await;
+        assert_eq!(runner.result().current.as_deref(), Some("warm"));
+        assert_eq!(runner.control.state().rate_percent, 50);
+        assert_eq!(runner.control.state().profile, 0);
+
+        time::sleep(Duration::from_secs(10)).await;
+        assert_eq!(runner.result().current.as_deref(), Some("peak"));
+        let state = runner.control.state();
+        assert_eq!(
+            (state.rate_percent, state.profile, state.paused),
+            (300, 1, false)
+        );
+
+        task.await.unwrap();
+        let state = runner.control.state();
+        assert_eq!(
+            (state.rate_percent, state.profile, state.paused),
+            (300, 1, true)
+        );
+        let result = runner.result();
+        let names: Vec<_> = result
+            .phases
+            .iter()
+            .map(|phase| phase.stage.name.as_str())
+            .collect();
+        assert_eq!(names, ["warm", "peak", "drain"]);
+        assert_eq!(result.current, None);
+        assert_eq!(result.failed(), 0);
+        // No receipt was pending, so "drain" ended at its first check.
+        assert!(started.elapsed() < Duration::from_secs(21));
+        assert!(shutdown.is_cancelled());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stopping_the_run_abandons_the_current_phase() {
+        let shutdown = CancellationToken::new();
+        let runner = runner(vec![phase("first", 10), phase("second", 10)], &shutdown);
+        let task = runner.clone().spawn(shutdown.clone());
+
+        time::sleep(Duration::from_secs(15)).await;
+        shutdown.cancel();
+        task.await.unwrap();
+
+        let result = runner.result();
+        assert_eq!(result.phases.len(), 1);
+        assert_eq!(result.current.as_deref(), Some("second"));
+        assert_eq!(result.total_phases, 2);
+    }
+}
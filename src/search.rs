@@ -0,0 +1,344 @@
+use std::sync::{Arc, Mutex};
+
+use tokio::{
+    task::JoinHandle,
+    time::{self, Instant},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    config::{AccountConfig, BindType, SearchConfig, SearchStrategy},
+    metrics::Metrics,
+    rate_limiter::TokenBucket,
+};
+
+/// Outcome of holding one target rate.
+#[derive(Debug, Clone)]
+pub struct StepResult {
+    pub target_tps: u32,
+    pub achieved_tps: f64,
+    pub attempts: u64,
+    pub error_percent: f64,
+    pub p99_ms: f64,
+    /// Limits the step broke; empty when it passed.
+    pub failures: Vec<String>,
+}
+
+impl StepResult {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SearchResult {
+    pub steps: Vec<StepResult>,
+    /// Highest target rate that passed, if any did.
+    pub sustainable_tps: Option<u32>,
+    /// Rate currently being measured.
+    pub current_tps: Option<u32>,
+}
+
+/// Finds the highest aggregate rate the SMSC sustains by steering every
+/// account's token bucket and judging each step by its own window of
+/// metrics. Stops the run when done.
+pub struct CapacitySearch {
+    config: SearchConfig,
+    /// Each account's bucket and its share of the aggregate rate.
+    limiters: Vec<(Arc<TokenBucket>, f64)>,
+    metrics: Arc<Metrics>,
+    result: Mutex<SearchResult>,
+}
+
+impl CapacitySearch {
+    /// `limiters` holds each account's bucket, in account order. The rate is
+    /// split across accounts by their number of submitting binds.
+    pub fn new(
+        config: SearchConfig,
+        accounts: &[AccountConfig],
+        limiters: &[Option<Arc<TokenBucket>>],
+        metrics: Arc<Metrics>,
+    ) -> Self {
+        let submitting = |account: &AccountConfig| {
+            if account.smpp.bind_type == BindType::Rx {
+                0
+            } else {
+                account.load.binds
+            }
+        };
+        let total = accounts.iter().map(submitting).sum::<usize>().max(1) as f64;
+        let limiters = accounts
+            .iter()
+            .zip(limiters)
+            .filter_map(|(account, bucket)| {
+                let share = submitting(account) as f64 / total;
+                bucket.clone().map(|bucket| (bucket, share))
+            })
+            .collect();
+        Self {
+            config,
+            limiters,
+            metrics,
+            result: Mutex::new(SearchResult::default()),
+        }
+    }
+
+    pub fn result(&self) -> SearchResult {
+        self.result.lock().expect("search lock poisoned").clone()
+    }
+
+    pub fn spawn(self: Arc<Self>, shutdown: CancellationToken) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let measure = |tps| self.measure(tps, &shutdown);
+            match self.config.strategy {
+                SearchStrategy::Step => step_up(&self.config, measure).await,
+                SearchStrategy::Binary => bisect(&self.config, measure).await,
+            }
+            self.result
+                .lock()
+                .expect("search lock poisoned")
+                .current_tps = None;
+            shutdown.cancel();
+        })
+    }
+
+    /// Holds `tps` for a step and records the result. `None` when the run
+    /// was stopped before the step completed.
+    async fn measure(&self, tps: u32, shutdown: &CancellationToken) -> Option<bool> {
+        self.result
+            .lock()
+            .expect("search lock poisoned")
+            .current_tps = Some(tps);
+        for (bucket, share) in &self.limiters {
+            bucket.set_rate(f64::from(tps) * share);
+        }
+        tracing::info!(tps, "Capacity search step");
+
+        let settle = time::sleep(self.config.settle_duration());
+        tokio::select! {
+            _ = shutdown.cancelled() => return None,
+            _ = settle => {}
+        }
+        let checkpoint = self.metrics.checkpoint();
+        let started = Instant::now();
+        tokio::select! {
+            _ = shutdown.cancelled() => return None,
+            _ = time::sleep(self.config.step_duration()) => {}
+        }
+        let window = self.metrics.window_since(&checkpoint);
+        let elapsed = started.elapsed().as_secs_f64();
+
+        let step = StepResult {
+            target_tps: tps,
+            achieved_tps: window.attempts as f64 / elapsed,
+            attempts: window.attempts,
+            error_percent: window.error_percent(),
+            p99_ms: window.latency.p99_ms,
+            failures: Vec::new(),
+        };
+        let step = self.judge(step);
+        let passed = step.passed();
+        tracing::info!(tps, passed, failures = ?step.failures, "Capacity search step done");
+
+        let mut result = self.result.lock().expect("search lock poisoned");
+        if passed {
+            result.sustainable_tps = result.sustainable_tps.max(Some(tps));
+        }
+        result.steps.push(step);
+        Some(passed)
+    }
+
+    fn judge(&self, mut step: StepResult) -> StepResult {
+        let config = &self.config;
+        if step.error_percent > config.max_error_percent {
+            step.failures.push(format!(
+                "errors {:.2}% > {:.2}%",
+                step.error_percent, config.max_error_percent
+            ));
+        }
+        if step.p99_ms > config.max_p99_ms {
+            step.failures.push(format!(
+                "p99 {:.1} ms > {:.1} ms",
+                step.p99_ms, config.max_p99_ms
+            ));
+        }
+        let achieved_percent = step.achieved_tps / f64::from(step.target_tps) * 100.0;
+        if achieved_percent < config.min_achieved_percent {
+            step.failures.push(format!(
+                "reached {achieved_percent:.1}% of target < {:.1}%",
+                config.min_achieved_percent
+            ));
+        }
+        step
+    }
+}
+
+/// Raises the rate by `step_tps` from `start_tps` until a step fails or the
+/// next one would pass `max_tps`. `measure` returns `None` once the run was
+/// stopped.
+async fn step_up<F, Fut>(config: &SearchConfig, mut measure: F)
+where
+    F: FnMut(u32) -> Fut,
+    Fut: Future<Output = Option<bool>>,
+{
+    let mut tps = config.start_tps;
+    while tps <= config.max_tps {
+        match measure(tps).await {
+            Some(true) => match tps.checked_add(config.step_tps) {
+                Some(next) => tps = next,
+                None => break,
+            },
+            _ => break,
+        }
+    }
+}
+
+/// Checks `start_tps` and `max_tps`, then bisects between them until the
+/// passing and failing rates are within `step_tps`.
+async fn bisect<F, Fut>(config: &SearchConfig, mut measure: F)
+where
+    F: FnMut(u32) -> Fut,
+    Fut: Future<Output = Option<bool>>,
+{
+    let (mut low, mut high) = (config.start_tps, config.max_tps);
+    match measure(low).await {
+        Some(true) => {}
+        _ => return,
+    }
+    match measure(high).await {
+        Some(false) => {}
+        _ => return,
+    }
+    // `low` always passed and `high` always failed.
+    while high - low > config.step_tps {
+        let mid = low + (high - low) / 2;
+        match measure(mid).await {
+            Some(true) => low = mid,
+            Some(false) => high = mid,
+            None => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, future};
+
+    use super::*;
+
+    fn config(start_tps: u32, step_tps: u32, max_tps: u32) -> SearchConfig {
+        SearchConfig {
+            strategy: SearchStrategy::Step,
+            start_tps,
+            step_tps,
+            max_tps,
+            step_secs: 1,
+            settle_secs: 0,
+            max_error_percent: 1.0,
+            max_p99_ms: 100.0,
+            min_achieved_percent: 95.0,
+        }
+    }
+
+    /// An SMSC that sustains up to `capacity` TPS, for a run that is stopped
+    /// after `steps` measurements.
+    struct Stub {
+        capacity: u32,
+        steps: usize,
+        measured: RefCell<Vec<u32>>,
+    }
+
+    impl Stub {
+        fn new(capacity: u32) -> Self {
+            Self::stopped_after(capacity, usize::MAX)
+        }
+
+        fn stopped_after(capacity: u32, steps: usize) -> Self {
+            Self {
+                capacity,
+                steps,
+                measured: RefCell::new(Vec::new()),
+            }
+        }
+
+        fn measure(&self, tps: u32) -> future::Ready<Option<bool>> {
+            let mut measured = self.measured.borrow_mut();
+            if measured.len() >= self.steps {
+                return future::ready(None);
+            }
+            measured.push(tps);
+            future::ready(Some(tps <= self.capacity))
+        }
+
+        fn measured(self) -> Vec<u32> {
+            self.measured.into_inner()
+        }
+    }
+
+    #[tokio::test]
+    async fn step_up_stops_at_first_failure() {
+        let stub = Stub::new(250);
+        step_up(&config(100, 50, 1000), |tps| stub.measure(tps)).await;
+        assert_eq!(stub.measured(), vec![100, 150, 200, 250, 300]);
+    }
+
+    #[tokio::test]
+    async fn step_up_stops_at_max() {
+        let stub = Stub::new(u32::MAX);
+        step_up(&config(100, 50, 220), |tps| stub.measure(tps)).await;
+        assert_eq!(stub.measured(), vec![100, 150, 200]);
+
+        let stub = Stub::new(0);
+        step_up(&config(100, 50, 220), |tps| stub.measure(tps)).await;
+        assert_eq!(stub.measured(), vec![100]);
+    }
+
+    #[tokio::test]
+    async fn step_up_does_not_overflow() {
+        let stub = Stub::new(u32::MAX);
+        let start = u32::MAX - 10;
+        step_up(&config(start, 8, u32::MAX), |tps| stub.measure(tps)).await;
+        assert_eq!(stub.measured(), vec![start, start + 8]);
+    }
+
+    #[tokio::test]
+    async fn step_up_stops_with_the_run() {
+        let stub = Stub::stopped_after(u32::MAX, 2);
+        step_up(&config(100, 50, 1000), |tps| stub.measure(tps)).await;
+        assert_eq!(stub.measured(), vec![100, 150]);
+    }
+
+    #[tokio::test]
+    async fn bisect_narrows_to_step() {
+        let stub = Stub::new(637);
+        bisect(&config(100, 10, 1000), |tps| stub.measure(tps)).await;
+        let measured = stub.measured();
+        assert_eq!(measured[..2], [100, 1000]);
+        assert_eq!(measured[2..], [550, 775, 662, 606, 634, 648, 641]);
+    }
+
+    #[tokio::test]
+    async fn bisect_stops_when_bounds_decide() {
+        // Fails at the start: nothing to bisect.
+        let stub = Stub::new(50);
+        bisect(&config(100, 10, 1000), |tps| stub.measure(tps)).await;
+        assert_eq!(stub.measured(), vec![100]);
+
+        // Passes at the maximum: that is the answer.
+        let stub = Stub::new(2000);
+        bisect(&config(100, 10, 1000), |tps| stub.measure(tps)).await;
+        assert_eq!(stub.measured(), vec![100, 1000]);
+
+        // Bounds already within a step.
+        let stub = Stub::new(105);
+        bisect(&config(100, 10, 110), |tps| stub.measure(tps)).await;
+        assert_eq!(stub.measured(), vec![100, 110]);
+    }
+
+    #[tokio::test]
+    async fn bisect_stops_with_the_run() {
+        let stub = Stub::stopped_after(637, 3);
+        bisect(&config(100, 10, 1000), |tps| stub.measure(tps)).await;
+        assert_eq!(stub.measured(), vec![100, 1000, 550]);
+    }
+}
@@ -0,0 +1,524 @@
+use std::{
+    collections::HashMap,
+    io,
+    pin::Pin,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU32, Ordering},
+    },
+    task::{Context, Poll, Waker, ready},
+    time::Duration,
+};
+
+use rusmpp::{Command, CommandId, CommandStatus, Pdu, tokio_codec::CommandCodec};
+use rusmppc::error::Error as ClientError;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    sync::oneshot,
+    time,
+};
+use tokio_util::{
+    bytes::{Buf, BytesMut},
+    codec::{Decoder, Encoder},
+};
+
+/// SMPP header: command_length, command_id, command_status, sequence_number.
+const HEADER_LENGTH: usize = 16;
+/// Frames claiming to be longer than this are left to rusmppc to reject.
+const MAX_FRAME_LENGTH: usize = 64 * 1024;
+/// Outgoing bytes buffered before writes from rusmppc are held back.
+const MAX_BUFFERED: usize = 256 * 1024;
+const RESPONSE_BIT: u32 = 0x8000_0000;
+
+type Reply = Result<Command, ClientError>;
+
+/// Sends PDUs `rusmppc::Client` has no method for (`query_sm`, `replace_sm`,
+/// `cancel_sm`, `data_sm_resp`) over the client's own connection.
+///
+/// rusmppc numbers its requests with odd sequence numbers, so requests sent
+/// here use even ones and their responses are taken out of the stream before
+/// rusmppc sees them.
+#[derive(Clone)]
+pub struct SideChannel {
+    shared: Arc<Shared>,
+    sequence_number: Arc<AtomicU32>,
+    response_timeout: Option<Duration>,
+}
+
+/// Wraps `stream` before it is handed to rusmppc. `response_timeout` bounds
+/// every request sent through the returned `SideChannel`.
+pub fn wrap<S>(
+    stream: S,
+    response_timeout: Option<Duration>,
+) -> (SideChannelStream<S>, SideChannel) {
+    let shared = Arc::new(Shared::default());
+    let stream = SideChannelStream {
+        inner: stream,
+        shared: shared.clone(),
+        incoming: BytesMut::new(),
+        ready: BytesMut::new(),
+        partial: BytesMut::new(),
+        out: BytesMut::new(),
+        unframed: false,
+        eof: false,
+    };
+    let channel = SideChannel {
+        shared,
+        sequence_number: Arc::new(AtomicU32::new(0)),
+        response_timeout,
+    };
+    (stream, channel)
+}
+
+impl SideChannel {
+    /// Sends `pdu` and waits for a successful response of type `response`.
+    /// Fails like the rusmppc client does: `UnexpectedResponse` for an error
+    /// status or another command, `ResponseTimeout` when it doesn't come.
+    pub async fn request(
+        &self,
+        pdu: impl Into<Pdu>,
+        response: CommandId,
+    ) -> Result<Command, ClientError> {
+        let sequence_number = self.next_sequence_number();
+        let frame = encode(sequence_number, pdu.into())?;
+        let (reply_tx, reply) = oneshot::channel();
+        self.shared.send(frame, Some((sequence_number, reply_tx)))?;
+        let _pending = PendingGuard {
+            shared: &self.shared,
+            sequence_number,
+        };
+        let timed_out = |timeout| ClientError::ResponseTimeout {
+            sequence_number,
+            timeout,
+        };
+
+        let reply = match self.response_timeout {
+            Some(timeout) => time::timeout(timeout, reply)
+                .await
+                .map_err(|_| timed_out(timeout))?,
+            None => reply.await,
+        };
+        let command = reply.map_err(|_| ClientError::ConnectionClosed)??;
+        if command.status() != CommandStatus::EsmeRok || command.id() != response {
+            return Err(ClientError::UnexpectedResponse {
+                response: Box::new(command),
+            });
+        }
+        Ok(command)
+    }
+
+    /// Answers the SMSC's request `sequence_number` with `pdu`.
+    pub fn respond(&self, sequence_number: u32, pdu: impl Into<Pdu>) -> Result<(), ClientError> {
+        self.shared.send(encode(sequence_number, pdu.into())?, None)
+    }
+
+    /// Even numbers within 2..=0x7FFF_FFFE, the range SMPP allows.
+    fn next_sequence_number(&self) -> u32 {
+        let n = self.sequence_number.fetch_add(1, Ordering::Relaxed);
+        (n % 0x3FFF_FFFF + 1) * 2
+    }
+}
+
+fn encode(sequence_number: u32, pdu: Pdu) -> Result<BytesMut, ClientError> {
+    let command = Command::builder()
+        .status(CommandStatus::EsmeRok)
+        .sequence_number(sequence_number)
+        .pdu(pdu);
+    let mut frame = BytesMut::new();
+    CommandCodec::new()
+        .encode(command, &mut frame)
+        .map_err(ClientError::from)?;
+    Ok(frame)
+}
+
+/// Forgets a request once its caller stops waiting, answered or not.
+struct PendingGuard<'a> {
+    shared: &'a Shared,
+    sequence_number: u32,
+}
+
+impl Drop for PendingGuard<'_> {
+    fn drop(&mut self) {
+        self.shared.lock().pending.remove(&self.sequence_number);
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Encoded frames waiting to be written.
+    queued: BytesMut,
+    pending: HashMap<u32, oneshot::Sender<Reply>>,
+    /// Wakes the connection task, which always has a read outstanding.
+    waker: Option<Waker>,
+    closed: bool,
+}
+
+impl Shared {
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().expect("side channel lock poisoned")
+    }
+
+    fn send(
+        &self,
+        frame: BytesMut,
+        pending: Option<(u32, oneshot::Sender<Reply>)>,
+    ) -> Result<(), ClientError> {
+        let mut state = self.lock();
+        if state.closed {
+            return Err(ClientError::ConnectionClosed);
+        }
+        state.queued.extend_from_slice(&frame);
+        if let Some((sequence_number, reply)) = pending {
+            state.pending.insert(sequence_number, reply);
+        }
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+        Ok(())
+    }
+
+    /// Fails every waiting request and refuses new ones.
+    fn close(&self) {
+        let mut state = self.lock();
+        state.closed = true;
+        state.pending.clear();
+    }
+}
+
+/// The connection as rusmppc sees it. Frames written by rusmppc and by the
+/// `SideChannel` are interleaved whole; responses to side channel requests
+/// are routed to their callers.
+pub struct SideChannelStream<S> {
+    inner: S,
+    shared: Arc<Shared>,
+    /// Read from the socket, not yet a whole frame.
+    incoming: BytesMut,
+    /// Whole frames for rusmppc.
+    ready: BytesMut,
+    /// Written by rusmppc, not yet a whole frame.
+    partial: BytesMut,
+    /// Whole frames for the socket.
+    out: BytesMut,
+    /// Set once the peer sent something that isn't SMPP framing; from then
+    /// on bytes are passed through untouched and rusmppc reports the error.
+    unframed: bool,
+    eof: bool,
+}
+
+impl<S> SideChannelStream<S> {
+    /// Moves whole frames from `incoming` to `ready`, handing responses to
+    /// side channel requests to their callers instead.
+    fn route_incoming(&mut self) {
+        while !self.unframed && self.incoming.len() >= HEADER_LENGTH {
+            let length = frame_length(&self.incoming);
+            if !(HEADER_LENGTH..=MAX_FRAME_LENGTH).contains(&length) {
+                self.unframed = true;
+                break;
+            }
+            if self.incoming.len() < length {
+                break;
+            }
+            let mut frame = self.incoming.split_to(length);
+            let command_id = u32::from_be_bytes([frame[4], frame[5], frame[6], frame[7]]);
+            let sequence_number = u32::from_be_bytes([frame[12], frame[13], frame[14], frame[15]]);
+            if command_id & RESPONSE_BIT == 0 || sequence_number % 2 == 1 {
+                self.ready.extend_from_slice(&frame);
+                continue;
+            }
+            // rusmppc never sends even sequence numbers, so this response
+            // is ours even if nobody waits for it anymore.
+            let reply = self.shared.lock().pending.remove(&sequence_number);
+            if let Some(reply) = reply {
+                let command = match CommandCodec::new().decode(&mut frame) {
+                    Ok(Some(command)) => Ok(command),
+                    Ok(None) => Err(ClientError::ConnectionClosed),
+                    Err(err) => Err(ClientError::from(err)),
+                };
+                reply.send(command).ok();
+            } else {
+                tracing::debug!(sequence_number, "Dropping late side channel response");
+            }
+        }
+        if self.unframed {
+            self.ready.unsplit(self.incoming.split());
+        }
+    }
+
+    /// Moves whole frames written by rusmppc from `partial` to `out`.
+    fn frame_outgoing(&mut self) {
+        while self.partial.len() >= 4 {
+            let length = frame_length(&self.partial);
+            if length < HEADER_LENGTH {
+                self.out.unsplit(self.partial.split());
+                break;
+            }
+            if self.partial.len() < length {
+                break;
+            }
+            let frame = self.partial.split_to(length);
+            self.out.unsplit(frame);
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> SideChannelStream<S> {
+    /// Writes queued side channel frames and whole rusmppc frames.
+    fn poll_write_out(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        {
+            let mut state = self.shared.lock();
+            if !state.queued.is_empty() {
+                self.out.unsplit(state.queued.split());
+            }
+        }
+        while !self.out.is_empty() {
+            let written = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.out))?;
+            if written == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.out.advance(written);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+fn frame_length(bytes: &[u8]) -> usize {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for SideChannelStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.shared.lock().waker = Some(cx.waker().clone());
+        // rusmppc may be idle; the pending read is what gets side channel
+        // frames onto the wire.
+        if let Poll::Ready(Err(err)) = this.poll_write_out(cx) {
+            this.shared.close();
+            return Poll::Ready(Err(err));
+        }
+
+        loop {
+            if !this.ready.is_empty() {
+                let len = this.ready.len().min(buf.remaining());
+                buf.put_slice(&this.ready[..len]);
+                this.ready.advance(len);
+                return Poll::Ready(Ok(()));
+            }
+            if this.eof {
+                return Poll::Ready(Ok(()));
+            }
+
+            let mut chunk = [0; 8192];
+            let mut chunk_buf = ReadBuf::new(&mut chunk);
+            match ready!(Pin::new(&mut this.inner).poll_read(cx, &mut chunk_buf)) {
+                Ok(()) if chunk_buf.filled().is_empty() => {
+                    this.eof = true;
+                    this.shared.close();
+                    this.ready.unsplit(this.incoming.split());
+                }
+                Ok(()) => {
+                    this.incoming.extend_from_slice(chunk_buf.filled());
+                    this.route_incoming();
+                }
+                Err(err) => {
+                    this.shared.close();
+                    return Poll::Ready(Err(err));
+                }
+            }
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for SideChannelStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.out.len() >= MAX_BUFFERED {
+            ready!(this.poll_write_out(cx))?;
+        }
+        this.partial.extend_from_slice(buf);
+        this.frame_outgoing();
+        if let Poll::Ready(Err(err)) = this.poll_write_out(cx) {
+            return Poll::Ready(Err(err));
+        }
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_out(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.shared.close();
+        ready!(this.poll_write_out(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+impl<S> Drop for SideChannelStream<S> {
+    fn drop(&mut self) {
+        self.shared.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{SinkExt, StreamExt};
+    use rusmpp::pdus::{DataSmResp, QuerySm, QuerySmResp};
+    use rusmppc::ConnectionBuilder;
+    use tokio::io::{DuplexStream, duplex};
+    use tokio_util::codec::Framed;
+
+    use super::*;
+
+    type Smsc = Framed<DuplexStream, CommandCodec>;
+
+    /// A rusmppc client and a side channel over one in-memory connection,
+    /// with the SMSC end of it.
+    fn connect(response_timeout: Option<Duration>) -> (rusmppc::Client, SideChannel, Smsc) {
+        let (esme, smsc) = duplex(64 * 1024);
+        let (stream, side_channel) = wrap(esme, response_timeout);
+        let (client, events) = ConnectionBuilder::new()
+            .no_enquire_link_interval()
+            .no_response_timeout()
+            .connected(stream);
+        tokio::spawn(events.for_each(|_| async {}));
+        (client, side_channel, Framed::new(smsc, CommandCodec::new()))
+    }
+
+    fn command(status: CommandStatus, sequence_number: u32, pdu: impl Into<Pdu>) -> Command {
+        Command::builder()
+            .status(status)
+            .sequence_number(sequence_number)
+            .pdu(pdu.into())
+    }
+
+    async fn receive(smsc: &mut Smsc) -> Command {
+        smsc.next().await.unwrap().unwrap()
+    }
+
+    #[tokio::test]
+    async fn requests_interleave_with_the_client() {
+        let (client, side_channel, mut smsc) = connect(None);
+        let server = tokio::spawn(async move {
+            let mut seen = Vec::new();
+            // Both requests are in flight before either is answered.
+            let first = receive(&mut smsc).await;
+            let second = receive(&mut smsc).await;
+            for request in [second, first] {
+                let sequence_number = request.sequence_number();
+                seen.push((request.id(), sequence_number));
+                let reply = match request.id() {
+                    CommandId::EnquireLink => command(
+                        CommandStatus::EsmeRok,
+                        sequence_number,
+                        Pdu::EnquireLinkResp,
+                    ),
+                    CommandId::QuerySm => command(
+                        CommandStatus::EsmeRok,
+                        sequence_number,
+                        QuerySmResp::default(),
+                    ),
+                    id => panic!("unexpected {id:?}"),
+                };
+                smsc.send(reply).await.unwrap();
+            }
+            seen
+        });
+
+        let (enquire_link, query) = tokio::join!(
+            client.enquire_link(),
+            side_channel.request(QuerySm::default(), CommandId::QuerySmResp),
+        );
+        enquire_link.unwrap();
+        assert_eq!(query.unwrap().id(), CommandId::QuerySmResp);
+
+        let seen = server.await.unwrap();
+        assert_eq!(seen.len(), 2);
+        for (id, sequence_number) in seen {
+            let ours = id == CommandId::QuerySm;
+            assert_eq!(sequence_number % 2 == 0, ours, "{id:?} {sequence_number}");
+        }
+    }
+
+    #[tokio::test]
+    async fn error_status_is_an_unexpected_response() {
+        let (_client, side_channel, mut smsc) = connect(None);
+        tokio::spawn(async move {
+            let request = receive(&mut smsc).await;
+            let reply = command(
+                CommandStatus::EsmeRinvmsgid,
+                request.sequence_number(),
+                QuerySmResp::default(),
+            );
+            smsc.send(reply).await.unwrap();
+            // Keep the connection open until the test is done.
+            smsc.next().await;
+        });
+
+        let err = side_channel
+            .request(QuerySm::default(), CommandId::QuerySmResp)
+            .await
+            .unwrap_err();
+        match err {
+            ClientError::UnexpectedResponse { response } => {
+                assert_eq!(response.status(), CommandStatus::EsmeRinvmsgid);
+            }
+            err => panic!("unexpected {err:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn respond_answers_the_smsc_request() {
+        let (_client, side_channel, mut smsc) = connect(None);
+        side_channel.respond(7, DataSmResp::default()).unwrap();
+
+        let response = receive(&mut smsc).await;
+        assert_eq!(response.id(), CommandId::DataSmResp);
+        assert_eq!(response.sequence_number(), 7);
+        assert_eq!(response.status(), CommandStatus::EsmeRok);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn unanswered_request_times_out() {
+        let timeout = Duration::from_millis(500);
+        let (_client, side_channel, mut smsc) = connect(Some(timeout));
+        tokio::spawn(async move { while smsc.next().await.is_some() {} });
+
+        let err = side_channel
+            .request(QuerySm::default(), CommandId::QuerySmResp)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, ClientError::ResponseTimeout { timeout: t, .. } if t == timeout),
+            "{err:?}"
+        );
+        assert!(side_channel.shared.lock().pending.is_empty());
+    }
+
+    #[tokio::test]
+    async fn closed_connection_fails_requests() {
+        let (_client, side_channel, smsc) = connect(None);
+        drop(smsc);
+
+        let err = side_channel
+            .request(QuerySm::default(), CommandId::QuerySmResp)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ClientError::ConnectionClosed), "{err:?}");
+    }
+}
@@ -0,0 +1,188 @@
+use std::{collections::VecDeque, sync::Mutex, time::Duration};
+
+use dashmap::DashMap;
+use tokio::time::Instant;
+
+/// Concurrent map whose entries expire `timeout` after insertion, holding at
+/// most `capacity` entries (the oldest are evicted first).
+pub struct TimedIndex<V> {
+    entries: DashMap<String, (V, Instant)>,
+    /// Insertion order, used to find the oldest entries for expiry and eviction.
+    /// May hold IDs that were already removed; those are skipped.
+    order: Mutex<VecDeque<(String, Instant)>>,
+    capacity: usize,
+    timeout: Duration,
+}
+
+impl<V: Copy> TimedIndex<V> {
+    pub fn new(capacity: usize, timeout: Duration) -> Self {
+        Self {
+            entries: DashMap::with_capacity(capacity.min(64 * 1024)),
+            order: Mutex::new(VecDeque::new()),
+            capacity,
+            timeout,
+        }
+    }
+
+    /// Inserts an entry, returning the value evicted to make room, if any.
+    pub fn insert(&self, id: String, value: V, at: Instant) -> Option<V> {
+        let mut evicted = None;
+        {
+            let mut order = self.order.lock().expect("timed index order lock poisoned");
+            while self.entries.len() >= self.capacity {
+                let Some((oldest, oldest_at)) = order.pop_front() else {
+                    break;
+                };
+                if let Some((_, (value, _))) = self.remove_exact(&oldest, oldest_at) {
+                    evicted = Some(value);
+                }
+            }
+            order.push_back((id.clone(), at));
+            // Removed IDs stay queued until they would expire; compact the queue
+            // when they dominate so it stays proportional to the live set.
+            if order.len() > self.capacity.saturating_mul(2).max(1024) {
+                order.retain(|(id, at)| self.entries.get(id).is_some_and(|entry| entry.1 == *at));
+            }
+        }
+        self.entries.insert(id, (value, at));
+        evicted
+    }
+
+    pub fn remove(&self, id: &str) -> Option<V> {
+        self.entries.remove(id).map(|(_, (value, _))| value)
+    }
+
+    /// Modifies an entry in place, returning its updated value.
+    pub fn update(&self, id: &str, apply: impl FnOnce(&mut V)) -> Option<V> {
+        self.entries.get_mut(id).map(|mut entry| {
+            apply(&mut entry.0);
+            entry.0
+        })
+    }
+
+    pub fn get(&self, id: &str) -> Option<V> {
+        self.entries.get(id).map(|entry| entry.0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Drops and returns every entry inserted more than `timeout` before `now`.
+    pub fn expire(&self, now: Instant) -> Vec<V> {
+        let mut expired = Vec::new();
+        let mut order = self.order.lock().expect("timed index order lock poisoned");
+        while let Some((_, at)) = order.front() {
+            if now.saturating_duration_since(*at) < self.timeout {
+                break;
+            }
+            let Some((id, at)) = order.pop_front() else {
+                break;
+            };
+            if let Some((_, (value, _))) = self.remove_exact(&id, at) {
+                expired.push(value);
+            }
+        }
+        expired
+    }
+
+    /// Removes `id` only if it is still the entry inserted at `at`, so a
+    /// recycled message ID is not dropped by its predecessor's queue slot.
+    fn remove_exact(&self, id: &str, at: Instant) -> Option<(String, (V, Instant))> {
+        self.entries.remove_if(id, |_, entry| entry.1 == at)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn expires_entries_after_timeout() {
+        let start = Instant::now();
+        let index = TimedIndex::new(100, TIMEOUT);
+        index.insert("a".to_string(), 1, start);
+        index.insert("b".to_string(), 2, start + secs(5));
+
+        assert!(index.expire(start + secs(9)).is_empty());
+        assert_eq!(index.expire(start + secs(10)), vec![1]);
+        assert_eq!(index.get("a"), None);
+        assert_eq!(index.get("b"), Some(2));
+        assert_eq!(index.expire(start + secs(20)), vec![2]);
+        assert_eq!(index.len(), 0);
+    }
+
+    #[test]
+    fn removed_entries_do_not_expire() {
+        let start = Instant::now();
+        let index = TimedIndex::new(100, TIMEOUT);
+        index.insert("a".to_string(), 1, start);
+        index.insert("b".to_string(), 2, start);
+        assert_eq!(index.remove("a"), Some(1));
+        assert_eq!(index.remove("a"), None);
+        assert_eq!(index.expire(start + TIMEOUT), vec![2]);
+    }
+
+    #[test]
+    fn recycled_id_outlives_its_predecessor() {
+        let start = Instant::now();
+        let index = TimedIndex::new(100, TIMEOUT);
+        index.insert("a".to_string(), 1, start);
+        index.remove("a");
+        index.insert("a".to_string(), 2, start + secs(5));
+
+        // The first insert's queue slot expires without touching the second.
+        assert!(index.expire(start + secs(10)).is_empty());
+        assert_eq!(index.get("a"), Some(2));
+        assert_eq!(index.expire(start + secs(15)), vec![2]);
+    }
+
+    #[test]
+    fn evicts_oldest_beyond_capacity() {
+        let start = Instant::now();
+        let index = TimedIndex::new(2, TIMEOUT);
+        assert_eq!(index.insert("a".to_string(), 1, start), None);
+        assert_eq!(index.insert("b".to_string(), 2, start + secs(1)), None);
+        assert_eq!(index.insert("c".to_string(), 3, start + secs(2)), Some(1));
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.get("a"), None);
+
+        // Removed entries free their slot without being reported as evicted.
+        index.remove("b");
+        assert_eq!(index.insert("d".to_string(), 4, start + secs(3)), None);
+        assert_eq!(index.insert("e".to_string(), 5, start + secs(4)), Some(3));
+        assert_eq!(index.get("d"), Some(4));
+        assert_eq!(index.get("e"), Some(5));
+    }
+
+    #[test]
+    fn update_modifies_in_place() {
+        let start = Instant::now();
+        let index = TimedIndex::new(10, TIMEOUT);
+        index.insert("a".to_string(), 1, start);
+        assert_eq!(index.update("a", |value| *value += 10), Some(11));
+        assert_eq!(index.update("b", |value| *value += 10), None);
+        // Updating doesn't extend the entry's lifetime.
+        assert_eq!(index.expire(start + TIMEOUT), vec![11]);
+    }
+
+    #[test]
+    fn compacts_removed_ids() {
+        let start = Instant::now();
+        let index = TimedIndex::new(4, TIMEOUT);
+        for idx in 0..5000 {
+            let id = idx.to_string();
+            index.insert(id.clone(), idx, start);
+            index.remove(&id);
+        }
+        let queued = index.order.lock().unwrap().len();
+        assert!(queued <= 1025, "{queued} IDs still queued");
+        assert_eq!(index.len(), 0);
+    }
+}
@@ -0,0 +1,284 @@
+use std::{fs::File, io::BufReader, path::Path, sync::Arc};
+
+use anyhow::{Context, Result, bail};
+use tokio::net::TcpStream;
+use tokio_rustls::{
+    TlsConnector,
+    client::TlsStream,
+    rustls::{
+        ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme,
+        client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        crypto::{CryptoProvider, ring},
+        pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime},
+    },
+};
+
+use crate::config::TlsConfig;
+
+/// TLS client for one account. Certificates, keys and roots are loaded once
+/// and reused by every connection the account opens.
+pub struct TlsClient {
+    connector: TlsConnector,
+    name: String,
+    server_name: ServerName<'static>,
+}
+
+impl TlsClient {
+    /// `host` is checked against the server certificate unless `server_name`
+    /// is configured.
+    pub fn new(config: &TlsConfig, host: &str) -> Result<Self> {
+        let name = config.server_name.as_deref().unwrap_or(host).to_string();
+        let server_name = ServerName::try_from(name.clone())
+            .with_context(|| format!("invalid TLS server name '{name}'"))?;
+        Ok(Self {
+            connector: TlsConnector::from(Arc::new(client_config(config)?)),
+            name,
+            server_name,
+        })
+    }
+
+    /// Opens a TCP connection to `host:port` and performs the TLS handshake.
+    pub async fn connect(&self, host: &str, port: u16) -> Result<TlsStream<TcpStream>> {
+        let tcp = TcpStream::connect((host, port))
+            .await
+            .with_context(|| format!("failed to connect to {host}:{port}"))?;
+        tcp.set_nodelay(true).ok();
+
+        self.connector
+            .connect(self.server_name.clone(), tcp)
+            .await
+            .with_context(|| format!("TLS handshake with {} failed", self.name))
+    }
+}
+
+fn client_config(config: &TlsConfig) -> Result<ClientConfig> {
+    let provider = Arc::new(ring::default_provider());
+    let builder = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .context("failed to select TLS protocol versions")?;
+
+    let builder = if config.insecure_skip_verify {
+        tracing::warn!("TLS certificate verification is disabled");
+        builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(SkipVerification(provider)))
+    } else {
+        builder.with_root_certificates(root_store(config.ca_file.as_deref())?)
+    };
+
+    match (&config.cert_file, &config.key_file) {
+        (Some(cert_file), Some(key_file)) => builder
+            .with_client_auth_cert(load_certs(cert_file)?, load_key(key_file)?)
+            .context("invalid client certificate or key"),
+        (None, None) => Ok(builder.with_no_client_auth()),
+        _ => bail!("tls.cert_file and tls.key_file must be set together"),
+    }
+}
+
+fn root_store(ca_file: Option<&Path>) -> Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    match ca_file {
+        Some(path) => {
+            for cert in load_certs(path)? {
+                roots
+                    .add(cert)
+                    .with_context(|| format!("invalid CA certificate in {}", path.display()))?;
+            }
+        }
+        None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+    }
+    Ok(roots)
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let file = File::open(path).with_context(|| format!("Unable to read {}", path.display()))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Failed to parse certificates from {}", path.display()))?;
+    if certs.is_empty() {
+        bail!("no certificates found in {}", path.display());
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &Path) -> Result<PrivateKeyDer<'static>> {
+    let file = File::open(path).with_context(|| format!("Unable to read {}", path.display()))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .with_context(|| format!("Failed to parse private key from {}", path.display()))?
+        .with_context(|| format!("no private key found in {}", path.display()))
+}
+
+/// Certificate verifier for `insecure_skip_verify`: accepts any certificate but
+/// still checks handshake signatures so the session itself is sound.
+#[derive(Debug)]
+struct SkipVerification(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for SkipVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, tokio_rustls::rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        tokio_rustls::rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        tokio_rustls::rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+    use tokio_rustls::{TlsAcceptor, rustls::ServerConfig};
+
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("testdata/tls")
+            .join(name)
+    }
+
+    /// Trusts the test CA that signed the `localhost` server certificate.
+    fn config() -> TlsConfig {
+        TlsConfig {
+            enabled: true,
+            ca_file: Some(fixture("ca.pem")),
+            cert_file: None,
+            key_file: None,
+            server_name: Some("localhost".to_string()),
+            insecure_skip_verify: false,
+        }
+    }
+
+    /// Serves the `localhost` certificate on a loopback port and echoes what
+    /// each connection sends first.
+    async fn echo_server() -> u16 {
+        let server_config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(
+                load_certs(&fixture("server.pem")).unwrap(),
+                load_key(&fixture("server.key")).unwrap(),
+            )
+            .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(server_config));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((tcp, _)) = listener.accept().await {
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    let Ok(mut stream) = acceptor.accept(tcp).await else {
+                        return;
+                    };
+                    let mut buf = [0; 64];
+                    if let Ok(len) = stream.read(&mut buf).await {
+                        stream.write_all(&buf[..len]).await.ok();
+                        stream.shutdown().await.ok();
+                    }
+                });
+            }
+        });
+        port
+    }
+
+    async fn round_trip(client: &TlsClient, port: u16, message: &[u8]) -> Result<Vec<u8>> {
+        let mut stream = client.connect("127.0.0.1", port).await?;
+        stream.write_all(message).await?;
+        let mut echoed = Vec::new();
+        stream.read_to_end(&mut echoed).await?;
+        Ok(echoed)
+    }
+
+    #[tokio::test]
+    async fn one_client_serves_every_connection() {
+        let port = echo_server().await;
+        let client = TlsClient::new(&config(), "127.0.0.1").unwrap();
+        for n in 0..3 {
+            let message = format!("ping {n}");
+            let echoed = round_trip(&client, port, message.as_bytes()).await.unwrap();
+            assert_eq!(echoed, message.as_bytes());
+        }
+    }
+
+    #[tokio::test]
+    async fn server_name_mismatch_is_rejected() {
+        let port = echo_server().await;
+        let config = TlsConfig {
+            server_name: Some("smsc.example.com".to_string()),
+            ..config()
+        };
+        let client = TlsClient::new(&config, "127.0.0.1").unwrap();
+        let err = round_trip(&client, port, b"ping").await.unwrap_err();
+        assert!(
+            format!("{err:#}").contains("TLS handshake with smsc.example.com failed"),
+            "{err:#}"
+        );
+    }
+
+    #[tokio::test]
+    async fn untrusted_certificate_needs_skip_verify() {
+        let port = echo_server().await;
+        let config = TlsConfig {
+            ca_file: None,
+            ..config()
+        };
+        let client = TlsClient::new(&config, "127.0.0.1").unwrap();
+        assert!(round_trip(&client, port, b"ping").await.is_err());
+
+        let config = TlsConfig {
+            insecure_skip_verify: true,
+            ..config
+        };
+        let client = TlsClient::new(&config, "127.0.0.1").unwrap();
+        assert_eq!(round_trip(&client, port, b"ping").await.unwrap(), b"ping");
+    }
+
+    #[test]
+    fn client_certificate_needs_its_key() {
+        let config = TlsConfig {
+            cert_file: Some(fixture("server.pem")),
+            ..config()
+        };
+        assert!(TlsClient::new(&config, "127.0.0.1").is_err());
+    }
+}
@@ -1,67 +1,213 @@
-use std::{str::FromStr, sync::Arc, sync::atomic::{AtomicU64, Ordering}, time::Duration};
+use std::{collections::VecDeque, str::FromStr, sync::Arc, sync::atomic::{AtomicU64, Ordering}, time::Duration};
 
 use anyhow::{Context, Result};
-use futures::{FutureExt, StreamExt, future::BoxFuture, stream::FuturesUnordered};
+use futures::{StreamExt, future::BoxFuture, stream::FuturesUnordered};
 use rusmpp::{
-    CommandId,
-    pdus::{BindTransceiver, BindTransmitter, SubmitSm},
+    pdus::{BindReceiver, BindTransceiver, BindTransmitter, SubmitSm},
     types::{COctetString, OctetString},
     values::{DataCoding, EsmClass, RegisteredDelivery, ServiceType},
 };
-use rusmpp::Pdu;
-use rusmpp::tlvs::TlvValue;
-use rusmppc::{ConnectionBuilder, Event, error::Error as ClientError};
-use tokio::time::{self, Instant, MissedTickBehavior};
+use rusmppc::error::Error as ClientError;
+use tokio::{
+    sync::watch,
+    time::{self, Instant, MissedTickBehavior},
+};
 use tokio_util::sync::CancellationToken;
 
 use crate::{
-    bind_tracker::{BindState, BindTracker},
-    config::{BindType, Config, MessageConfig},
+    bind_tracker::{BindState, BindTracker, format_ms},
+    config::{AccountConfig, Arrival, BindType, DisconnectAction, MessageConfig, SmppConfig},
+    connection::Connector,
+    control::ControlState,
+    correlation::CorrelationStore,
+    events::{Inbound, SessionEnd, status_name},
     metrics::Metrics,
+    operations::{Dispatcher, Operation, OperationOutcome},
+    rate_limiter::{ArrivalSchedule, TokenBucket},
 };
 
-pub async fn spawn_bind(
-    idx: usize,
-    config: Arc<Config>,
-    metrics: Arc<Metrics>,
-    tracker: Arc<BindTracker>,
-    shutdown: CancellationToken,
-    messages_sent: Arc<AtomicU64>,
-    messages_limit: u64,
-) {
-    tracker.set_state(idx, BindState::Connecting).await;
+/// Scheduled sends an open-loop bind keeps waiting for a free slot; beyond
+/// this the oldest are dropped so a stalled SMSC can't exhaust memory.
+const MAX_OPEN_LOOP_BACKLOG: usize = 100_000;
+/// How often an open-loop bind looks at its share of the account's rate.
+const OPEN_LOOP_RATE_CHECK: Duration = Duration::from_millis(200);
+
+/// Everything a bind slot shares across its sessions: its account, the
+/// run-wide metrics and stores, and the account's rate and message budget.
+pub struct BindContext {
+    pub idx: usize,
+    pub account: Arc<AccountConfig>,
+    /// The account's connection setup, shared by all of its binds.
+    pub connector: Arc<Connector>,
+    pub metrics: Arc<Metrics>,
+    pub tracker: Arc<BindTracker>,
+    pub sent_index: Arc<CorrelationStore>,
+    pub inbound: Arc<Inbound>,
+    /// The account's `total_tps` bucket, shared by all of its binds.
+    pub rate_limiter: Option<Arc<TokenBucket>>,
+    pub control: watch::Receiver<ControlState>,
+    /// Messages accepted across the account's binds, for `messages_count`.
+    pub messages_sent: Arc<AtomicU64>,
+}
 
-    let result = run_bind(idx, config, metrics, tracker.clone(), shutdown.clone(), messages_sent, messages_limit).await;
+impl BindContext {
+    /// Whether the account has sent its `messages_count`.
+    fn limit_reached(&self) -> bool {
+        let limit = self.account.load.messages_count;
+        limit > 0 && self.messages_sent.load(Ordering::Relaxed) >= limit
+    }
+}
 
-    if let Err(err) = result {
-        tracing::error!(bind = idx, error = ?err, "Bind task failed");
-        tracker
-            .set_state(idx, BindState::Error(err.to_string()))
-            .await;
+pub async fn spawn_bind(ctx: BindContext, shutdown: CancellationToken) {
+    let idx = ctx.idx;
+    let mut reconnecting = false;
+    loop {
+        ctx.tracker.set_state(idx, BindState::Connecting).await;
+
+        match run_bind(&ctx, shutdown.clone()).await {
+            Ok(SessionEnd::Finished) => return,
+            // `run_bind` already recorded how the session ended.
+            Ok(_) => {}
+            Err(err) => {
+                tracing::error!(bind = idx, error = ?err, "Bind task failed");
+                ctx.tracker
+                    .transition(
+                        idx,
+                        BindState::Error(err.to_string()),
+                        Some(failure_detail(&err)),
+                    )
+                    .await;
+                // A failed first bind is a configuration or SMSC problem;
+                // only sessions that were up before keep retrying.
+                if !reconnecting {
+                    return;
+                }
+            }
+        }
+
+        if ctx.account.smpp.on_disconnect == DisconnectAction::Stop || ctx.limit_reached() {
+            return;
+        }
+        tokio::select! {
+            _ = shutdown.cancelled() => return,
+            _ = time::sleep(ctx.account.smpp.reconnect_delay()) => {}
+        }
+        reconnecting = true;
+        ctx.metrics.record_reconnect(idx);
     }
 }
 
-async fn run_bind(
-    idx: usize,
-    config: Arc<Config>,
-    metrics: Arc<Metrics>,
-    tracker: Arc<BindTracker>,
-    shutdown: CancellationToken,
-    messages_sent: Arc<AtomicU64>,
-    messages_limit: u64,
-) -> Result<()> {
-    let (client, mut events) = ConnectionBuilder::new()
-        .enquire_link_interval(Duration::from_secs(5))
-        .response_timeout(Duration::from_secs(5))
-        .connect(&config.smpp.connection_uri())
+/// Runs one session, from connect to unbind, and records its timings and
+/// final state. Returns how it ended so `spawn_bind` can decide whether to
+/// reconnect.
+async fn run_bind(ctx: &BindContext, shutdown: CancellationToken) -> Result<SessionEnd> {
+    let (idx, account, metrics, tracker) = (ctx.idx, &ctx.account, &ctx.metrics, &ctx.tracker);
+    let start = Instant::now();
+    let (client, side_channel, events) = ctx.connector.open().await?;
+    let connect = start.elapsed();
+    metrics.record_connect(connect);
+
+    let bind_timeout = account.smpp.bind_timeout();
+    let start = Instant::now();
+    time::timeout(bind_timeout, bind(&client, &account.smpp))
         .await
-        .context("failed to connect to SMPP server")?;
+        .map_err(|_| anyhow::anyhow!("bind timed out after {bind_timeout:?}"))??;
+    let bind_latency = start.elapsed();
+    metrics.record_bind(bind_latency);
+
+    let detail = format!(
+        "connect {}, bind {}",
+        format_ms(connect),
+        format_ms(bind_latency)
+    );
+    tracker
+        .transition(idx, BindState::Bound, Some(detail))
+        .await;
+    let bound_at = Instant::now();
+
+    // Cancelled by the run's shutdown or by the SMSC ending the session.
+    let session = shutdown.child_token();
+    let event_task = ctx
+        .inbound
+        .clone()
+        .spawn(
+            idx,
+            client.clone(),
+            side_channel.clone(),
+            events,
+            session.clone(),
+        );
+
+    let keepalive = account.smpp.enquire_link_interval().map(|interval| {
+        tokio::spawn(drive_enquire_link(
+            idx,
+            client.clone(),
+            interval,
+            account.smpp.response_timeout().unwrap_or(interval),
+            metrics.clone(),
+            session.clone(),
+        ))
+    });
+
+    if account.smpp.bind_type == BindType::Rx {
+        // Receivers only take deliver_sm; stay bound until the session ends.
+        session.cancelled().await;
+    } else {
+        let profiles = std::iter::once(&account.message)
+            .chain(&account.profiles)
+            .map(|message| Ok((build_submit_sm(message)?, message.clone())))
+            .collect::<Result<Vec<_>>>()?;
+        let dispatcher = Dispatcher::new(
+            client.clone(),
+            side_channel,
+            profiles,
+            account.load.operations.clone(),
+            ctx.control.clone(),
+        );
+
+        drive_submit_loop(ctx, dispatcher, session.clone()).await?;
+    }
+
+    session.cancel();
+    if let Some(keepalive) = keepalive {
+        keepalive.abort();
+    }
+    let end = event_task.await.unwrap_or(SessionEnd::Finished);
+    let bound = bound_at.elapsed();
+    metrics.record_bound(bound);
+    let mut detail = format!("bound {:.1} s", bound.as_secs_f64());
+    let state = if end == SessionEnd::Finished {
+        let start = Instant::now();
+        if client.unbind().await.is_ok() {
+            let unbind = start.elapsed();
+            metrics.record_unbind(unbind);
+            detail = format!("{detail}, unbind {}", format_ms(unbind));
+        }
+        BindState::Stopped
+    } else {
+        BindState::Unbound(end.reason().to_string())
+    };
+    client.close().await.ok();
+    tracker.transition(idx, state, Some(detail)).await;
+    Ok(end)
+}
+
+/// Cause kept in a bind's history: the `command_status` of a rejected bind,
+/// otherwise the whole error chain.
+fn failure_detail(err: &anyhow::Error) -> String {
+    match err.downcast_ref::<ClientError>() {
+        Some(ClientError::UnexpectedResponse { response }) => status_name(response.status()),
+        _ => format!("{err:#}"),
+    }
+}
 
-    match config.smpp.bind_type {
+/// Sends the bind request matching `bind_type` and waits for its response.
+pub async fn bind(client: &rusmppc::Client, smpp: &SmppConfig) -> Result<()> {
+    match smpp.bind_type {
         BindType::Trx => {
             client
                 .bind_transceiver(
-                    build_bind_trx_pdu(&config).context("failed to build TRX bind request")?,
+                    build_bind_trx_pdu(smpp).context("failed to build TRX bind request")?,
                 )
                 .await
                 .context("failed to bind as TRX")?;
@@ -69,147 +215,86 @@ async fn run_bind(
         BindType::Tx => {
             client
                 .bind_transmitter(
-                    build_bind_tx_pdu(&config).context("failed to build TX bind request")?,
+                    build_bind_tx_pdu(smpp).context("failed to build TX bind request")?,
                 )
                 .await
                 .context("failed to bind as TX")?;
         }
+        BindType::Rx => {
+            client
+                .bind_receiver(build_bind_rx_pdu(smpp).context("failed to build RX bind request")?)
+                .await
+                .context("failed to bind as RX")?;
+        }
     }
+    Ok(())
+}
 
-    tracker.set_state(idx, BindState::Bound).await;
-
-    let submit_template = build_submit_sm(&config.message)?;
-    let client_for_events = client.clone();
-    let event_shutdown = shutdown.clone();
-
-    // Track submit_sm send times by message_id so we can compute DLR delay
-    let sent_index: Arc<dashmap::DashMap<String, Instant>> =
-        Arc::new(dashmap::DashMap::with_capacity(1024));
-    let sent_index_events = sent_index.clone();
-    let metrics_for_events = metrics.clone();
-
-    tokio::spawn(async move {
-        while let Some(event) = events.next().await {
-            if event_shutdown.is_cancelled() {
-                break;
-            }
+/// Sends `enquire_link` every `interval` and records its round-trip time.
+async fn drive_enquire_link(
+    idx: usize,
+    client: rusmppc::Client,
+    interval: Duration,
+    timeout: Duration,
+    metrics: Arc<Metrics>,
+    shutdown: CancellationToken,
+) {
+    let mut ticker = time::interval_at(Instant::now() + interval, interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
-            match event {
-                Event::Incoming(command) => {
-                    tracing::debug!(bind = idx, ?command, "Incoming command");
-                    if command.id() == CommandId::DeliverSm {
-                        let _ = client_for_events
-                            .deliver_sm_resp(
-                                command.sequence_number(),
-                                rusmpp::pdus::DeliverSmResp::default(),
-                            )
-                            .await;
-
-                        if let Some(pdu) = command.pdu() {
-                            if let Pdu::DeliverSm(deliver) = pdu {
-                                for tlv in deliver.tlvs().iter() {
-                                    if let rusmpp::tlvs::TlvTag::ReceiptedMessageId = tlv.tag() {
-                                        if let Some(val) = tlv.value() {
-                                            if let TlvValue::ReceiptedMessageId(co) = val {
-                                                let id = co.as_str().to_string();
-                                                if let Some(start) = sent_index_events.remove(&id).map(|e| e.1) {
-                                                    metrics_for_events.record_dlr(idx, start.elapsed());
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                                // Record DLR outcome if MessageState TLV is present
-                                for tlv in deliver.tlvs().iter() {
-                                    if let rusmpp::tlvs::TlvTag::MessageState = tlv.tag() {
-                                        if let Some(val) = tlv.value() {
-                                            if let TlvValue::MessageState(ms) = val {
-                                                use rusmpp::values::MessageState as MS;
-                                                let delivered = matches!(ms, MS::Delivered);
-                                                let failed = matches!(ms, MS::Undeliverable | MS::Rejected | MS::Expired | MS::Deleted);
-                                                metrics_for_events.record_dlr_status(idx, delivered, failed);
-                                                metrics_for_events.record_dlr_state(idx, *ms);
-                                            }
-                                        }
-                                    }
-                                }
-                                // Fallback: parse id/stat from textual short_message if TLVs missing
-                                let sm_text_res = deliver.short_message().to_str();
-                                if let Ok(sm_text) = sm_text_res {
-                                    if let Some((id, ms)) = parse_textual_dlr(sm_text) {
-                                        if let Some(start) = sent_index_events.remove(&id).map(|e| e.1) {
-                                            metrics_for_events.record_dlr(idx, start.elapsed());
-                                        }
-                                        metrics_for_events.record_dlr_state(idx, ms);
-                                        let delivered = matches!(ms, rusmpp::values::MessageState::Delivered);
-                                        let failed = matches!(ms, rusmpp::values::MessageState::Undeliverable
-                                            | rusmpp::values::MessageState::Rejected
-                                            | rusmpp::values::MessageState::Expired
-                                            | rusmpp::values::MessageState::Deleted);
-                                        metrics_for_events.record_dlr_status(idx, delivered, failed);
-                                    }
-                                }
-                            }
-                        }
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = ticker.tick() => {
+                let start = Instant::now();
+                match time::timeout(timeout, client.enquire_link()).await {
+                    Ok(Ok(())) => metrics.record_enquire_link(idx, start.elapsed()),
+                    Ok(Err(ClientError::ResponseTimeout { .. })) | Err(_) => {
+                        tracing::warn!(bind = idx, ?timeout, "enquire_link timed out");
+                        metrics.record_enquire_link_timeout(idx);
+                    }
+                    Ok(Err(err)) => {
+                        tracing::warn!(bind = idx, ?err, "enquire_link failed");
+                        metrics.record_enquire_link_error(idx);
                     }
-                }
-                Event::Error(err) => {
-                    tracing::warn!(bind = idx, ?err, "Background error");
                 }
             }
         }
-    });
+    }
+}
 
-    drive_submit_loop(
-        idx,
-        client.clone(),
-        submit_template,
-        metrics,
-        tracker.clone(),
-        &config,
-        shutdown,
-        sent_index,
-        messages_sent,
-        messages_limit,
-    )
-    .await?;
-
-    client.unbind().await.ok();
-    client.close().await.ok();
-    Ok(())
+/// Builds any of the bind PDUs; they only differ in their type.
+macro_rules! bind_pdu {
+    ($pdu:ident, $smpp:expr) => {{
+        let smpp: &SmppConfig = $smpp;
+        $pdu::builder()
+            .system_id(COctetString::from_str(&smpp.system_id)?)
+            .password(COctetString::from_str(&smpp.password)?)
+            .system_type(match smpp.system_type.as_deref() {
+                Some(system_type) => COctetString::from_str(system_type)?,
+                None => COctetString::empty(),
+            })
+            .interface_version(smpp.interface_version())
+            .addr_ton(smpp.addr_ton())
+            .addr_npi(smpp.addr_npi())
+            .address_range(COctetString::from_str(&smpp.address_range)?)
+            .build()
+    }};
 }
 
-fn build_bind_trx_pdu(config: &Config) -> Result<BindTransceiver> {
-    Ok(BindTransceiver::builder()
-        .system_id(COctetString::from_str(&config.smpp.system_id)?)
-        .password(COctetString::from_str(&config.smpp.password)?)
-        .system_type(if let Some(system_type) = &config.smpp.system_type {
-            COctetString::from_str(system_type)?
-        } else {
-            COctetString::empty()
-        })
-        .addr_ton(config.message.source_ton())
-        .addr_npi(config.message.source_npi())
-        .address_range(COctetString::empty())
-        .build())
+fn build_bind_trx_pdu(smpp: &SmppConfig) -> Result<BindTransceiver> {
+    Ok(bind_pdu!(BindTransceiver, smpp))
 }
 
-fn build_bind_tx_pdu(config: &Config) -> Result<BindTransmitter> {
-    Ok(BindTransmitter::builder()
-        .system_id(COctetString::from_str(&config.smpp.system_id)?)
-        .password(COctetString::from_str(&config.smpp.password)?)
-        .system_type(if let Some(system_type) = &config.smpp.system_type {
-            COctetString::from_str(system_type)?
-        } else {
-            COctetString::empty()
-        })
-        .addr_ton(config.message.source_ton())
-        .addr_npi(config.message.source_npi())
-        .address_range(COctetString::empty())
-        .build())
+fn build_bind_tx_pdu(smpp: &SmppConfig) -> Result<BindTransmitter> {
+    Ok(bind_pdu!(BindTransmitter, smpp))
+}
+
+fn build_bind_rx_pdu(smpp: &SmppConfig) -> Result<BindReceiver> {
+    Ok(bind_pdu!(BindReceiver, smpp))
 }
 
-fn build_submit_sm(message: &MessageConfig) -> Result<SubmitSm> {
+pub fn build_submit_sm(message: &MessageConfig) -> Result<SubmitSm> {
     let service_type = if let Some(raw) = message.service_type.as_deref() {
         if raw.is_empty() {
             ServiceType::default()
@@ -239,292 +324,257 @@ fn build_submit_sm(message: &MessageConfig) -> Result<SubmitSm> {
         .build())
 }
 
-fn parse_textual_dlr(text: &str) -> Option<(String, rusmpp::values::MessageState)> {
-    let mut id: Option<String> = None;
-    let mut stat: Option<String> = None;
-    // Tokenize on whitespace; fields are key:value
-    for token in text.split_whitespace() {
-        if let Some(rest) = token.strip_prefix("id:") {
-            if !rest.is_empty() {
-                id = Some(rest.to_string());
-            }
-        } else if let Some(rest) = token.strip_prefix("stat:") {
-            if !rest.is_empty() {
-                stat = Some(rest.to_string());
-            }
-        }
-        if id.is_some() && stat.is_some() {
-            break;
-        }
-    }
-    let id = id?;
-    let ms = map_stat_to_message_state(stat.as_deref().unwrap_or(""));
-    Some((id, ms))
-}
-
-fn map_stat_to_message_state(stat: &str) -> rusmpp::values::MessageState {
-    use rusmpp::values::MessageState as MS;
-    match stat.to_ascii_uppercase().as_str() {
-        "DELIVRD" | "DELIVERED" => MS::Delivered,
-        "ENROUTE" => MS::Enroute,
-        "EXPIRED" => MS::Expired,
-        "DELETED" => MS::Deleted,
-        "UNDELIV" | "UNDELIVERABLE" => MS::Undeliverable,
-        "ACCEPTD" | "ACCEPTED" => MS::Accepted,
-        "REJECTD" | "REJECTED" => MS::Rejected,
-        "UNKNOWN" => MS::Unknown,
-        _ => MS::Unknown,
-    }
-}
+type Inflight = FuturesUnordered<BoxFuture<'static, OperationOutcome>>;
 
 async fn drive_submit_loop(
-    idx: usize,
-    client: rusmppc::Client,
-    submit_template: SubmitSm,
-    metrics: Arc<Metrics>,
-    tracker: Arc<BindTracker>,
-    config: &Config,
+    ctx: &BindContext,
+    dispatcher: Dispatcher,
     shutdown: CancellationToken,
-    sent_index: Arc<dashmap::DashMap<String, Instant>>,
-    messages_sent: Arc<AtomicU64>,
-    messages_limit: u64,
 ) -> Result<()> {
-    let max_tps = config.load.max_tps_per_bind();
-    let max_inflight = config.load.inflight_per_bind().max(1);
-
-    let inflight: FuturesUnordered<BoxFuture<'static, SubmissionOutcome>> = FuturesUnordered::new();
-
-    if max_tps == 0 {
-        drive_unthrottled_loop(
-            idx,
-            inflight,
-            metrics,
-            max_inflight,
-            client,
-            submit_template,
-            tracker,
-            shutdown,
-            sent_index,
-            messages_sent,
-            messages_limit,
-        )
-        .await
+    let load = &ctx.account.load;
+    if load.arrival != Arrival::Batched {
+        drive_open_loop(ctx, dispatcher, shutdown).await
+    } else if load.max_tps_per_bind() == 0 && ctx.rate_limiter.is_none() {
+        drive_unthrottled_loop(ctx, dispatcher, shutdown).await
     } else {
-        drive_throttled_loop(
-            idx,
-            inflight,
-            metrics,
-            max_inflight,
-            client,
-            submit_template,
-            max_tps,
-            tracker,
-            shutdown,
-            sent_index,
-            messages_sent,
-            messages_limit,
-        )
-        .await
+        drive_throttled_loop(ctx, dispatcher, shutdown).await
     }
 }
 
 async fn drive_unthrottled_loop(
-    idx: usize,
-    mut inflight: FuturesUnordered<BoxFuture<'static, SubmissionOutcome>>,
-    metrics: Arc<Metrics>,
-    max_inflight: usize,
-    client: rusmppc::Client,
-    submit_template: SubmitSm,
-    tracker: Arc<BindTracker>,
+    ctx: &BindContext,
+    mut dispatcher: Dispatcher,
     shutdown: CancellationToken,
-    sent_index: Arc<dashmap::DashMap<String, Instant>>,
-    messages_sent: Arc<AtomicU64>,
-    messages_limit: u64,
 ) -> Result<()> {
-    fill_inflight(
-        &mut inflight,
-        max_inflight,
-        client.clone(),
-        submit_template.clone(),
-        messages_sent.clone(),
-        messages_limit,
-    );
+    let max_inflight = ctx.account.load.inflight_per_bind().max(1);
+    let mut inflight = Inflight::new();
+    let mut control = ctx.control.clone();
+    if !control.borrow_and_update().paused {
+        fill_inflight(ctx, &mut inflight, max_inflight, &mut dispatcher);
+    }
 
-    while !shutdown.is_cancelled() {
-        // Check if limit is reached
-        if messages_limit > 0 && messages_sent.load(Ordering::Relaxed) >= messages_limit {
-            break;
-        }
-        
+    while !shutdown.is_cancelled() && !ctx.limit_reached() {
         tokio::select! {
             _ = shutdown.cancelled() => break,
+            Ok(()) = control.changed() => {
+                // Resuming refills the window the pause let drain.
+                if !control.borrow_and_update().paused {
+                    fill_inflight(ctx, &mut inflight, max_inflight, &mut dispatcher);
+                }
+            }
             Some(outcome) = inflight.next() => {
-                handle_outcome(idx, outcome, &metrics, &tracker, &sent_index, messages_sent.clone()).await;
-                
-                // Check limit again after handling outcome
-                if messages_limit > 0 && messages_sent.load(Ordering::Relaxed) >= messages_limit {
+                handle_outcome(ctx, outcome, &mut dispatcher).await;
+                if ctx.limit_reached() {
                     break;
                 }
-                
-                queue_if_capacity(
-                    &mut inflight,
-                    max_inflight,
-                    client.clone(),
-                    submit_template.clone(),
-                    messages_sent.clone(),
-                    messages_limit,
-                );
+                if !control.borrow().paused && inflight.len() < max_inflight {
+                    inflight.push(dispatcher.next(|id| ctx.sent_index.is_pending(id)));
+                }
             }
         }
     }
 
-    drain_inflight(idx, inflight, &metrics, &tracker, &sent_index, messages_sent).await;
+    drain_inflight(ctx, inflight, &mut dispatcher).await;
     Ok(())
 }
 
 async fn drive_throttled_loop(
-    idx: usize,
-    mut inflight: FuturesUnordered<BoxFuture<'static, SubmissionOutcome>>,
-    metrics: Arc<Metrics>,
-    max_inflight: usize,
-    client: rusmppc::Client,
-    submit_template: SubmitSm,
-    max_tps: u32,
-    tracker: Arc<BindTracker>,
+    ctx: &BindContext,
+    mut dispatcher: Dispatcher,
     shutdown: CancellationToken,
-    sent_index: Arc<dashmap::DashMap<String, Instant>>,
-    messages_sent: Arc<AtomicU64>,
-    messages_limit: u64,
 ) -> Result<()> {
     const TICK_MS: u64 = 10;
     let ticks_per_sec = (1000 / TICK_MS) as u32;
+    let max_tps = ctx.account.load.max_tps_per_bind();
+    let max_inflight = ctx.account.load.inflight_per_bind().max(1);
+    let mut inflight = Inflight::new();
     let mut allowance = 0u32;
     let mut remainder = 0u32;
     let mut ticker = time::interval(Duration::from_millis(TICK_MS));
     ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
-    while !shutdown.is_cancelled() {
-        // Check if limit is reached
-        if messages_limit > 0 && messages_sent.load(Ordering::Relaxed) >= messages_limit {
-            break;
-        }
-        
+    while !shutdown.is_cancelled() && !ctx.limit_reached() {
         tokio::select! {
             _ = shutdown.cancelled() => break,
             Some(outcome) = inflight.next(), if !inflight.is_empty() => {
-                handle_outcome(idx, outcome, &metrics, &tracker, &sent_index, messages_sent.clone()).await;
-                
-                // Check limit again after handling outcome
-                if messages_limit > 0 && messages_sent.load(Ordering::Relaxed) >= messages_limit {
-                    break;
-                }
+                handle_outcome(ctx, outcome, &mut dispatcher).await;
             }
             _ = ticker.tick() => {
-                // Check limit before processing tick
-                if messages_limit > 0 && messages_sent.load(Ordering::Relaxed) >= messages_limit {
-                    break;
-                }
-                
-                allowance += max_tps / ticks_per_sec;
-                remainder += max_tps % ticks_per_sec;
-                if remainder >= ticks_per_sec {
-                    allowance += 1;
-                    remainder -= ticks_per_sec;
+                let state = *ctx.control.borrow();
+                if state.paused {
+                    // Nothing accrues while paused, so resuming doesn't burst.
+                    allowance = 0;
+                } else if let Some(bucket) = &ctx.rate_limiter {
+                    // Only draw what fits in the window; the rest is left to
+                    // the account's other binds. The bucket's rate is already
+                    // scaled by the controls.
+                    allowance = bucket.take(max_inflight.saturating_sub(inflight.len())) as u32;
+                } else {
+                    let max_tps = state.scale_tps(max_tps);
+                    allowance += max_tps / ticks_per_sec;
+                    remainder += max_tps % ticks_per_sec;
+                    if remainder >= ticks_per_sec {
+                        allowance += 1;
+                        remainder -= ticks_per_sec;
+                    }
                 }
 
-                while allowance > 0 && inflight.len() < max_inflight {
-                    if messages_limit > 0 && messages_sent.load(Ordering::Relaxed) >= messages_limit {
-                        break;
-                    }
-                    inflight.push(submit_once(client.clone(), submit_template.clone()));
+                while allowance > 0 && inflight.len() < max_inflight && !ctx.limit_reached() {
+                    inflight.push(dispatcher.next(|id| ctx.sent_index.is_pending(id)));
                     allowance -= 1;
                 }
             }
         }
     }
 
-    drain_inflight(idx, inflight, &metrics, &tracker, &sent_index, messages_sent).await;
+    drain_inflight(ctx, inflight, &mut dispatcher).await;
     Ok(())
 }
 
-fn fill_inflight(
-    inflight: &mut FuturesUnordered<BoxFuture<'static, SubmissionOutcome>>,
-    max_inflight: usize,
-    client: rusmppc::Client,
-    submit_template: SubmitSm,
-    messages_sent: Arc<AtomicU64>,
-    messages_limit: u64,
-) {
-    while inflight.len() < max_inflight {
-        if messages_limit > 0 && messages_sent.load(Ordering::Relaxed) >= messages_limit {
-            break;
+/// Sends at the times drawn from the bind's arrival schedule, whatever the
+/// SMSC's response times. The schedule runs at the bind's share of the
+/// account's bucket (or at `max_tps_per_bind` without one) and is redrawn
+/// whenever that rate changes. A send whose slot comes up while the in-flight
+/// window is full waits in a backlog, and its latency is still measured from
+/// the intended time so a slow SMSC can't hide behind the window
+/// (coordinated omission).
+async fn drive_open_loop(
+    ctx: &BindContext,
+    mut dispatcher: Dispatcher,
+    shutdown: CancellationToken,
+) -> Result<()> {
+    let load = &ctx.account.load;
+    let max_inflight = load.inflight_per_bind().max(1);
+    let metrics = &ctx.metrics;
+    let share = ctx.rate_limiter.as_ref().map(TokenBucket::share);
+    // The bucket's rate is already scaled by the controls.
+    let target_tps = |state: &ControlState| match &share {
+        Some(share) => share.tps(),
+        None => f64::from(state.scale_tps(load.max_tps_per_bind())),
+    };
+    let mut control = ctx.control.clone();
+    let mut schedule = ArrivalSchedule::new(load.arrival, target_tps(&control.borrow_and_update()));
+    // Bucket rates also move with the capacity search and the number of
+    // binds sharing them, neither of which goes through the controls.
+    let mut rate_check = time::interval(OPEN_LOOP_RATE_CHECK);
+    rate_check.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut inflight = Inflight::new();
+    let mut backlog: VecDeque<Instant> = VecDeque::new();
+    let mut next_send = Instant::now() + schedule.next_interval();
+
+    while !shutdown.is_cancelled() && !ctx.limit_reached() {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            Some(outcome) = inflight.next(), if !inflight.is_empty() => {
+                handle_outcome(ctx, outcome, &mut dispatcher).await;
+            }
+            Ok(()) = control.changed() => {
+                if schedule.set_tps(target_tps(&control.borrow_and_update())) {
+                    next_send = Instant::now() + schedule.next_interval();
+                }
+            }
+            _ = rate_check.tick() => {
+                if schedule.set_tps(target_tps(&control.borrow())) {
+                    next_send = Instant::now() + schedule.next_interval();
+                }
+            }
+            _ = time::sleep_until(next_send) => {
+                let intended = next_send;
+                let state = *control.borrow();
+                next_send += schedule.next_interval();
+                // Arrivals that fall into a pause are skipped, not owed.
+                if state.paused {
+                    continue;
+                }
+                let delayed = !backlog.is_empty() || inflight.len() >= max_inflight;
+                if backlog.len() >= MAX_OPEN_LOOP_BACKLOG {
+                    backlog.pop_front();
+                    metrics.record_open_loop_dropped();
+                }
+                backlog.push_back(intended);
+                metrics.record_open_loop_scheduled(delayed, backlog.len());
+            }
         }
-        inflight.push(submit_once(client.clone(), submit_template.clone()));
-    }
-}
 
-fn queue_if_capacity(
-    inflight: &mut FuturesUnordered<BoxFuture<'static, SubmissionOutcome>>,
-    max_inflight: usize,
-    client: rusmppc::Client,
-    submit_template: SubmitSm,
-    messages_sent: Arc<AtomicU64>,
-    messages_limit: u64,
-) {
-    if inflight.len() < max_inflight {
-        if messages_limit > 0 && messages_sent.load(Ordering::Relaxed) >= messages_limit {
-            return;
+        while inflight.len() < max_inflight && !control.borrow().paused && !ctx.limit_reached() {
+            let Some(intended) = backlog.pop_front() else {
+                break;
+            };
+            metrics.record_open_loop_send(intended.elapsed());
+            let operation = dispatcher.next(|id| ctx.sent_index.is_pending(id));
+            inflight.push(Box::pin(async move {
+                let mut outcome = operation.await;
+                outcome.latency = intended.elapsed();
+                outcome
+            }));
         }
-        inflight.push(submit_once(client, submit_template));
     }
-}
 
-type SubmissionOutcome = (Result<rusmpp::pdus::SubmitSmResp, ClientError>, Duration);
+    drain_inflight(ctx, inflight, &mut dispatcher).await;
+    Ok(())
+}
 
-fn submit_once(client: rusmppc::Client, submit: SubmitSm) -> BoxFuture<'static, SubmissionOutcome> {
-    async move {
-        let start = Instant::now();
-        let result = client.submit_sm(submit).await;
-        (result, start.elapsed())
+fn fill_inflight(
+    ctx: &BindContext,
+    inflight: &mut Inflight,
+    max_inflight: usize,
+    dispatcher: &mut Dispatcher,
+) {
+    while inflight.len() < max_inflight && !ctx.limit_reached() {
+        inflight.push(dispatcher.next(|id| ctx.sent_index.is_pending(id)));
     }
-    .boxed()
 }
 
-async fn handle_outcome(
-    idx: usize,
-    outcome: SubmissionOutcome,
-    metrics: &Arc<Metrics>,
-    tracker: &Arc<BindTracker>,
-    sent_index: &Arc<dashmap::DashMap<String, Instant>>,
-    messages_sent: Arc<AtomicU64>,
-) {
-    match outcome {
-        (Ok(resp), latency) => {
-            tracing::debug!(bind = idx, ?resp, "SubmitSmResp");
-            messages_sent.fetch_add(1, Ordering::Relaxed);
-            metrics.record_success(idx, latency);
-            let message_id = resp.message_id().as_str().to_string();
-            tracker
+async fn handle_outcome(ctx: &BindContext, outcome: OperationOutcome, dispatcher: &mut Dispatcher) {
+    let idx = ctx.idx;
+    let OperationOutcome {
+        operation,
+        profile,
+        result,
+        target,
+        latency,
+    } = outcome;
+    ctx.metrics
+        .record_operation(operation, result.is_ok(), latency);
+
+    match (operation, result) {
+        (Operation::Submit, Ok(message_id)) => {
+            let message_id = message_id.unwrap_or_default();
+            tracing::debug!(bind = idx, %message_id, "SubmitSmResp");
+            ctx.messages_sent.fetch_add(1, Ordering::Relaxed);
+            ctx.metrics.record_success(idx, latency);
+            ctx.tracker
                 .set_last_message_id(idx, Some(message_id.clone()))
                 .await;
-            sent_index.insert(message_id, Instant::now());
+            if dispatcher.requests_dlr(profile) {
+                ctx.sent_index
+                    .insert(message_id.clone(), idx, profile, latency);
+            }
+            dispatcher.remember(profile, message_id);
         }
-        (Err(err), latency) => {
+        (Operation::Submit, Err(err)) => {
             tracing::warn!(bind = idx, ?err, "SubmitSm failed");
-            metrics.record_error(idx, latency);
+            ctx.metrics.record_error(idx, latency);
+        }
+        (Operation::Cancel, Ok(_)) => {
+            tracing::debug!(bind = idx, ?target, "CancelSmResp");
+            // A cancelled message gets no receipt, or a DELETED one at most.
+            if let Some(target) = target {
+                ctx.sent_index.cancel(&target);
+                dispatcher.forget(profile, &target);
+            }
+        }
+        (_, Ok(_)) => {
+            tracing::debug!(bind = idx, %operation, "Operation succeeded");
+        }
+        (_, Err(err)) => {
+            tracing::warn!(bind = idx, %operation, ?err, "Operation failed");
         }
     }
 }
 
-async fn drain_inflight(
-    idx: usize,
-    mut inflight: FuturesUnordered<BoxFuture<'static, SubmissionOutcome>>,
-    metrics: &Arc<Metrics>,
-    tracker: &Arc<BindTracker>,
-    sent_index: &Arc<dashmap::DashMap<String, Instant>>,
-    messages_sent: Arc<AtomicU64>,
-) {
+async fn drain_inflight(ctx: &BindContext, mut inflight: Inflight, dispatcher: &mut Dispatcher) {
     while let Some(outcome) = inflight.next().await {
-        handle_outcome(idx, outcome, metrics, tracker, sent_index, messages_sent.clone()).await;
+        handle_outcome(ctx, outcome, dispatcher).await;
     }
 }